@@ -1,4 +1,12 @@
+use std::{env, path::PathBuf};
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tonic_build::compile_protos("proto/flaunch.proto")?;
+    let descriptor = PathBuf::from(env::var("OUT_DIR")?).join("flaunch_descriptor.bin");
+    tonic_prost_build::configure()
+        .file_descriptor_set_path(descriptor)
+        .compile_protos(
+            &["proto/flaunch.proto", "proto/flaunch_v2.proto"],
+            &["proto"],
+        )?;
     Ok(())
 }
\ No newline at end of file
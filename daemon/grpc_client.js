@@ -1,16 +1,42 @@
+const fs = require('fs');
+const os = require('os');
+const path = require('path');
 const grpc = require('@grpc/grpc-js');
 const protoLoader = require('@grpc/proto-loader');
-const packageDefinition = protoLoader.loadSync('/home/svenr/rusty/daemon/proto/flaunch.proto', {});
+const packageDefinition = protoLoader.loadSync(path.join(__dirname, 'proto', 'flaunch.proto'), {});
 const flaunch = grpc.loadPackageDefinition(packageDefinition).flaunch;
 
-const client = new flaunch.ScriptEngine('localhost:50051', grpc.credentials.createInsecure());
+// flaunchd writes where it listens to its runtime file, and the token tcp
+// clients need next to its settings.
+const runtimeDir = path.join(process.env.XDG_RUNTIME_DIR || os.tmpdir(), 'flaunch');
+const configDir = path.join(process.env.XDG_CONFIG_HOME || path.join(os.homedir(), '.config'), 'Svenson');
+
+const readRuntime = () => {
+    try {
+        return JSON.parse(fs.readFileSync(path.join(runtimeDir, 'flaunchd.json'), 'utf8'));
+    } catch (e) {
+        return {};
+    }
+};
+
+const runtime = readRuntime();
+const metadata = new grpc.Metadata();
+let client;
+if (runtime.unix) {
+    client = new flaunch.ScriptEngine('unix:' + runtime.unix, grpc.credentials.createInsecure());
+} else {
+    const token = fs.readFileSync(path.join(configDir, 'token'), 'utf8').trim();
+    metadata.add('authorization', 'Bearer ' + token);
+    const credentials = runtime.tls ? grpc.credentials.createSsl() : grpc.credentials.createInsecure();
+    client = new flaunch.ScriptEngine(runtime.tcp || 'localhost:50051', credentials);
+}
 
 const wat = () => {
     console.log("svensson");
-    let call = client.GetAll();
+    let call = client.GetAll({}, metadata);
 
     call.on('data', function (response) {
-        console.log(response.message);
+        console.log(response.name);
     });
 
     call.on('end', function () {
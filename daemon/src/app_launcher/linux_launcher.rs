@@ -0,0 +1,153 @@
+use std::{io, path::PathBuf, process::Command};
+
+use flaunch_core::{
+    app_meta,
+    logging::{info, warn},
+};
+
+use crate::app_launcher::AppLauncherT;
+
+const SERVICE: &str = "flaunchd.service";
+const SOCKET: &str = "flaunchd.socket";
+const AUTOSTART: &str = "flaunchd.desktop";
+
+/// Installs flaunchd as a systemd user service, socket activated on the
+/// unix socket, and as an XDG autostart entry for sessions without systemd.
+pub struct LinuxLauncher {}
+
+impl AppLauncherT for LinuxLauncher {
+    fn set_resources() -> io::Result<()> {
+        let exe = std::env::current_exe()?;
+        let exe = exe.to_string_lossy();
+
+        let units = unit_dir();
+        std::fs::create_dir_all(&units)?;
+        std::fs::write(units.join(SERVICE), service_unit(&exe))?;
+        std::fs::write(units.join(SOCKET), socket_unit())?;
+        info!(
+            "wrote {} and {} to {}",
+            SERVICE,
+            SOCKET,
+            units.to_string_lossy()
+        );
+
+        let autostart = autostart_dir();
+        std::fs::create_dir_all(&autostart)?;
+        std::fs::write(autostart.join(AUTOSTART), autostart_entry(&exe))?;
+        info!("wrote {}", autostart.join(AUTOSTART).to_string_lossy());
+
+        if systemctl(&["daemon-reload"]) {
+            systemctl(&["enable", "--now", SOCKET, SERVICE]);
+        }
+        Ok(())
+    }
+
+    fn remove_resources() -> io::Result<()> {
+        systemctl(&["disable", "--now", SERVICE, SOCKET]);
+        for file in [
+            unit_dir().join(SERVICE),
+            unit_dir().join(SOCKET),
+            autostart_dir().join(AUTOSTART),
+        ] {
+            match std::fs::remove_file(&file) {
+                Ok(()) => info!("removed {}", file.to_string_lossy()),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+        systemctl(&["daemon-reload"]);
+        Ok(())
+    }
+
+    fn configure_url_scheme(_scheme: &str, _description: &str) {}
+}
+
+fn config_home() -> PathBuf {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_default()
+}
+
+fn unit_dir() -> PathBuf {
+    config_home().join("systemd").join("user")
+}
+
+fn autostart_dir() -> PathBuf {
+    config_home().join("autostart")
+}
+
+/// Runs `systemctl --user`, false when it isn't there or fails, as on
+/// systems without systemd.
+fn systemctl(args: &[&str]) -> bool {
+    match Command::new("systemctl").arg("--user").args(args).status() {
+        Ok(status) if status.success() => true,
+        Ok(status) => {
+            warn!("systemctl --user {} failed, {}", args.join(" "), status);
+            false
+        }
+        Err(e) => {
+            warn!("systemctl not available, {}", e);
+            false
+        }
+    }
+}
+
+/// Signals readiness once the scripts are loaded, reloads on SIGHUP.
+fn service_unit(exe: &str) -> String {
+    format!(
+        "[Unit]
+Description={name} script daemon
+Requires={socket}
+After={socket}
+
+[Service]
+Type=notify
+ExecStart=\"{exe}\"
+ExecReload=/bin/kill -HUP $MAINPID
+Restart=on-failure
+
+[Install]
+WantedBy=default.target
+Also={socket}
+",
+        name = app_meta::APP_NAME,
+        socket = SOCKET,
+        exe = exe,
+    )
+}
+
+/// Listens where `runtime::socket_path` points, %t is $XDG_RUNTIME_DIR.
+fn socket_unit() -> String {
+    format!(
+        "[Unit]
+Description={name} script daemon socket
+
+[Socket]
+ListenStream=%t/flaunch/flaunchd.sock
+SocketMode=0600
+DirectoryMode=0700
+
+[Install]
+WantedBy=sockets.target
+",
+        name = app_meta::APP_NAME,
+    )
+}
+
+/// Skipped by the systemd autostart generator, the units take over there.
+fn autostart_entry(exe: &str) -> String {
+    format!(
+        "[Desktop Entry]
+Type=Application
+Name={name}
+Comment=Runs the {name} script daemon
+Exec=\"{exe}\" --unix-socket
+Terminal=false
+NoDisplay=true
+X-systemd-skip=true
+",
+        name = app_meta::APP_NAME,
+        exe = exe,
+    )
+}
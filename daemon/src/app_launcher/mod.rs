@@ -1,11 +1,19 @@
+use std::io;
+
+#[cfg(not(target_os = "linux"))]
 use flaunch_core::app_meta;
+#[cfg(not(target_os = "linux"))]
 use futures::channel::mpsc::Sender;
 
+#[cfg(not(target_os = "linux"))]
 pub use crate::system_tray::StatusBar;
+#[cfg(not(target_os = "linux"))]
 pub use crate::system_tray::TStatusBar;
 
 #[cfg(target_os = "linux")]
-pub type AppLauncher = DummyContainer;
+mod linux_launcher;
+#[cfg(target_os = "linux")]
+pub type AppLauncher = linux_launcher::LinuxLauncher;
 #[cfg(target_os = "macos")]
 mod osx_launcher;
 #[cfg(target_os = "macos")]
@@ -17,23 +25,18 @@ mod win_launcher;
 pub type AppLauncher = win_launcher::WindowsLauncher;
 
 pub trait AppLauncherT {
-    fn set_resources();
+    /// Installs what starts flaunchd with the user session.
+    fn set_resources() -> io::Result<()>;
 
+    /// Undoes `set_resources`.
+    fn remove_resources() -> io::Result<()>;
+
+    /// there's no tray on linux, flaunchd runs as a user service there.
+    #[cfg(not(target_os = "linux"))]
     fn build_system_tray(&self, sender: Sender<String>) -> StatusBar {
         StatusBar::new(sender, app_meta::APP_NAME, app_meta::ICON)
     }
 
+    #[allow(dead_code)]
     fn configure_url_scheme(scheme: &str, description: &str);
 }
-
-pub struct DummyContainer {}
-impl DummyContainer {
-    pub fn new() -> Self {
-        DummyContainer {}
-    }
-}
-
-impl AppLauncherT for DummyContainer {
-    fn set_resources() {}
-    fn configure_url_scheme(_scheme: &str, _description: &str) {}
-}
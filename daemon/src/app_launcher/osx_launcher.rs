@@ -1,7 +1,7 @@
 use crate::app_launcher::AppLauncherT;
+use flaunch_core::app_meta;
 use fruitbasket::*;
 use std::sync::mpsc::{self, Receiver, Sender};
-use flaunch_core::app_meta;
 
 pub struct OsxLauncher {
     fruit_app: FruitApp,
@@ -30,7 +30,12 @@ impl OsxLauncher {
 }
 
 impl AppLauncherT for OsxLauncher {
-    fn set_resources() {}
+    fn set_resources() -> std::io::Result<()> {
+        Ok(())
+    }
+    fn remove_resources() -> std::io::Result<()> {
+        Ok(())
+    }
 
     // // fn get_bundle_url() -> String {
     // //     let uritypes = "<key>CFBundleURLTypes</key>
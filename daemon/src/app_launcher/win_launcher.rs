@@ -9,6 +9,11 @@ impl WindowsLauncher {
 }
 
 impl AppLauncherT for WindowsLauncher {
-    fn set_resources() {}
+    fn set_resources() -> std::io::Result<()> {
+        Ok(())
+    }
+    fn remove_resources() -> std::io::Result<()> {
+        Ok(())
+    }
     fn configure_url_scheme(_scheme: &str, _description: &str) {}
 }
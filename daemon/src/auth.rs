@@ -0,0 +1,222 @@
+use std::{io, path::Path, sync::Arc};
+
+use flaunch_core::{logging::info, settings::auth_token_file};
+use tonic::{metadata::MetadataValue, service::Interceptor, Request, Status};
+
+use crate::policy::{Client, Policy};
+
+/// What the server knows about a connection on the unix socket.
+#[cfg(unix)]
+pub type PeerInfo = tonic::transport::server::UdsConnectInfo;
+/// There's no unix socket, requests never carry it.
+#[cfg(not(unix))]
+#[derive(Debug, Clone)]
+pub struct PeerInfo;
+
+/// Returns the token clients need to present, generating one on first start.
+/// Only the user running the daemon can read it.
+pub fn load_or_create_token() -> io::Result<String> {
+    let file = auth_token_file();
+    if let Ok(token) = std::fs::read_to_string(&file) {
+        if !token.trim().is_empty() {
+            return Ok(token.trim().to_string());
+        }
+    }
+
+    let mut bytes = [0u8; 32];
+    getrandom::fill(&mut bytes).map_err(|e| io::Error::other(e.to_string()))?;
+    let token: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    write_private(&file, &token)?;
+    info!("generated auth token in {}", file.to_string_lossy());
+    Ok(token)
+}
+
+fn write_private(file: &Path, content: &str) -> io::Result<()> {
+    if let Some(dir) = file.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut opened = options.open(file)?;
+    // the mode only applies to new files, an existing one may be readable by others
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        opened.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    }
+    io::Write::write_all(&mut opened, content.as_bytes())
+}
+
+/// Requires an `authorization: Bearer <token>` header on tcp connections,
+/// with the daemon token or one from the access policy. Unix socket
+/// connections are identified by their peer credentials instead. The
+/// resulting `Client` is added to the request extensions.
+#[derive(Clone)]
+pub struct TokenAuth {
+    expected: MetadataValue<tonic::metadata::Ascii>,
+    policy: Arc<Policy>,
+}
+
+impl TokenAuth {
+    pub fn new(
+        token: &str,
+        policy: Arc<Policy>,
+    ) -> Result<Self, tonic::metadata::errors::InvalidMetadataValue> {
+        Ok(TokenAuth {
+            expected: format!("Bearer {}", token).parse()?,
+            policy,
+        })
+    }
+
+    /// Identifies a client by its authorization header or, on the unix
+    /// socket, its peer credentials.
+    pub fn client(&self, authorization: Option<&[u8]>, uds: Option<&PeerInfo>) -> Option<Client> {
+        if let Some(token) = authorization {
+            if same(token, self.expected.as_bytes()) {
+                return Some(Client::Owner);
+            }
+            let token = token.strip_prefix(b"Bearer ")?;
+            return self.policy.knows_token(token).map(Client::Token);
+        }
+
+        #[cfg(unix)]
+        {
+            let credentials = uds?.peer_cred?;
+            Some(peer(credentials.uid(), credentials.gid()))
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = uds;
+            None
+        }
+    }
+}
+
+/// The socket is only reachable by other users when a policy is in place,
+/// peers running as the daemon's user own it.
+#[cfg(unix)]
+fn peer(uid: u32, gid: u32) -> Client {
+    if uid == unsafe { libc::geteuid() } {
+        Client::Owner
+    } else {
+        Client::Unix { uid, gid }
+    }
+}
+
+/// compares in constant time, so the token can't be guessed byte by byte.
+pub(crate) fn same(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+impl Interceptor for TokenAuth {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let client = self
+            .client(
+                request
+                    .metadata()
+                    .get("authorization")
+                    .map(MetadataValue::as_bytes),
+                request.extensions().get::<PeerInfo>(),
+            )
+            .ok_or_else(|| Status::unauthenticated("missing or invalid token"))?;
+        request.extensions_mut().insert(client);
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auth() -> TokenAuth {
+        let policy = Policy::parse("[[rule]]\ntokens = [\"ops-token\"]\ntags = [\"deploy\"]\n");
+        TokenAuth::new("daemon-token", Arc::new(policy.unwrap())).unwrap()
+    }
+
+    #[test]
+    fn tokens_identify_clients() {
+        let auth = auth();
+        assert_eq!(
+            auth.client(Some(b"Bearer daemon-token"), None),
+            Some(Client::Owner)
+        );
+        assert_eq!(
+            auth.client(Some(b"Bearer ops-token"), None),
+            Some(Client::Token("ops-token".to_string()))
+        );
+        assert_eq!(auth.client(Some(b"Bearer daemon-tokeN"), None), None);
+        assert_eq!(auth.client(Some(b"Bearer ops"), None), None);
+        assert_eq!(auth.client(Some(b"daemon-token"), None), None);
+        assert_eq!(auth.client(Some(b"ops-token"), None), None);
+        assert_eq!(auth.client(Some(b""), None), None);
+        assert_eq!(auth.client(None, None), None);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn unix_peers_are_identified_by_uid() {
+        use tonic::transport::server::Connected;
+
+        let (stream, _peer) = tokio::net::UnixStream::pair().unwrap();
+        let info = stream.connect_info();
+        assert_eq!(auth().client(None, Some(&info)), Some(Client::Owner));
+        // a token wins over the peer credentials
+        assert_eq!(auth().client(Some(b"Bearer wrong"), Some(&info)), None);
+
+        let other = unsafe { libc::geteuid() } + 1;
+        assert_eq!(
+            peer(other, 100),
+            Client::Unix {
+                uid: other,
+                gid: 100
+            }
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_private_tightens_existing_files() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let file = std::env::temp_dir().join(format!("flaunch_token_{}", std::process::id()));
+        std::fs::write(&file, "old").unwrap();
+        std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o644)).unwrap();
+        write_private(&file, "secret").unwrap();
+
+        let mode = std::fs::metadata(&file).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "secret");
+        std::fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn interceptor_rejects_missing_tokens() {
+        let mut auth = auth();
+        let status = auth.call(Request::new(())).unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+
+        let mut request = Request::new(());
+        request
+            .metadata_mut()
+            .insert("authorization", "Bearer ops-token".parse().unwrap());
+        let request = auth.call(request).unwrap();
+        assert_eq!(
+            request.extensions().get::<Client>(),
+            Some(&Client::Token("ops-token".to_string()))
+        );
+    }
+
+    #[test]
+    fn same_compares_whole_tokens() {
+        assert!(same(b"abc", b"abc"));
+        assert!(!same(b"abc", b"abd"));
+        assert!(!same(b"abc", b"abcd"));
+        assert!(!same(b"", b"a"));
+    }
+}
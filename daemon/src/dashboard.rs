@@ -0,0 +1,32 @@
+use axum::{http::header, response::IntoResponse, routing::get, Router};
+
+/// Web ui on top of the json api, compiled into the binary. The pages
+/// themselves are public, the api calls they make need the token.
+pub fn router() -> Router {
+    Router::new()
+        .route(
+            "/",
+            get(|| file("text/html", include_str!("../web/index.html"))),
+        )
+        .route(
+            "/dashboard.js",
+            get(|| file("text/javascript", include_str!("../web/dashboard.js"))),
+        )
+        .route(
+            "/dashboard.css",
+            get(|| file("text/css", include_str!("../web/dashboard.css"))),
+        )
+        .route(
+            "/logo.png",
+            get(|| {
+                file(
+                    "image/png",
+                    include_bytes!("../../flaunch/images/rosengard_logo.png").as_slice(),
+                )
+            }),
+        )
+}
+
+async fn file(content_type: &'static str, content: impl IntoResponse) -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, content_type)], content)
+}
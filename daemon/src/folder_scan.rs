@@ -0,0 +1,111 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use flaunch_core::{
+    logging::{error, info, warn},
+    script_engine::ScriptEngine,
+    settings::Settings,
+    SettingKey,
+};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::{mpsc, oneshot, RwLock};
+
+/// editors often write a file in several steps, reload once they're done.
+const SETTLE_TIME: Duration = Duration::from_millis(300);
+
+/// Reloads `dir` into the engine whenever a file in it changes. Watching
+/// stops when the returned watcher is dropped.
+pub fn watch_scripts_dir(
+    engine: Arc<ScriptEngine>,
+    dir: PathBuf,
+) -> notify::Result<RecommendedWatcher> {
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let mut watcher =
+        notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+            Ok(event) if !event.kind.is_access() => {
+                let _ = sender.send(());
+            }
+            Ok(_) => {}
+            Err(e) => error!("watching scripts failed: {}", e),
+        })?;
+    watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+
+    tokio::spawn(async move {
+        while receiver.recv().await.is_some() {
+            tokio::time::sleep(SETTLE_TIME).await;
+            while receiver.try_recv().is_ok() {}
+
+            load(&engine, &dir).await;
+        }
+    });
+
+    Ok(watcher)
+}
+
+pub async fn load(engine: &ScriptEngine, dir: &Path) {
+    // diagnostics stay available through GetDiagnostics
+    match engine.load(dir).await {
+        Ok(errors) => errors
+            .iter()
+            .for_each(|e| warn!("{}: {}", e.filename, e.message)),
+        Err(e) => warn!("{}", e),
+    }
+}
+
+/// Loads the scripts dir of the settings and follows changes to it. A new
+/// dir replaces the scripts of the old one, `folder_scan` toggles watching.
+/// `loaded` fires once the initial scripts dir is loaded.
+pub async fn follow_settings(
+    engine: Arc<ScriptEngine>,
+    settings: Arc<RwLock<Settings<SettingKey>>>,
+    loaded: oneshot::Sender<()>,
+) {
+    let mut loaded = Some(loaded);
+    let mut changes = settings.read().await.observe();
+    let mut current: Option<(PathBuf, bool)> = None;
+    let mut _watcher = None;
+
+    loop {
+        let wanted = {
+            let settings = settings.read().await;
+            settings.get_str(SettingKey::ScriptsDir).map(|dir| {
+                let scan = settings
+                    .get_bool(SettingKey::FolderScan)
+                    .unwrap_or_default();
+                (PathBuf::from(dir), scan)
+            })
+        };
+
+        if wanted != current {
+            _watcher = None;
+            let old_dir = current.as_ref().map(|(dir, _)| dir);
+            let new_dir = wanted.as_ref().map(|(dir, _)| dir);
+            if old_dir != new_dir {
+                if let Some(dir) = old_dir {
+                    engine.unload(dir).await;
+                }
+                if let Some(dir) = new_dir {
+                    info!("loading scripts from {}", dir.to_string_lossy());
+                    load(&engine, dir).await;
+                }
+            }
+            if let Some((dir, true)) = &wanted {
+                match watch_scripts_dir(engine.clone(), dir.clone()) {
+                    Ok(watcher) => _watcher = Some(watcher),
+                    Err(e) => warn!("not watching scripts: {}", e),
+                }
+            }
+            current = wanted;
+        }
+        if let Some(loaded) = loaded.take() {
+            let _ = loaded.send(());
+        }
+
+        if changes.changed().await.is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use std::sync::Arc;
+
+use flaunch_core::{app_meta, script_engine::InterpreterType, settings::Settings, SettingKey};
+use tokio::sync::RwLock;
+
+use super::proto;
+
+/// versions of the script engine service that are served.
+const PROTOCOL_VERSIONS: [u32; 2] = [1, 2];
+
+/// Tells clients which daemon they talk to and what it supports.
+pub struct DaemonService {
+    settings: Arc<RwLock<Settings<SettingKey>>>,
+    features: Vec<String>,
+}
+
+impl DaemonService {
+    /// `features` are the optional parts turned on for this run.
+    pub fn new(settings: Arc<RwLock<Settings<SettingKey>>>, features: Vec<String>) -> Self {
+        DaemonService { settings, features }
+    }
+}
+
+#[tonic::async_trait]
+impl proto::daemon_server::Daemon for DaemonService {
+    async fn get_info(
+        &self,
+        _: tonic::Request<()>,
+    ) -> Result<tonic::Response<proto::Info>, tonic::Status> {
+        let scripts_dir = self
+            .settings
+            .read()
+            .await
+            .get_str(SettingKey::ScriptsDir)
+            .unwrap_or_default()
+            .to_string();
+        Ok(tonic::Response::new(proto::Info {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            build_date: app_meta::BUILD_DATE.to_string(),
+            git_describe: app_meta::VERSION.to_string(),
+            interpreters: InterpreterType::all()
+                .iter()
+                .map(|interpreter| interpreter.name().to_string())
+                .collect(),
+            features: self.features.clone(),
+            protocol_versions: PROTOCOL_VERSIONS.to_vec(),
+            scripts_dirs: vec![scripts_dir],
+        }))
+    }
+}
@@ -1,22 +1,297 @@
+mod daemon_service;
 mod script_engine_service;
-use std::sync::Arc;
+mod settings_service;
+use std::{net::SocketAddr, path::PathBuf, sync::Arc};
 
-use tonic::transport::Server;
+use flaunch_core::{logging::info, settings::Settings, SettingKey};
+use tokio::{
+    net::TcpListener,
+    sync::{oneshot, RwLock},
+};
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::{
+    service::Routes,
+    transport::{Identity, Server, ServerTlsConfig},
+};
+use tonic_health::ServingStatus;
 
+use crate::{
+    auth::TokenAuth, dashboard, lifecycle::Shutdown, policy::Policy, rest, runs::Runs, runtime,
+};
+
+pub mod proto {
+    tonic::include_proto!("flaunch");
+
+    pub mod v2 {
+        tonic::include_proto!("flaunch.v2");
+    }
+
+    /// descriptors of all services, for reflection.
+    pub const FILE_DESCRIPTOR_SET: &[u8] =
+        tonic::include_file_descriptor_set!("flaunch_descriptor");
+}
+
+const SCRIPT_ENGINE: &str = <proto::script_engine_server::ScriptEngineServer<
+    script_engine_service::ScriptEngineService,
+> as tonic::server::NamedService>::NAME;
+const SCRIPT_ENGINE_V2: &str = <proto::v2::script_engine_server::ScriptEngineServer<
+    script_engine_service::ScriptEngineServiceV2,
+> as tonic::server::NamedService>::NAME;
+
+/// Where the server listens, on tcp, a unix socket or both.
+#[derive(Debug, Default)]
+pub struct Endpoints {
+    pub tcp: Option<SocketAddr>,
+    /// pem certificate and key to serve tcp over tls.
+    pub tls: Option<(PathBuf, PathBuf)>,
+    pub unix: Option<PathBuf>,
+    /// listening socket passed by systemd, served instead of binding `unix`.
+    #[cfg(unix)]
+    pub activated: Option<std::os::unix::net::UnixListener>,
+    /// serve the json api of `rest` on the same endpoints.
+    pub rest: bool,
+    /// serve the web ui as well, needs `rest`.
+    pub dashboard: bool,
+}
+
+impl Endpoints {
+    /// The optional parts that are turned on, as `GetInfo` reports them.
+    fn features(&self, policy: &Policy) -> Vec<String> {
+        #[cfg(unix)]
+        let activated = self.activated.is_some();
+        #[cfg(not(unix))]
+        let activated = false;
+        [
+            ("tcp", self.tcp.is_some()),
+            ("tls", self.tls.is_some()),
+            ("unix_socket", self.unix.is_some()),
+            ("socket_activation", activated),
+            ("rest", self.rest),
+            ("dashboard", self.rest && self.dashboard),
+            ("access_policy", policy.is_enforced()),
+        ]
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(feature, _)| feature.to_string())
+        .collect()
+    }
+}
+
+/// Serves the flaunch services, next to the standard health and reflection
+/// services. Health reports NOT_SERVING for the server and the script engine
+/// until `loaded` fires, after the initial load of the scripts. Once
+/// `shutdown` is requested no new connections are accepted and the server
+/// returns when the open requests completed.
+#[allow(clippy::too_many_arguments)]
 pub async fn run_gprc_server(
     engine: Arc<flaunch_core::script_engine::ScriptEngine>,
+    settings: Arc<RwLock<Settings<SettingKey>>>,
+    endpoints: Endpoints,
+    auth: TokenAuth,
+    policy: Arc<Policy>,
+    loaded: oneshot::Receiver<()>,
+    runs: Arc<Runs>,
+    shutdown: Shutdown,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let addr = "[::1]:50051".parse()?;
-    let script_engine_service = script_engine_service::ScriptEngineService::new(engine);
+    if endpoints.tcp.is_none() && endpoints.unix.is_none() {
+        return Err("no address or unix socket to listen on".into());
+    }
+
+    let (health, health_server) = tonic_health::server::health_reporter();
+    for service in ["", SCRIPT_ENGINE, SCRIPT_ENGINE_V2] {
+        health
+            .set_service_status(service, ServingStatus::NotServing)
+            .await;
+    }
+    health
+        .set_serving::<proto::settings_server::SettingsServer<settings_service::SettingsService>>()
+        .await;
+    health
+        .set_serving::<proto::daemon_server::DaemonServer<daemon_service::DaemonService>>()
+        .await;
+    let reflection_server = tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(proto::FILE_DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(tonic_health::pb::FILE_DESCRIPTOR_SET)
+        .build_v1()?;
+    let reflection_v1alpha_server = tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(proto::FILE_DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(tonic_health::pb::FILE_DESCRIPTOR_SET)
+        .build_v1alpha()?;
 
-    Server::builder()
-        .add_service(
-            script_engine_service::proto::script_engine_server::ScriptEngineServer::new(
-                script_engine_service,
+    let script_engine_server = proto::script_engine_server::ScriptEngineServer::with_interceptor(
+        script_engine_service::ScriptEngineService::new(
+            engine.clone(),
+            runs.clone(),
+            policy.clone(),
+            shutdown.clone(),
+        ),
+        auth.clone(),
+    );
+    let script_engine_v2_server =
+        proto::v2::script_engine_server::ScriptEngineServer::with_interceptor(
+            script_engine_service::ScriptEngineServiceV2(
+                script_engine_service::ScriptEngineService::new(
+                    engine.clone(),
+                    runs.clone(),
+                    policy.clone(),
+                    shutdown.clone(),
+                ),
             ),
-        )
-        .serve(addr)
-        .await?;
+            auth.clone(),
+        );
+    let settings_server = proto::settings_server::SettingsServer::with_interceptor(
+        settings_service::SettingsService::new(settings.clone(), policy.clone(), shutdown.clone()),
+        auth.clone(),
+    );
+    let daemon_server = proto::daemon_server::DaemonServer::with_interceptor(
+        daemon_service::DaemonService::new(settings.clone(), endpoints.features(&policy)),
+        auth.clone(),
+    );
+    let mut routes = Routes::builder();
+    routes
+        .add_service(script_engine_server)
+        .add_service(script_engine_v2_server)
+        .add_service(settings_server)
+        .add_service(daemon_server)
+        .add_service(health_server)
+        .add_service(reflection_server)
+        .add_service(reflection_v1alpha_server);
+    let mut routes = routes.routes();
+    if endpoints.rest {
+        let gateway = rest::Gateway {
+            engine,
+            runs,
+            settings,
+            policy: policy.clone(),
+            auth,
+        };
+        routes = routes
+            .into_axum_router()
+            .merge(rest::router(gateway))
+            .into();
+        info!("serving the json api under /api");
+    }
+    if endpoints.rest && endpoints.dashboard {
+        routes = routes.into_axum_router().merge(dashboard::router()).into();
+        info!("serving the dashboard under /");
+    }
+    // the json api is plain http, grpc needs http/2
+    let router = |server: Server| {
+        server
+            .accept_http1(endpoints.rest)
+            .add_routes(routes.clone())
+    };
+
+    let mut tcp_server_builder = Server::builder();
+    if let Some((certificate, key)) = &endpoints.tls {
+        let identity = Identity::from_pem(std::fs::read(certificate)?, std::fs::read(key)?);
+        tcp_server_builder =
+            tcp_server_builder.tls_config(ServerTlsConfig::new().identity(identity))?;
+        info!("serving tcp over tls");
+    }
+
+    let tcp = match endpoints.tcp {
+        Some(addr) => {
+            let listener = TcpListener::bind(addr).await?;
+            info!("listening on {}", listener.local_addr()?);
+            Some(listener)
+        }
+        None => None,
+    };
+    let tcp_addr = tcp.as_ref().map(TcpListener::local_addr).transpose()?;
+
+    #[cfg(unix)]
+    let unix = match (endpoints.activated, &endpoints.unix) {
+        (Some(listener), _) => Some(activated_listener(listener, policy.is_enforced())?),
+        (None, Some(path)) => Some(unix_listener(path, policy.is_enforced())?),
+        (None, None) => None,
+    };
+    runtime::write_runtime_file(tcp_addr, endpoints.tls.is_some(), endpoints.unix.as_deref())?;
+    #[cfg(unix)]
+    if unix.is_some() && policy.is_enforced() {
+        runtime::share_runtime_dir()?;
+    }
+
+    // ready once listening and the scripts are loaded
+    tokio::spawn(async move {
+        if loaded.await.is_ok() {
+            for service in ["", SCRIPT_ENGINE, SCRIPT_ENGINE_V2] {
+                health
+                    .set_service_status(service, ServingStatus::Serving)
+                    .await;
+            }
+            #[cfg(target_os = "linux")]
+            if let Err(e) = crate::systemd::notify("READY=1") {
+                flaunch_core::logging::warn!("could not notify systemd, {}", e);
+            }
+        }
+    });
+
+    let tcp_server = async {
+        match tcp {
+            Some(listener) => {
+                router(tcp_server_builder)
+                    .serve_with_incoming_shutdown(
+                        TcpListenerStream::new(listener),
+                        shutdown.clone().requested(),
+                    )
+                    .await
+            }
+            None => Ok(()),
+        }
+    };
+    let unix_server = async {
+        #[cfg(unix)]
+        if let Some(listener) = unix {
+            return router(Server::builder())
+                .serve_with_incoming_shutdown(
+                    tokio_stream::wrappers::UnixListenerStream::new(listener),
+                    shutdown.clone().requested(),
+                )
+                .await;
+        }
+        Ok(())
+    };
+    tokio::try_join!(tcp_server, unix_server)?;
 
     Ok(())
 }
+
+/// Binds `path`, only accessible by the user running the daemon unless
+/// the access policy decides per client.
+#[cfg(unix)]
+fn unix_listener(
+    path: &std::path::Path,
+    shared: bool,
+) -> std::io::Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::PermissionsExt;
+
+    runtime::create_runtime_dir()?;
+    // a previous daemon that didn't shut down cleanly leaves its socket behind
+    if path.exists() {
+        std::fs::remove_file(path)?;
+    }
+    let listener = tokio::net::UnixListener::bind(path)?;
+    let mode = if shared { 0o666 } else { 0o600 };
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    info!("listening on {}", path.to_string_lossy());
+    Ok(listener)
+}
+
+/// Serves the socket systemd bound, with the same permissions as
+/// `unix_listener` gives.
+#[cfg(unix)]
+fn activated_listener(
+    listener: std::os::unix::net::UnixListener,
+    shared: bool,
+) -> std::io::Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::PermissionsExt;
+
+    listener.set_nonblocking(true)?;
+    if let Some(path) = listener.local_addr()?.as_pathname() {
+        let mode = if shared { 0o666 } else { 0o600 };
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+        info!("listening on {}", path.to_string_lossy());
+    }
+    tokio::net::UnixListener::from_std(listener)
+}
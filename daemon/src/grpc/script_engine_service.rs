@@ -1,28 +1,122 @@
-use flaunch_core::script_engine::ArgumentType;
+mod v2;
+use flaunch_core::script_engine::{
+    ArgumentType, CallError, CallEvent, CallOutput, InterpreterType, ParseError, Script,
+    ScriptEngineError,
+};
+use std::any::Any;
+use std::collections::{HashMap, HashSet};
 use std::{pin::Pin, sync::Arc};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::UnboundedReceiverStream;
 use tokio_stream::{Stream, StreamExt};
-pub mod proto {
-    tonic::include_proto!("flaunch");
-}
 
-#[derive(Debug, Default)]
+use super::proto;
+use crate::lifecycle::Shutdown;
+use crate::policy::{Client, Policy};
+use crate::runs::{RunUpdate, Runs};
+pub use v2::ScriptEngineServiceV2;
+
+/// Serves the scripts the access policy allows the calling client.
+#[derive(Debug)]
 pub struct ScriptEngineService {
     engine: Arc<flaunch_core::script_engine::ScriptEngine>,
+    runs: Arc<Runs>,
+    policy: Arc<Policy>,
+    /// ends the watch streams, runs stream until their script finishes.
+    shutdown: Shutdown,
+}
+
+impl ScriptEngineService {
+    pub fn new(
+        engine: Arc<flaunch_core::script_engine::ScriptEngine>,
+        runs: Arc<Runs>,
+        policy: Arc<Policy>,
+        shutdown: Shutdown,
+    ) -> Self {
+        ScriptEngineService {
+            engine,
+            runs,
+            policy,
+            shutdown,
+        }
+    }
+}
+
+impl ScriptEngineService {
+    /// The current catalog as `added` events and `synced`, then the changes
+    /// of the catalog the client may see, for v1 and v2 clients alike.
+    fn watch_catalog<E>(&self, client: Client) -> UnboundedReceiverStream<Result<E, tonic::Status>>
+    where
+        E: From<CatalogChange> + Send + 'static,
+    {
+        let policy = self.policy.clone();
+        let engine = self.engine.clone();
+        let mut changes = engine.observe();
+        changes.borrow_and_update();
+        let shutdown = self.shutdown.clone();
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            let mut known = HashMap::new();
+            let mut events =
+                catalog_changes(&mut known, policy.visible(&client, engine.scripts().await));
+            events.push(CatalogChange::Synced);
+
+            loop {
+                for event in events {
+                    if sender.send(Ok(event.into())).is_err() {
+                        return;
+                    }
+                }
+
+                tokio::select! {
+                    changed = changes.changed() => {
+                        if changed.is_err() {
+                            return;
+                        }
+                    }
+                    _ = sender.closed() => return,
+                    _ = shutdown.clone().requested() => return,
+                }
+                // a watch only keeps the latest change, diffing the catalog
+                // doesn't miss any in between.
+                events =
+                    catalog_changes(&mut known, policy.visible(&client, engine.scripts().await));
+            }
+        });
+        UnboundedReceiverStream::new(receiver)
+    }
 }
 
 impl ScriptEngineService {
-    pub fn new(engine: Arc<flaunch_core::script_engine::ScriptEngine>) -> Self {
-        ScriptEngineService { engine }
+    /// diagnostics name files of scripts the client may not see.
+    fn inspection<T>(&self, request: &tonic::Request<T>) -> Result<(), tonic::Status> {
+        if !self.policy.allows_inspection(&client(request)?) {
+            return Err(tonic::Status::permission_denied(
+                "only the owner may read the diagnostics",
+            ));
+        }
+        Ok(())
     }
 }
 
+/// Set by `auth::TokenAuth` on every request.
+fn client<T>(request: &tonic::Request<T>) -> Result<Client, tonic::Status> {
+    request
+        .extensions()
+        .get::<Client>()
+        .cloned()
+        .ok_or_else(|| tonic::Status::unauthenticated("unknown client"))
+}
+
 #[tonic::async_trait]
 impl proto::script_engine_server::ScriptEngine for ScriptEngineService {
     async fn get_all(
         &self,
-        _: tonic::Request<()>,
+        request: tonic::Request<()>,
     ) -> Result<tonic::Response<Self::GetAllStream>, tonic::Status> {
-        let scripts = self.engine.scripts().await;
+        let client = client(&request)?;
+        let scripts = self.policy.visible(&client, self.engine.scripts().await);
         Ok(tonic::Response::new(Box::pin(
             tokio_stream::iter(scripts)
                 .map(|d| Result::<proto::Script, tonic::Status>::Ok(d.into())),
@@ -31,15 +125,299 @@ impl proto::script_engine_server::ScriptEngine for ScriptEngineService {
 
     type GetAllStream =
         Pin<Box<dyn Stream<Item = Result<proto::Script, tonic::Status>> + Send + 'static>>;
+
+    /// Streams `Accepted` first, then the output of the script while it runs
+    /// and finally its result or error.
+    async fn run(
+        &self,
+        request: tonic::Request<proto::RunRequest>,
+    ) -> Result<tonic::Response<Self::RunStream>, tonic::Status> {
+        let client = client(&request)?;
+        let request = request.into_inner();
+        let script_id = request.script_id;
+        let args = request
+            .arguments
+            .into_iter()
+            .map(into_any)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let script = self
+            .engine
+            .scripts()
+            .await
+            .into_iter()
+            .find(|s| s.get_key() == Some(script_id));
+        let script = match script {
+            None => {
+                return Err(tonic::Status::not_found(format!(
+                    "no script with id {}",
+                    script_id
+                )))
+            }
+            Some(script) if !self.policy.allows(&client, &script) => {
+                return Err(tonic::Status::permission_denied(format!(
+                    "not allowed to run script {}",
+                    script_id
+                )))
+            }
+            Some(script) => script,
+        };
+
+        let (run_id, mut updates) = self
+            .runs
+            .start(script, args)
+            .map_err(|e| tonic::Status::unavailable(e.to_string()))?;
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let _ = sender.send(Ok(run_event(proto::run_event::Event::Accepted(
+            proto::Accepted { run_id },
+        ))));
+
+        tokio::spawn(async move {
+            while let Some(update) = updates.recv().await {
+                let result = match update {
+                    RunUpdate::Event(event) => {
+                        let _ = sender.send(Ok(event.into()));
+                        continue;
+                    }
+                    RunUpdate::Finished(result) => result,
+                };
+
+                let event = match result {
+                    Ok(output) => proto::run_event::Event::Result(output.into()),
+                    Err(ScriptEngineError::CallFailed(_, error)) => {
+                        proto::run_event::Event::Error(error.into())
+                    }
+                    Err(ScriptEngineError::ScriptKeyDoesNotExist(key)) => {
+                        let _ = sender.send(Err(tonic::Status::not_found(format!(
+                            "no script with id {}",
+                            key
+                        ))));
+                        return;
+                    }
+                    Err(e) => proto::run_event::Event::Error(proto::RunError {
+                        message: e.to_string(),
+                        traceback: String::new(),
+                    }),
+                };
+                let _ = sender.send(Ok(run_event(event)));
+            }
+        });
+
+        Ok(tonic::Response::new(
+            Box::pin(UnboundedReceiverStream::new(receiver)) as Self::RunStream,
+        ))
+    }
+
+    /// Sends the current catalog, then every change `ScriptEngine::observe`
+    /// reports until the client disconnects.
+    async fn watch_scripts(
+        &self,
+        request: tonic::Request<()>,
+    ) -> Result<tonic::Response<Self::WatchScriptsStream>, tonic::Status> {
+        let client = client(&request)?;
+        Ok(tonic::Response::new(
+            Box::pin(self.watch_catalog(client)) as Self::WatchScriptsStream
+        ))
+    }
+
+    type WatchScriptsStream =
+        Pin<Box<dyn Stream<Item = Result<proto::ScriptEvent, tonic::Status>> + Send + 'static>>;
+
+    async fn get_diagnostics(
+        &self,
+        request: tonic::Request<()>,
+    ) -> Result<tonic::Response<proto::Diagnostics>, tonic::Status> {
+        self.inspection(&request)?;
+        Ok(tonic::Response::new(self.engine.diagnostics().into()))
+    }
+
+    async fn watch_diagnostics(
+        &self,
+        request: tonic::Request<()>,
+    ) -> Result<tonic::Response<Self::WatchDiagnosticsStream>, tonic::Status> {
+        self.inspection(&request)?;
+        let mut changes = self.engine.observe_diagnostics();
+        let shutdown = self.shutdown.clone();
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            loop {
+                let diagnostics = changes.borrow_and_update().clone();
+                if sender.send(Ok(diagnostics.into())).is_err() {
+                    return;
+                }
+
+                tokio::select! {
+                    changed = changes.changed() => {
+                        if changed.is_err() {
+                            return;
+                        }
+                    }
+                    _ = sender.closed() => return,
+                    _ = shutdown.clone().requested() => return,
+                }
+            }
+        });
+
+        Ok(tonic::Response::new(
+            Box::pin(UnboundedReceiverStream::new(receiver)) as Self::WatchDiagnosticsStream,
+        ))
+    }
+
+    type WatchDiagnosticsStream =
+        Pin<Box<dyn Stream<Item = Result<proto::Diagnostics, tonic::Status>> + Send + 'static>>;
+
+    type RunStream =
+        Pin<Box<dyn Stream<Item = Result<proto::RunEvent, tonic::Status>> + Send + 'static>>;
+}
+
+pub enum CatalogChange {
+    Added(Script),
+    Updated(Script),
+    Removed(u64),
+    /// the client knows the whole catalog.
+    Synced,
+}
+
+/// Diffs the scripts of the engine against the ones the client knows about.
+fn catalog_changes(known: &mut HashMap<u64, Script>, scripts: Vec<Script>) -> Vec<CatalogChange> {
+    let mut events = Vec::new();
+    let mut current = HashSet::new();
+
+    for script in scripts {
+        let key = match script.get_key() {
+            Some(key) => key,
+            None => continue,
+        };
+        current.insert(key);
+        match known.insert(key, script.clone()) {
+            None => events.push(CatalogChange::Added(script)),
+            Some(old) if old != script => events.push(CatalogChange::Updated(script)),
+            Some(_) => {}
+        }
+    }
+
+    known.retain(|key, _| {
+        let keep = current.contains(key);
+        if !keep {
+            events.push(CatalogChange::Removed(*key));
+        }
+        keep
+    });
+    events
+}
+
+impl From<CatalogChange> for proto::ScriptEvent {
+    fn from(change: CatalogChange) -> Self {
+        use proto::script_event::Event;
+        proto::ScriptEvent {
+            event: Some(match change {
+                CatalogChange::Added(script) => Event::Added(script.into()),
+                CatalogChange::Updated(script) => Event::Updated(script.into()),
+                CatalogChange::Removed(key) => Event::Removed(key),
+                CatalogChange::Synced => Event::Synced(()),
+            }),
+        }
+    }
+}
+
+fn run_event(event: proto::run_event::Event) -> proto::RunEvent {
+    proto::RunEvent { event: Some(event) }
+}
+
+fn into_any(argument: proto::ArgumentValue) -> Result<Box<dyn Any + Send>, tonic::Status> {
+    use proto::argument_value::Value;
+    match argument.value {
+        Some(Value::Boolean(b)) => Ok(Box::new(b)),
+        Some(Value::Integer(i)) => Ok(Box::new(i)),
+        Some(Value::Uinteger(u)) => Ok(Box::new(u)),
+        Some(Value::Float(f)) => Ok(Box::new(f)),
+        Some(Value::String(s)) => Ok(Box::new(s)),
+        Some(Value::List(l)) => Ok(Box::new(l.values)),
+        None => Err(tonic::Status::invalid_argument("argument without a value")),
+    }
+}
+
+impl From<Vec<ParseError>> for proto::Diagnostics {
+    fn from(errors: Vec<ParseError>) -> Self {
+        proto::Diagnostics {
+            diagnostics: errors
+                .into_iter()
+                .map(|e| proto::Diagnostic {
+                    filename: e.filename,
+                    message: e.message,
+                    traceback: e.traceback,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<CallEvent> for proto::RunEvent {
+    fn from(event: CallEvent) -> Self {
+        run_event(match event {
+            CallEvent::Output(text) => proto::run_event::Event::Stdout(text),
+            CallEvent::Error(text) => proto::run_event::Event::Stderr(text),
+            CallEvent::Progress(fraction, message) => {
+                proto::run_event::Event::Progress(proto::Progress { fraction, message })
+            }
+        })
+    }
+}
+
+impl From<CallOutput> for proto::RunResult {
+    fn from(output: CallOutput) -> Self {
+        use proto::run_result::Output;
+        let output = match output {
+            CallOutput::Nothing => Output::Nothing(()),
+            CallOutput::Text(text) => Output::Text(text),
+            CallOutput::Table(table) => Output::Table(proto::Table {
+                columns: table.columns,
+                rows: table
+                    .rows
+                    .into_iter()
+                    .map(|row| proto::Row {
+                        cells: row.iter().map(|cell| cell.dump()).collect(),
+                    })
+                    .collect(),
+            }),
+            CallOutput::Response(response) => Output::Response(proto::HttpResponse {
+                status: response.status as u32,
+                headers: response
+                    .headers
+                    .into_iter()
+                    .map(|(name, value)| proto::Header { name, value })
+                    .collect(),
+                body: response.body,
+            }),
+        };
+        proto::RunResult {
+            output: Some(output),
+        }
+    }
+}
+
+impl From<CallError> for proto::RunError {
+    fn from(error: CallError) -> Self {
+        match error {
+            CallError::Exception { message, traceback } => proto::RunError { message, traceback },
+            other => proto::RunError {
+                message: other.to_string(),
+                traceback: String::new(),
+            },
+        }
+    }
 }
 
 impl From<flaunch_core::script_engine::Script> for proto::Script {
     fn from(s: flaunch_core::script_engine::Script) -> Self {
         proto::Script {
+            id: s.get_key().unwrap_or_default(),
             name: s.name,
             description: s.description,
             file: s.file.to_string_lossy().to_string(),
-            interpreter: 0,
+            interpreter: proto::Interpreter::from(s.interpreter_type) as i32,
+            tags: s.tags,
             arguments: s
                 .arguments
                 .into_iter()
@@ -49,6 +427,19 @@ impl From<flaunch_core::script_engine::Script> for proto::Script {
     }
 }
 
+impl From<InterpreterType> for proto::Interpreter {
+    fn from(from: InterpreterType) -> Self {
+        match from {
+            InterpreterType::Python => proto::Interpreter::Python,
+            InterpreterType::Notebook => proto::Interpreter::Notebook,
+            InterpreterType::Sql => proto::Interpreter::Sql,
+            InterpreterType::Http => proto::Interpreter::Http,
+            InterpreterType::Rust => proto::Interpreter::Rust,
+            InterpreterType::Template => proto::Interpreter::Template,
+        }
+    }
+}
+
 impl From<(String, ArgumentType, String)> for proto::ScriptArgument {
     fn from(from: (String, ArgumentType, String)) -> Self {
         proto::ScriptArgument {
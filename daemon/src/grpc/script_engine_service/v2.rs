@@ -0,0 +1,144 @@
+use std::pin::Pin;
+
+use flaunch_core::script_engine::{ArgumentConstraint, ArgumentType, Script};
+use tokio_stream::{Stream, StreamExt};
+
+use super::{client, CatalogChange, ScriptEngineService};
+use crate::grpc::proto::{self, script_engine_server::ScriptEngine as _, v2};
+
+/// The v2 script engine service. Runs and diagnostics are the same as in v1,
+/// scripts are described in full.
+#[derive(Debug)]
+pub struct ScriptEngineServiceV2(pub ScriptEngineService);
+
+#[tonic::async_trait]
+impl v2::script_engine_server::ScriptEngine for ScriptEngineServiceV2 {
+    async fn get_all(
+        &self,
+        request: tonic::Request<()>,
+    ) -> Result<tonic::Response<Self::GetAllStream>, tonic::Status> {
+        let client = client(&request)?;
+        let scripts = self
+            .0
+            .policy
+            .visible(&client, self.0.engine.scripts().await);
+        Ok(tonic::Response::new(Box::pin(
+            tokio_stream::iter(scripts).map(|s| Result::<v2::Script, tonic::Status>::Ok(s.into())),
+        ) as Self::GetAllStream))
+    }
+
+    type GetAllStream =
+        Pin<Box<dyn Stream<Item = Result<v2::Script, tonic::Status>> + Send + 'static>>;
+
+    async fn run(
+        &self,
+        request: tonic::Request<v2::RunRequest>,
+    ) -> Result<tonic::Response<Self::RunStream>, tonic::Status> {
+        let (metadata, extensions, request) = request.into_parts();
+        let script_id = request.script_id.parse().map_err(|_| {
+            tonic::Status::invalid_argument(format!("invalid script id {:?}", request.script_id))
+        })?;
+        let request = proto::RunRequest {
+            script_id,
+            arguments: request.arguments,
+        };
+        self.0
+            .run(tonic::Request::from_parts(metadata, extensions, request))
+            .await
+    }
+
+    type RunStream = <ScriptEngineService as proto::script_engine_server::ScriptEngine>::RunStream;
+
+    async fn watch_scripts(
+        &self,
+        request: tonic::Request<()>,
+    ) -> Result<tonic::Response<Self::WatchScriptsStream>, tonic::Status> {
+        let client = client(&request)?;
+        Ok(tonic::Response::new(
+            Box::pin(self.0.watch_catalog(client)) as Self::WatchScriptsStream
+        ))
+    }
+
+    type WatchScriptsStream =
+        Pin<Box<dyn Stream<Item = Result<v2::ScriptEvent, tonic::Status>> + Send + 'static>>;
+
+    async fn get_diagnostics(
+        &self,
+        request: tonic::Request<()>,
+    ) -> Result<tonic::Response<proto::Diagnostics>, tonic::Status> {
+        self.0.get_diagnostics(request).await
+    }
+
+    async fn watch_diagnostics(
+        &self,
+        request: tonic::Request<()>,
+    ) -> Result<tonic::Response<Self::WatchDiagnosticsStream>, tonic::Status> {
+        self.0.watch_diagnostics(request).await
+    }
+
+    type WatchDiagnosticsStream =
+        <ScriptEngineService as proto::script_engine_server::ScriptEngine>::WatchDiagnosticsStream;
+}
+
+impl From<CatalogChange> for v2::ScriptEvent {
+    fn from(change: CatalogChange) -> Self {
+        use v2::script_event::Event;
+        v2::ScriptEvent {
+            event: Some(match change {
+                CatalogChange::Added(script) => Event::Added(script.into()),
+                CatalogChange::Updated(script) => Event::Updated(script.into()),
+                CatalogChange::Removed(key) => Event::Removed(key.to_string()),
+                CatalogChange::Synced => Event::Synced(()),
+            }),
+        }
+    }
+}
+
+impl From<Script> for v2::Script {
+    fn from(s: Script) -> Self {
+        v2::Script {
+            id: s.get_key().unwrap_or_default().to_string(),
+            name: s.name,
+            description: s.description,
+            file: s.file.to_string_lossy().to_string(),
+            interpreter: s.interpreter_type.name().to_string(),
+            tags: s.tags,
+            arguments: s
+                .arguments
+                .into_iter()
+                .map(|arg| {
+                    let constraint = s.constraints.get(&arg.0).cloned().unwrap_or_default();
+                    argument(arg, constraint)
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The core only knows the type of an argument, with its default as
+/// payload. Choices and bounds are left empty unless the script declares
+/// them.
+fn argument(
+    (name, argument_type, description): (String, ArgumentType, String),
+    constraint: ArgumentConstraint,
+) -> v2::ScriptArgument {
+    let default_value = match &argument_type {
+        ArgumentType::Boolean(value) | ArgumentType::String(value) | ArgumentType::List(value) => {
+            Some(value.clone()).filter(|value| !value.is_empty())
+        }
+        ArgumentType::Int(value) => Some(value.to_string()),
+        ArgumentType::Uint(value) => Some(value.to_string()),
+        ArgumentType::Float(value) => Some(value.to_string()),
+        ArgumentType::NotSpecified => None,
+    };
+    v2::ScriptArgument {
+        name,
+        argument_type: proto::ArgumentType::from(argument_type) as i32,
+        description,
+        required: default_value.is_none(),
+        default_value,
+        choices: constraint.choices,
+        minimum: constraint.minimum,
+        maximum: constraint.maximum,
+    }
+}
@@ -0,0 +1,150 @@
+use flaunch_core::{
+    settings::{JsonValue, Settings, SettingsError},
+    SettingKey,
+};
+use std::{collections::HashMap, pin::Pin, sync::Arc};
+use tokio::sync::{mpsc, RwLock};
+use tokio_stream::{wrappers::UnboundedReceiverStream, Stream};
+
+use super::proto;
+use crate::lifecycle::Shutdown;
+use crate::policy::{Client, Policy};
+
+/// Reads and writes `Settings<SettingKey>`. Values are exchanged as json
+/// and need to have the type of their default. With an access policy in
+/// place only the owner may read and change them.
+pub struct SettingsService {
+    settings: Arc<RwLock<Settings<SettingKey>>>,
+    policy: Arc<Policy>,
+    shutdown: Shutdown,
+}
+
+impl SettingsService {
+    pub fn new(
+        settings: Arc<RwLock<Settings<SettingKey>>>,
+        policy: Arc<Policy>,
+        shutdown: Shutdown,
+    ) -> Self {
+        SettingsService {
+            settings,
+            policy,
+            shutdown,
+        }
+    }
+}
+
+impl SettingsService {
+    fn inspection<T>(&self, request: &tonic::Request<T>) -> Result<(), tonic::Status> {
+        let client = request.extensions().get::<Client>();
+        if !client.is_some_and(|client| self.policy.allows_inspection(client)) {
+            return Err(tonic::Status::permission_denied(
+                "only the owner may read settings",
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn setting(settings: &Settings<SettingKey>, key: &str, value: &JsonValue) -> proto::Setting {
+    proto::Setting {
+        key: key.to_string(),
+        value: value.dump(),
+        needs_restart: settings.key(key).is_some_and(|key| key.needs_restart()),
+    }
+}
+
+#[tonic::async_trait]
+impl proto::settings_server::Settings for SettingsService {
+    async fn get_settings(
+        &self,
+        request: tonic::Request<()>,
+    ) -> Result<tonic::Response<proto::SettingList>, tonic::Status> {
+        self.inspection(&request)?;
+        let settings = self.settings.read().await;
+        let settings = settings
+            .entries()
+            .iter()
+            .map(|(key, value)| setting(&settings, key, value))
+            .collect();
+        Ok(tonic::Response::new(proto::SettingList { settings }))
+    }
+
+    /// Persists the setting and returns the stored value.
+    async fn set_setting(
+        &self,
+        request: tonic::Request<proto::Setting>,
+    ) -> Result<tonic::Response<proto::Setting>, tonic::Status> {
+        if self.policy.is_enforced() && request.extensions().get::<Client>() != Some(&Client::Owner)
+        {
+            return Err(tonic::Status::permission_denied(
+                "only the owner may change settings",
+            ));
+        }
+        let request = request.into_inner();
+        let value = json::parse(&request.value)
+            .map_err(|e| tonic::Status::invalid_argument(format!("invalid json: {}", e)))?;
+
+        let mut settings = self.settings.write().await;
+        settings
+            .set_and_save(&request.key, value.clone())
+            .map_err(|e| match e {
+                SettingsError::UnknownKey(_) => tonic::Status::not_found(e.to_string()),
+                SettingsError::WrongType(..) => tonic::Status::invalid_argument(e.to_string()),
+                SettingsError::NotSaved(_) => tonic::Status::internal(e.to_string()),
+            })?;
+
+        Ok(tonic::Response::new(setting(
+            &settings,
+            &request.key,
+            &value,
+        )))
+    }
+
+    /// Sends all settings, then every setting that changes.
+    async fn watch_settings(
+        &self,
+        request: tonic::Request<()>,
+    ) -> Result<tonic::Response<Self::WatchSettingsStream>, tonic::Status> {
+        self.inspection(&request)?;
+        let settings = self.settings.clone();
+        let mut changes = settings.read().await.observe();
+        changes.borrow_and_update();
+        let shutdown = self.shutdown.clone();
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            let mut known: HashMap<&str, JsonValue> = HashMap::new();
+            loop {
+                {
+                    let current = settings.read().await;
+                    for (key, value) in current.entries() {
+                        if known.get(key) == Some(&value) {
+                            continue;
+                        }
+                        if sender.send(Ok(setting(&current, key, &value))).is_err() {
+                            return;
+                        }
+                        known.insert(key, value);
+                    }
+                }
+
+                tokio::select! {
+                    changed = changes.changed() => {
+                        if changed.is_err() {
+                            return;
+                        }
+                    }
+                    _ = sender.closed() => return,
+                    _ = shutdown.clone().requested() => return,
+                }
+            }
+        });
+
+        Ok(tonic::Response::new(
+            Box::pin(UnboundedReceiverStream::new(receiver)) as Self::WatchSettingsStream,
+        ))
+    }
+
+    type WatchSettingsStream =
+        Pin<Box<dyn Stream<Item = Result<proto::Setting, tonic::Status>> + Send + 'static>>;
+}
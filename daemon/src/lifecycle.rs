@@ -0,0 +1,115 @@
+#[cfg(unix)]
+use std::sync::Arc;
+use std::{
+    fs::{File, OpenOptions, TryLockError},
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use flaunch_core::settings::app_data_dir;
+#[cfg(unix)]
+use flaunch_core::{logging::info, script_engine::ScriptEngine, settings::Settings, SettingKey};
+use tokio::sync::watch;
+#[cfg(unix)]
+use tokio::sync::RwLock;
+
+#[cfg(unix)]
+use crate::folder_scan;
+
+/// Held while flaunchd runs, a second instance refuses to start. Contains
+/// the pid of the daemon holding it.
+pub struct InstanceLock {
+    _file: File,
+}
+
+pub fn lock_file() -> PathBuf {
+    app_data_dir().join("flaunchd.lock")
+}
+
+pub fn lock_instance() -> io::Result<InstanceLock> {
+    let path = lock_file();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)?;
+
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            let pid = std::fs::read_to_string(&path).unwrap_or_default();
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("flaunchd is already running (pid {})", pid.trim()),
+            ));
+        }
+        Err(TryLockError::Error(e)) => return Err(e),
+    }
+
+    file.set_len(0)?;
+    write!(file, "{}", std::process::id())?;
+    Ok(InstanceLock { _file: file })
+}
+
+/// Resolves with the name of the signal asking the daemon to stop.
+pub async fn shutdown_signal() -> io::Result<&'static str> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            _ = terminate.recv() => Ok("SIGTERM"),
+            interrupt = tokio::signal::ctrl_c() => interrupt.map(|_| "SIGINT"),
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await.map(|_| "ctrl-c")
+}
+
+/// Tells the server and open streams that flaunchd is stopping.
+#[derive(Debug, Clone)]
+pub struct Shutdown(watch::Receiver<bool>);
+
+impl Shutdown {
+    /// The sender starts the shutdown by sending `true`.
+    pub fn new() -> (watch::Sender<bool>, Shutdown) {
+        let (sender, receiver) = watch::channel(false);
+        (sender, Shutdown(receiver))
+    }
+
+    /// Resolves once the shutdown started, right away when it already did.
+    pub async fn requested(mut self) {
+        let _ = self.0.wait_for(|stopping| *stopping).await;
+    }
+}
+
+/// Reloads the settings and rescans the scripts dir on SIGHUP. The listen
+/// addresses, tls and the access policy only change on a restart.
+#[cfg(unix)]
+pub async fn reload_on_hangup(
+    engine: Arc<ScriptEngine>,
+    settings: Arc<RwLock<Settings<SettingKey>>>,
+) -> io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut hangup = signal(SignalKind::hangup())?;
+
+    while hangup.recv().await.is_some() {
+        info!("SIGHUP received, reloading settings and scripts");
+        let (before, after) = {
+            let mut settings = settings.write().await;
+            let before = settings.get_str(SettingKey::ScriptsDir).map(str::to_string);
+            settings.reload();
+            let after = settings.get_str(SettingKey::ScriptsDir).map(str::to_string);
+            (before, after)
+        };
+        // a new scripts dir is loaded by `folder_scan::follow_settings`
+        if let (Some(dir), true) = (&after, before == after) {
+            folder_scan::load(&engine, dir.as_ref()).await;
+        }
+    }
+    Ok(())
+}
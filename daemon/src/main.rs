@@ -1,17 +1,194 @@
+// the launchers and tray of macos and windows aren't built yet, their
+// dependencies aren't part of the manifest.
+#[cfg(target_os = "linux")]
+mod app_launcher;
+mod auth;
+mod dashboard;
+mod folder_scan;
 mod grpc;
-use std::{path::PathBuf, sync::Arc};
+mod lifecycle;
+mod policy;
+mod rest;
+mod runs;
+mod runtime;
+#[cfg(target_os = "linux")]
+mod systemd;
+use std::{sync::Arc, time::Duration};
 
-use flaunch_core::{load_settings, script_engine::ScriptEngine, SettingKey, load_logging};
-use grpc::run_gprc_server;
+#[cfg(target_os = "linux")]
+use app_launcher::{AppLauncher, AppLauncherT};
+use auth::TokenAuth;
+use clap::Parser;
+use flaunch_core::{
+    load_logging, load_settings,
+    logging::{flush_logging, info, warn},
+    script_engine::ScriptEngine,
+    SettingKey,
+};
+use folder_scan::follow_settings;
+use grpc::{run_gprc_server, Endpoints};
+use policy::Policy;
+use runs::Runs;
+use tokio::sync::{oneshot, RwLock};
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// how long running scripts get to finish when the daemon is asked to stop.
+const SHUTDOWN_DEADLINE: Duration = Duration::from_secs(30);
+
+/// Serves the scripts in the scripts dir to front-ends over gRPC.
+#[derive(Parser, Debug)]
+#[command(version)]
+struct Args {
+    /// address to listen on, overrides the listen_address setting.
+    /// port 0 picks a free port, see the runtime file for the chosen one.
+    #[arg(long)]
+    listen: Option<String>,
+    /// serve on a unix socket under $XDG_RUNTIME_DIR as well.
+    #[arg(long)]
+    unix_socket: bool,
+    /// serve a json api under /api as well.
+    #[arg(long)]
+    rest: bool,
+    /// serve the web dashboard, implies --rest.
+    #[arg(long)]
+    dashboard: bool,
+    #[cfg(target_os = "linux")]
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[cfg(target_os = "linux")]
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// start flaunchd with the user session, as a systemd user service and
+    /// an autostart entry.
+    Install,
+    /// undo install.
+    Uninstall,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
     load_logging();
-    let settings = load_settings();
-    let engine = ScriptEngine::default();
-    if let Some(script_path) = settings.get_str(SettingKey::ScriptsDir) {
-        let path = PathBuf::from(script_path);
-        engine.load(&path).await.unwrap();
+    #[cfg(target_os = "linux")]
+    match args.command {
+        Some(Command::Install) => return Ok(AppLauncher::set_resources()?),
+        Some(Command::Uninstall) => return Ok(AppLauncher::remove_resources()?),
+        None => {}
     }
-    run_gprc_server(Arc::new(engine)).await
+    let _lock = lifecycle::lock_instance().map_err(|e| e.to_string())?;
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+    let result = runtime.block_on(serve(args));
+    // `serve` waited for the scripts up to the deadline, the ones still
+    // running on the blocking pool are given up on.
+    runtime.shutdown_background();
+    info!("stopped");
+    flush_logging();
+    result
+}
+
+async fn serve(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    let settings = load_settings();
+
+    let listen = args
+        .listen
+        .or_else(|| {
+            settings
+                .get_str(SettingKey::ListenAddress)
+                .map(str::to_string)
+        })
+        .unwrap_or_default();
+    let mut endpoints = Endpoints {
+        tcp: match listen.as_str() {
+            "" => None,
+            addr => Some(addr.parse()?),
+        },
+        tls: match (
+            settings
+                .get_str(SettingKey::TlsCertificate)
+                .unwrap_or_default(),
+            settings.get_str(SettingKey::TlsKey).unwrap_or_default(),
+        ) {
+            ("", _) | (_, "") => None,
+            (certificate, key) => Some((certificate.into(), key.into())),
+        },
+        unix: (args.unix_socket
+            || settings
+                .get_bool(SettingKey::UnixSocket)
+                .unwrap_or_default())
+        .then(runtime::socket_path),
+        rest: args.rest || settings.get_bool(SettingKey::RestApi).unwrap_or_default(),
+        dashboard: args.dashboard || settings.get_bool(SettingKey::Dashboard).unwrap_or_default(),
+        #[cfg(target_os = "linux")]
+        activated: systemd::activated_listener()?,
+        #[cfg(all(unix, not(target_os = "linux")))]
+        activated: None,
+    };
+    endpoints.rest |= endpoints.dashboard;
+    // the socket systemd bound stays behind for the next activation
+    #[cfg(unix)]
+    let owns_socket = match &endpoints.activated {
+        Some(listener) => {
+            endpoints.unix = listener.local_addr()?.as_pathname().map(Into::into);
+            false
+        }
+        None => true,
+    };
+    #[cfg(not(unix))]
+    let owns_socket = true;
+
+    let policy = Arc::new(Policy::load()?);
+    let auth = TokenAuth::new(&auth::load_or_create_token()?, policy.clone())?;
+
+    let settings = Arc::new(RwLock::new(settings));
+    let engine = Arc::new(ScriptEngine::default());
+    let runs = Arc::new(Runs::new(engine.clone()));
+    let (loaded, on_loaded) = oneshot::channel();
+    tokio::spawn(follow_settings(engine.clone(), settings.clone(), loaded));
+    #[cfg(unix)]
+    tokio::spawn(lifecycle::reload_on_hangup(
+        engine.clone(),
+        settings.clone(),
+    ));
+
+    let unix = endpoints.unix.clone().filter(|_| owns_socket);
+    let (stop, shutdown) = lifecycle::Shutdown::new();
+    let server = run_gprc_server(
+        engine,
+        settings,
+        endpoints,
+        auth,
+        policy,
+        on_loaded,
+        runs.clone(),
+        shutdown,
+    );
+    tokio::pin!(server);
+    let result = tokio::select! {
+        result = &mut server => result,
+        signal = lifecycle::shutdown_signal() => {
+            info!("{} received, shutting down", signal?);
+            #[cfg(target_os = "linux")]
+            let _ = systemd::notify("STOPPING=1");
+            runs.close();
+            let _ = stop.send(true);
+            // runs stream to their clients until the script finishes, runs
+            // whose client went away are waited for as well
+            let drained = tokio::time::timeout(SHUTDOWN_DEADLINE, async {
+                tokio::join!(&mut server, runs.wait_idle(SHUTDOWN_DEADLINE))
+            });
+            match drained.await {
+                Ok((result, true)) => result,
+                _ => {
+                    warn!("scripts still running after {:?}, stopping anyway", SHUTDOWN_DEADLINE);
+                    Ok(())
+                }
+            }
+        }
+    };
+
+    runtime::remove_runtime_files(unix.as_deref());
+    result
 }
@@ -0,0 +1,386 @@
+use std::{io, path::PathBuf};
+
+use flaunch_core::{
+    logging::{info, warn},
+    script_engine::Script,
+    settings::master_settings,
+};
+
+/// Who is calling, as established by `auth::TokenAuth`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Client {
+    /// the daemon token or the user running the daemon, may do anything.
+    Owner,
+    /// one of the tokens listed in the policy.
+    Token(String),
+    /// a peer on the unix socket, running as another user.
+    Unix { uid: u32, gid: u32 },
+}
+
+/// Which scripts clients other than the owner may see and run.
+///
+/// Read from `policy.toml` next to the master settings:
+///
+/// ```toml
+/// [[rule]]
+/// tokens = ["3f1c..."]
+/// uids = [1001]
+/// gids = [100]
+/// tags = ["deploy"]
+/// files = ["/srv/scripts/ops/*.py"]
+/// ids = ["15674512336408235622"]
+/// ```
+///
+/// A rule applies to a client matching one of its tokens, uids or gids and
+/// allows the scripts matching one of its tags, files or ids. In `files`,
+/// `*` matches any sequence of characters, `/` included, and `?` a single
+/// one. `ids` are strings, as they don't fit a toml integer. Without a
+/// policy file every client may run everything.
+#[derive(Debug, Default)]
+pub struct Policy {
+    rules: Option<Vec<Rule>>,
+}
+
+#[derive(Debug, Default)]
+struct Rule {
+    tokens: Vec<String>,
+    uids: Vec<u32>,
+    gids: Vec<u32>,
+    tags: Vec<String>,
+    files: Vec<String>,
+    ids: Vec<u64>,
+}
+
+pub fn policy_file() -> PathBuf {
+    master_settings().with_file_name("policy.toml")
+}
+
+impl Policy {
+    pub fn load() -> io::Result<Policy> {
+        let file = policy_file();
+        let contents = match std::fs::read_to_string(&file) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Policy::default()),
+            Err(e) => return Err(e),
+        };
+        let policy = Policy::parse(&contents).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", file.to_string_lossy(), e),
+            )
+        })?;
+        info!("enforcing access policy {}", file.to_string_lossy());
+        Ok(policy)
+    }
+
+    pub fn parse(contents: &str) -> Result<Policy, String> {
+        let table = contents.parse::<toml::Table>().map_err(|e| e.to_string())?;
+        let rules = match table.get("rule") {
+            Some(toml::Value::Array(rules)) => rules,
+            Some(_) => return Err("rule should be an array of tables".to_string()),
+            None => {
+                return Ok(Policy {
+                    rules: Some(Vec::new()),
+                })
+            }
+        };
+
+        let rules = rules
+            .iter()
+            .map(|rule| {
+                let rule = rule
+                    .as_table()
+                    .ok_or_else(|| "rule should be an array of tables".to_string())?;
+                for key in rule.keys() {
+                    if !["tokens", "uids", "gids", "tags", "files", "ids"].contains(&key.as_str()) {
+                        warn!("unknown key {:?} in access policy rule", key);
+                    }
+                }
+                Ok(Rule {
+                    tokens: strings(rule, "tokens")?,
+                    uids: numbers(rule, "uids")?,
+                    gids: numbers(rule, "gids")?,
+                    tags: strings(rule, "tags")?,
+                    files: strings(rule, "files")?,
+                    ids: strings(rule, "ids")?
+                        .iter()
+                        .map(|id| {
+                            id.parse()
+                                .map_err(|_| format!("invalid script id {:?}", id))
+                        })
+                        .collect::<Result<_, String>>()?,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Policy { rules: Some(rules) })
+    }
+
+    /// Whether a policy file is in place at all.
+    pub fn is_enforced(&self) -> bool {
+        self.rules.is_some()
+    }
+
+    /// Tokens, besides the daemon token, that clients may present.
+    pub fn knows_token(&self, token: &[u8]) -> Option<String> {
+        self.rules
+            .iter()
+            .flatten()
+            .flat_map(|rule| &rule.tokens)
+            .find(|known| crate::auth::same(known.as_bytes(), token))
+            .cloned()
+    }
+
+    /// Whether `client` may see the state of the daemon itself, like its
+    /// settings and the diagnostics of all scripts. Only the owner, once a
+    /// policy is in place.
+    pub fn allows_inspection(&self, client: &Client) -> bool {
+        !self.is_enforced() || *client == Client::Owner
+    }
+
+    pub fn allows(&self, client: &Client, script: &Script) -> bool {
+        let rules = match (&self.rules, client) {
+            (None, _) | (_, Client::Owner) => return true,
+            (Some(rules), _) => rules,
+        };
+        rules
+            .iter()
+            .filter(|rule| rule.applies_to(client))
+            .any(|rule| rule.selects(script))
+    }
+
+    /// The scripts `client` is allowed to see.
+    pub fn visible(&self, client: &Client, scripts: Vec<Script>) -> Vec<Script> {
+        scripts
+            .into_iter()
+            .filter(|s| self.allows(client, s))
+            .collect()
+    }
+}
+
+impl Rule {
+    fn applies_to(&self, client: &Client) -> bool {
+        match client {
+            Client::Owner => true,
+            Client::Token(token) => self.tokens.contains(token),
+            Client::Unix { uid, gid } => self.uids.contains(uid) || self.gids.contains(gid),
+        }
+    }
+
+    fn selects(&self, script: &Script) -> bool {
+        let file = script.file.to_string_lossy();
+        script.tags.iter().any(|tag| self.tags.contains(tag))
+            || self.files.iter().any(|pattern| glob_match(pattern, &file))
+            || script.get_key().is_some_and(|id| self.ids.contains(&id))
+    }
+}
+
+fn strings(rule: &toml::Table, key: &str) -> Result<Vec<String>, String> {
+    values(rule, key)?
+        .iter()
+        .map(|v| {
+            v.as_str()
+                .map(str::to_string)
+                .ok_or_else(|| format!("{} should only contain strings", key))
+        })
+        .collect()
+}
+
+fn numbers(rule: &toml::Table, key: &str) -> Result<Vec<u32>, String> {
+    values(rule, key)?
+        .iter()
+        .map(|v| {
+            v.as_integer()
+                .and_then(|i| u32::try_from(i).ok())
+                .ok_or_else(|| format!("{} should only contain positive integers", key))
+        })
+        .collect()
+}
+
+fn values<'a>(rule: &'a toml::Table, key: &str) -> Result<&'a [toml::Value], String> {
+    match rule.get(key) {
+        Some(toml::Value::Array(values)) => Ok(values),
+        Some(_) => Err(format!("{} should be an array", key)),
+        None => Ok(&[]),
+    }
+}
+
+/// `*` matches any sequence, `?` any single character.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // position of the last `*` and the text it matched up to, to backtrack to
+    let mut star = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flaunch_core::script_engine::InterpreterType;
+
+    fn script(name: &str, file: &str, tags: &[&str]) -> Script {
+        let mut script = Script::new(name.to_string(), InterpreterType::Python);
+        script.file = file.into();
+        script.tags = tags.iter().map(|tag| tag.to_string()).collect();
+        script
+    }
+
+    #[test]
+    fn glob_edge_cases() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*", "/srv/scripts/a.py"));
+        // `*` crosses separators, `**` is the same as `*`
+        assert!(glob_match("/srv/*.py", "/srv/ops/a.py"));
+        assert!(glob_match("/srv/**/a.py", "/srv/ops/a.py"));
+        assert!(glob_match("/srv/**", "/srv/a.py"));
+        assert!(!glob_match("/srv/*.py", "/srv/a.pyc"));
+        assert!(glob_match("*a*b", "xaxab"));
+
+        assert!(glob_match("/srv/?.py", "/srv/a.py"));
+        assert!(!glob_match("/srv/?.py", "/srv/.py"));
+        assert!(!glob_match("/srv/?.py", "/srv/ab.py"));
+
+        // a directory doesn't match the files in it
+        assert!(!glob_match("/srv/scripts/", "/srv/scripts/a.py"));
+        assert!(!glob_match("/srv/scripts", "/srv/scripts/a.py"));
+        assert!(glob_match("/srv/scripts/*", "/srv/scripts/a.py"));
+        assert!(!glob_match("/srv/scripts/*", "/srv/scripts"));
+    }
+
+    #[test]
+    fn rules_select_by_tag_file_and_id() {
+        let by_id = script("report", "/home/me/report.py", &[]);
+        let policy = Policy::parse(&format!(
+            r#"
+            [[rule]]
+            tokens = ["ops-token"]
+            gids = [100]
+            tags = ["deploy"]
+            files = ["/srv/scripts/ops/*.py"]
+
+            [[rule]]
+            uids = [1001]
+            ids = ["{}"]
+            "#,
+            by_id.get_key().unwrap()
+        ))
+        .unwrap();
+        let tagged = script("deploy", "/home/me/deploy.py", &["deploy", "prod"]);
+        let in_ops = script("restart", "/srv/scripts/ops/restart.py", &[]);
+        let other = script("other", "/srv/scripts/other.py", &["prod"]);
+
+        let ops = Client::Token("ops-token".to_string());
+        assert!(policy.allows(&ops, &tagged));
+        assert!(policy.allows(&ops, &in_ops));
+        assert!(!policy.allows(&ops, &other));
+        assert!(!policy.allows(&ops, &by_id));
+
+        let staff = Client::Unix {
+            uid: 1002,
+            gid: 100,
+        };
+        assert!(policy.allows(&staff, &tagged));
+        assert!(!policy.allows(&staff, &by_id));
+
+        let reporter = Client::Unix { uid: 1001, gid: 1 };
+        assert!(policy.allows(&reporter, &by_id));
+        assert!(!policy.allows(&reporter, &tagged));
+
+        let stranger = Client::Token("guess".to_string());
+        assert!(!policy.allows(&stranger, &tagged));
+        assert!(policy.allows(&Client::Owner, &other));
+
+        let scripts = vec![tagged, in_ops, other, by_id];
+        assert_eq!(policy.visible(&ops, scripts.clone()).len(), 2);
+        assert_eq!(policy.visible(&Client::Owner, scripts).len(), 4);
+    }
+
+    #[test]
+    fn without_a_policy_everyone_may_run_everything() {
+        let client = Client::Token("anything".to_string());
+        let script = script("a", "/a.py", &[]);
+        assert!(!Policy::default().is_enforced());
+        assert!(Policy::default().allows(&client, &script));
+
+        // an empty policy file lets only the owner in
+        let empty = Policy::parse("").unwrap();
+        assert!(empty.is_enforced());
+        assert!(!empty.allows(&client, &script));
+        assert!(empty.allows(&Client::Owner, &script));
+    }
+
+    #[test]
+    fn only_the_owner_may_inspect_an_enforced_daemon() {
+        let client = Client::Unix {
+            uid: 1001,
+            gid: 100,
+        };
+        assert!(Policy::default().allows_inspection(&client));
+
+        let policy = Policy::parse("[[rule]]\nuids = [1001]\ntags = [\"deploy\"]").unwrap();
+        assert!(!policy.allows_inspection(&client));
+        assert!(policy.allows_inspection(&Client::Owner));
+    }
+
+    #[test]
+    fn unknown_keys_grant_nothing() {
+        let policy = Policy::parse(
+            r#"
+            comment = "top level keys are ignored"
+            [[rule]]
+            toknes = ["ops-token"]
+            tags = ["deploy"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(policy.knows_token(b"ops-token"), None);
+        let client = Client::Token("ops-token".to_string());
+        assert!(!policy.allows(&client, &script("a", "/a.py", &["deploy"])));
+    }
+
+    #[test]
+    fn invalid_policies_are_refused() {
+        for contents in [
+            "rule = 1",
+            "rule = [1]",
+            "[[rule]]\ntags = \"deploy\"",
+            "[[rule]]\nuids = [-1]",
+            "[[rule]]\ngids = [\"staff\"]",
+            "[[rule]]\nids = [\"not a number\"]",
+            "[[rule]\n",
+        ] {
+            assert!(Policy::parse(contents).is_err(), "{:?}", contents);
+        }
+    }
+
+    #[test]
+    fn knows_the_tokens_of_all_rules() {
+        let policy =
+            Policy::parse("[[rule]]\ntokens = [\"a\"]\n[[rule]]\ntokens = [\"b\"]\n").unwrap();
+        assert_eq!(policy.knows_token(b"b"), Some("b".to_string()));
+        assert_eq!(policy.knows_token(b"c"), None);
+        assert_eq!(policy.knows_token(b""), None);
+    }
+}
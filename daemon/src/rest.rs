@@ -0,0 +1,702 @@
+use std::{any::Any, convert::Infallible, sync::Arc, time::UNIX_EPOCH};
+
+use axum::{
+    extract::{Path, Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{
+        sse::{Event, Sse},
+        IntoResponse, Response,
+    },
+    routing::{get, post, put},
+    Extension, Router,
+};
+use flaunch_core::{
+    script_engine::{
+        ArgumentType, CallError, CallEvent, CallOutput, ParseError, Script, ScriptEngine,
+        ScriptEngineError,
+    },
+    settings::{JsonValue, Settings, SettingsError},
+    SettingKey,
+};
+use tokio::sync::RwLock;
+use tokio_stream::{wrappers::UnboundedReceiverStream, Stream, StreamExt};
+
+use crate::{
+    auth::{PeerInfo, TokenAuth},
+    policy::{Client, Policy},
+    runs::{Run, RunUpdate, Runs},
+};
+
+/// What the json api serves, the same the grpc services use.
+pub struct Gateway {
+    pub engine: Arc<ScriptEngine>,
+    pub runs: Arc<Runs>,
+    pub settings: Arc<RwLock<Settings<SettingKey>>>,
+    pub policy: Arc<Policy>,
+    pub auth: TokenAuth,
+}
+
+/// Json api under `/api`, mirroring the grpc services:
+///
+/// - `GET /api/scripts`, `GET /api/scripts/{id}`
+/// - `POST /api/scripts/{id}/run` with the arguments as a json array, or an
+///   object by argument name. Streams the run as server-sent events:
+///   `accepted`, `stdout`, `stderr`, `progress` and `result` or `error`.
+/// - `GET /api/runs`, `GET /api/runs/{id}`
+/// - `GET /api/diagnostics`
+/// - `GET /api/settings`, `PUT /api/settings/{key}` with a json value,
+///   answers the stored value and whether it needs a restart of flaunchd
+///
+/// Clients authenticate like on grpc, script ids are sent as strings.
+pub fn router(gateway: Gateway) -> Router {
+    let gateway = Arc::new(gateway);
+    Router::new()
+        .route("/api/scripts", get(get_scripts))
+        .route("/api/scripts/{id}", get(get_script))
+        .route("/api/scripts/{id}/run", post(run_script))
+        .route("/api/runs", get(get_runs))
+        .route("/api/runs/{id}", get(get_run))
+        .route("/api/diagnostics", get(get_diagnostics))
+        .route("/api/settings", get(get_settings))
+        .route("/api/settings/{key}", put(set_setting))
+        .layer(axum::middleware::from_fn_with_state(
+            gateway.clone(),
+            authenticate,
+        ))
+        .with_state(gateway)
+}
+
+async fn authenticate(
+    State(gateway): State<Arc<Gateway>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let client = gateway.auth.client(
+        request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .map(|value| value.as_bytes()),
+        request.extensions().get::<PeerInfo>(),
+    );
+    match client {
+        Some(client) => {
+            request.extensions_mut().insert(client);
+            next.run(request).await
+        }
+        None => error(StatusCode::UNAUTHORIZED, "missing or invalid token"),
+    }
+}
+
+type ApiResult = Result<Response, Response>;
+
+fn json(value: JsonValue) -> Response {
+    ([(header::CONTENT_TYPE, "application/json")], value.dump()).into_response()
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    let mut body = JsonValue::new_object();
+    body["error"] = message.into();
+    (status, json(body)).into_response()
+}
+
+async fn get_scripts(
+    State(gateway): State<Arc<Gateway>>,
+    Extension(client): Extension<Client>,
+) -> Response {
+    let scripts = gateway
+        .policy
+        .visible(&client, gateway.engine.scripts().await);
+    json(JsonValue::Array(scripts.iter().map(script_json).collect()))
+}
+
+/// The script with `id`, if `client` may use it. Scripts the client may
+/// not see are not found, like in the listing.
+async fn find_script(gateway: &Gateway, client: &Client, id: &str) -> Result<Script, Response> {
+    let not_found = || error(StatusCode::NOT_FOUND, &format!("no script with id {}", id));
+    let id = id.parse::<u64>().map_err(|_| not_found())?;
+    gateway
+        .engine
+        .scripts()
+        .await
+        .into_iter()
+        .find(|s| s.get_key() == Some(id) && gateway.policy.allows(client, s))
+        .ok_or_else(not_found)
+}
+
+async fn get_script(
+    State(gateway): State<Arc<Gateway>>,
+    Extension(client): Extension<Client>,
+    Path(id): Path<String>,
+) -> ApiResult {
+    let script = find_script(&gateway, &client, &id).await?;
+    Ok(json(script_json(&script)))
+}
+
+async fn run_script(
+    State(gateway): State<Arc<Gateway>>,
+    Extension(client): Extension<Client>,
+    Path(id): Path<String>,
+    body: String,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, Response> {
+    let script = find_script(&gateway, &client, &id).await?;
+    let args = if body.trim().is_empty() {
+        Vec::new()
+    } else {
+        let arguments = json::parse(&body)
+            .map_err(|e| error(StatusCode::BAD_REQUEST, &format!("invalid json: {}", e)))?;
+        into_args(&script, &arguments).map_err(|e| error(StatusCode::BAD_REQUEST, &e))?
+    };
+
+    let (run_id, updates) = gateway
+        .runs
+        .start(script, args)
+        .map_err(|e| error(StatusCode::SERVICE_UNAVAILABLE, &e.to_string()))?;
+    let mut accepted = JsonValue::new_object();
+    accepted["run_id"] = run_id.into();
+    let accepted = tokio_stream::once(Ok(event("accepted", accepted)));
+    let updates = UnboundedReceiverStream::new(updates).map(|update| {
+        Ok(match update {
+            RunUpdate::Event(e) => {
+                let (name, data) = call_event_json(&e);
+                event(name, data)
+            }
+            RunUpdate::Finished(Ok(output)) => event("result", output_json(&output)),
+            RunUpdate::Finished(Err(e)) => event("error", error_json(&e)),
+        })
+    });
+    Ok(Sse::new(accepted.chain(updates)))
+}
+
+fn event(name: &str, data: JsonValue) -> Event {
+    Event::default().event(name).data(data.dump())
+}
+
+async fn get_runs(
+    State(gateway): State<Arc<Gateway>>,
+    Extension(client): Extension<Client>,
+) -> Response {
+    let runs = gateway
+        .runs
+        .list()
+        .into_iter()
+        .filter(|run| gateway.policy.allows(&client, &run.script))
+        .map(|run| run_json(&run, false))
+        .collect();
+    json(JsonValue::Array(runs))
+}
+
+async fn get_run(
+    State(gateway): State<Arc<Gateway>>,
+    Extension(client): Extension<Client>,
+    Path(id): Path<u64>,
+) -> ApiResult {
+    match gateway.runs.get(id) {
+        Some(run) if gateway.policy.allows(&client, &run.script) => Ok(json(run_json(&run, true))),
+        _ => Err(error(
+            StatusCode::NOT_FOUND,
+            &format!("no run with id {}", id),
+        )),
+    }
+}
+
+/// diagnostics name files of scripts the client may not see.
+async fn get_diagnostics(
+    State(gateway): State<Arc<Gateway>>,
+    Extension(client): Extension<Client>,
+) -> ApiResult {
+    if !gateway.policy.allows_inspection(&client) {
+        return Err(error(
+            StatusCode::FORBIDDEN,
+            "only the owner may read the diagnostics",
+        ));
+    }
+    Ok(json(diagnostics_json(&gateway.engine.diagnostics())))
+}
+
+async fn get_settings(
+    State(gateway): State<Arc<Gateway>>,
+    Extension(client): Extension<Client>,
+) -> ApiResult {
+    if !gateway.policy.allows_inspection(&client) {
+        return Err(error(
+            StatusCode::FORBIDDEN,
+            "only the owner may read settings",
+        ));
+    }
+    let mut settings = JsonValue::new_object();
+    for (key, value) in gateway.settings.read().await.entries() {
+        settings[key] = value;
+    }
+    Ok(json(settings))
+}
+
+/// Persists the setting and returns the stored value.
+async fn set_setting(
+    State(gateway): State<Arc<Gateway>>,
+    Extension(client): Extension<Client>,
+    Path(key): Path<String>,
+    body: String,
+) -> ApiResult {
+    if gateway.policy.is_enforced() && client != Client::Owner {
+        return Err(error(
+            StatusCode::FORBIDDEN,
+            "only the owner may change settings",
+        ));
+    }
+    let value = json::parse(&body)
+        .map_err(|e| error(StatusCode::BAD_REQUEST, &format!("invalid json: {}", e)))?;
+
+    let mut settings = gateway.settings.write().await;
+    let setting_key = settings
+        .set_and_save(&key, value.clone())
+        .map_err(|e| match e {
+            SettingsError::UnknownKey(_) => error(StatusCode::NOT_FOUND, &e.to_string()),
+            SettingsError::WrongType(..) => error(StatusCode::BAD_REQUEST, &e.to_string()),
+            SettingsError::NotSaved(_) => error(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+        })?;
+
+    let mut setting = JsonValue::new_object();
+    setting[key.as_str()] = value;
+    setting["needs_restart"] = setting_key.needs_restart().into();
+    Ok(json(setting))
+}
+
+/// Matches the arguments, a json array or an object by argument name,
+/// to the types the script expects. Arguments left out of an object at the
+/// end take the defaults of the script, ones left out before a given
+/// argument the default of their type.
+fn into_args(script: &Script, arguments: &JsonValue) -> Result<Vec<Box<dyn Any + Send>>, String> {
+    if arguments.is_object() {
+        if let Some((key, _)) = arguments
+            .entries()
+            .find(|(key, _)| !script.arguments.iter().any(|(name, _, _)| name == key))
+        {
+            return Err(format!("unknown argument {}", key));
+        }
+        let mut values: Vec<Option<&JsonValue>> = script
+            .arguments
+            .iter()
+            .map(|(name, _, _)| Some(&arguments[name.as_str()]).filter(|v| !v.is_null()))
+            .collect();
+        while values.last() == Some(&None) {
+            values.pop();
+        }
+        return script
+            .arguments
+            .iter()
+            .zip(values)
+            .map(|((name, argument_type, _), value)| match value {
+                Some(value) => typed(name, value, argument_type),
+                None => {
+                    default_value(argument_type).ok_or_else(|| format!("missing argument {}", name))
+                }
+            })
+            .collect();
+    }
+    if !arguments.is_array() {
+        return Err("arguments should be an array or an object".to_string());
+    }
+
+    arguments
+        .members()
+        .enumerate()
+        .map(|(i, value)| match script.arguments.get(i) {
+            Some((name, argument_type, _)) => typed(name, value, argument_type),
+            None => typed(&i.to_string(), value, &ArgumentType::NotSpecified),
+        })
+        .collect()
+}
+
+fn typed(
+    name: &str,
+    value: &JsonValue,
+    argument_type: &ArgumentType,
+) -> Result<Box<dyn Any + Send>, String> {
+    into_any(value, argument_type)
+        .ok_or_else(|| format!("argument {} should be a {}", name, argument_type))
+}
+
+/// the value an argument type carries, parsed like the interpreters do.
+fn default_value(argument_type: &ArgumentType) -> Option<Box<dyn Any + Send>> {
+    Some(match argument_type {
+        ArgumentType::Boolean(b) => Box::new(matches!(
+            b.to_lowercase().as_str(),
+            "true" | "yes" | "on" | "1"
+        )),
+        ArgumentType::Int(i) => Box::new(*i),
+        ArgumentType::Uint(u) => Box::new(*u),
+        ArgumentType::Float(f) => Box::new(*f),
+        ArgumentType::String(s) => Box::new(s.clone()),
+        ArgumentType::List(l) => Box::new(
+            l.split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_string)
+                .collect::<Vec<_>>(),
+        ),
+        ArgumentType::NotSpecified => return None,
+    })
+}
+
+fn into_any(value: &JsonValue, argument_type: &ArgumentType) -> Option<Box<dyn Any + Send>> {
+    let strings = || {
+        value
+            .members()
+            .map(|v| v.as_str().map(str::to_string))
+            .collect::<Option<Vec<String>>>()
+    };
+    Some(match argument_type {
+        ArgumentType::Boolean(_) => Box::new(value.as_bool()?),
+        ArgumentType::Int(_) => Box::new(value.as_i32()?),
+        ArgumentType::Uint(_) => Box::new(value.as_u32()?),
+        ArgumentType::Float(_) => Box::new(value.as_f32()?),
+        ArgumentType::String(_) => Box::new(value.as_str()?.to_string()),
+        ArgumentType::List(_) if value.is_array() => Box::new(strings()?),
+        ArgumentType::List(_) => return None,
+        ArgumentType::NotSpecified => match value {
+            JsonValue::Boolean(b) => Box::new(*b),
+            JsonValue::Number(_) => match value.as_i32() {
+                Some(i) => Box::new(i),
+                None => Box::new(value.as_f32()?),
+            },
+            JsonValue::Array(_) => Box::new(strings()?),
+            _ => Box::new(value.as_str()?.to_string()),
+        },
+    })
+}
+
+fn script_json(script: &Script) -> JsonValue {
+    let mut json = JsonValue::new_object();
+    json["id"] = script.get_key().unwrap_or_default().to_string().into();
+    json["name"] = script.name.as_str().into();
+    json["description"] = script.description.as_str().into();
+    json["file"] = script.file.to_string_lossy().to_string().into();
+    json["interpreter"] = script.interpreter_type.name().into();
+    json["tags"] = script.tags.clone().into();
+    json["arguments"] = JsonValue::Array(
+        script
+            .arguments
+            .iter()
+            .map(|(name, argument_type, description)| {
+                let mut argument = JsonValue::new_object();
+                argument["name"] = name.as_str().into();
+                argument["type"] = argument_type_name(argument_type).into();
+                argument["description"] = description.as_str().into();
+                argument
+            })
+            .collect(),
+    );
+    json
+}
+
+fn argument_type_name(argument_type: &ArgumentType) -> &'static str {
+    match argument_type {
+        ArgumentType::Boolean(_) => "boolean",
+        ArgumentType::Int(_) => "integer",
+        ArgumentType::Uint(_) => "uinteger",
+        ArgumentType::Float(_) => "float",
+        ArgumentType::String(_) => "string",
+        ArgumentType::List(_) => "list",
+        ArgumentType::NotSpecified => "notspecified",
+    }
+}
+
+/// With `details` the events and the result are included as well.
+fn run_json(run: &Run, details: bool) -> JsonValue {
+    let mut json = JsonValue::new_object();
+    json["id"] = run.id.into();
+    json["script_id"] = run.script.get_key().unwrap_or_default().to_string().into();
+    json["script_name"] = run.script.name.as_str().into();
+    json["started"] = run
+        .started
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+        .into();
+    json["state"] = match &run.result {
+        None => "running",
+        Some(Ok(_)) => "finished",
+        Some(Err(_)) => "failed",
+    }
+    .into();
+
+    if details {
+        json["events"] = JsonValue::Array(
+            run.events
+                .iter()
+                .map(|e| {
+                    let (name, mut event) = call_event_json(e);
+                    event["type"] = name.into();
+                    event
+                })
+                .collect(),
+        );
+        match &run.result {
+            Some(Ok(output)) => json["result"] = output_json(output),
+            Some(Err(e)) => json["error"] = error_json(e),
+            None => {}
+        }
+    }
+    json
+}
+
+fn call_event_json(event: &CallEvent) -> (&'static str, JsonValue) {
+    let mut json = JsonValue::new_object();
+    match event {
+        CallEvent::Output(text) => {
+            json["text"] = text.as_str().into();
+            ("stdout", json)
+        }
+        CallEvent::Error(text) => {
+            json["text"] = text.as_str().into();
+            ("stderr", json)
+        }
+        CallEvent::Progress(fraction, message) => {
+            json["fraction"] = (*fraction).into();
+            json["message"] = message.as_str().into();
+            ("progress", json)
+        }
+    }
+}
+
+fn output_json(output: &CallOutput) -> JsonValue {
+    let mut json = JsonValue::new_object();
+    match output {
+        CallOutput::Nothing => json["type"] = "nothing".into(),
+        CallOutput::Text(text) => {
+            json["type"] = "text".into();
+            json["text"] = text.as_str().into();
+        }
+        CallOutput::Table(table) => {
+            json["type"] = "table".into();
+            json["columns"] = table.columns.clone().into();
+            json["rows"] = JsonValue::Array(
+                table
+                    .rows
+                    .iter()
+                    .map(|row| JsonValue::Array(row.clone()))
+                    .collect(),
+            );
+        }
+        CallOutput::Response(response) => {
+            json["type"] = "response".into();
+            json["status"] = response.status.into();
+            json["headers"] = JsonValue::Array(
+                response
+                    .headers
+                    .iter()
+                    .map(|(name, value)| vec![name.as_str(), value.as_str()].into())
+                    .collect(),
+            );
+            json["body"] = response.body.as_str().into();
+        }
+    }
+    json
+}
+
+fn error_json(error: &ScriptEngineError) -> JsonValue {
+    let mut json = JsonValue::new_object();
+    match error {
+        ScriptEngineError::CallFailed(_, CallError::Exception { message, traceback }) => {
+            json["message"] = message.as_str().into();
+            json["traceback"] = traceback.as_str().into();
+        }
+        ScriptEngineError::CallFailed(_, e) => json["message"] = e.to_string().into(),
+        e => json["message"] = e.to_string().into(),
+    }
+    json
+}
+
+fn diagnostics_json(errors: &[ParseError]) -> JsonValue {
+    JsonValue::Array(
+        errors
+            .iter()
+            .map(|e| {
+                let mut json = JsonValue::new_object();
+                json["filename"] = e.filename.as_str().into();
+                json["message"] = e.message.as_str().into();
+                json["traceback"] = e.traceback.as_str().into();
+                json
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::{to_bytes, Body};
+    use tower::ServiceExt;
+
+    const GREET: &str = concat!(
+        "+++\n",
+        "name = \"greet\"\n",
+        "tags = [\"public\"]\n",
+        "\n",
+        "[[variables]]\n",
+        "name = \"who\"\n",
+        "type = \"string\"\n",
+        "default = \"world\"\n",
+        "\n",
+        "[[variables]]\n",
+        "name = \"times\"\n",
+        "type = \"uint\"\n",
+        "default = 1\n",
+        "+++\n",
+        "{{ who }} {{ times }}\n",
+    );
+
+    /// a router serving `greet` to the ops token and `secret` to the owner
+    /// only, with the ids of both.
+    async fn router_with_scripts(name: &str) -> (Router, String, String) {
+        let dir =
+            std::env::temp_dir().join(format!("flaunch_rest_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("greet.tera"), GREET).unwrap();
+        std::fs::write(
+            dir.join("secret.tera"),
+            "+++\nname = \"secret\"\n+++\nsecret\n",
+        )
+        .unwrap();
+        let engine = Arc::new(ScriptEngine::default());
+        engine.load(&dir).await.unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+
+        let scripts = engine.scripts().await;
+        let id = |name: &str| {
+            scripts
+                .iter()
+                .find(|s| s.name == name)
+                .and_then(Script::get_key)
+                .unwrap()
+                .to_string()
+        };
+        let (greet, secret) = (id("greet"), id("secret"));
+
+        let policy = Arc::new(
+            Policy::parse("[[rule]]\ntokens = [\"ops-token\"]\ntags = [\"public\"]").unwrap(),
+        );
+        let gateway = Gateway {
+            runs: Arc::new(Runs::new(engine.clone())),
+            engine,
+            settings: Arc::new(RwLock::new(Settings::new(
+                &flaunch_core::app_setting_defaults(),
+            ))),
+            auth: TokenAuth::new("owner-token", policy.clone()).unwrap(),
+            policy,
+        };
+        (router(gateway), greet, secret)
+    }
+
+    async fn send(
+        router: &Router,
+        method: &str,
+        uri: &str,
+        token: Option<&str>,
+        body: &str,
+    ) -> (StatusCode, String) {
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let response = router
+            .clone()
+            .oneshot(request.body(Body::from(body.to_string())).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    /// the text the run of `greet` rendered, from its `result` event.
+    fn rendered(events: &str) -> String {
+        let data = events
+            .split("\n\n")
+            .find(|event| event.starts_with("event: result"))
+            .and_then(|event| event.lines().find_map(|line| line.strip_prefix("data: ")))
+            .unwrap_or_else(|| panic!("no result in {:?}", events));
+        json::parse(data).unwrap()["text"].to_string()
+    }
+
+    #[tokio::test]
+    async fn clients_need_a_known_token() {
+        let (router, _, _) = router_with_scripts("auth").await;
+        for token in [None, Some("wrong-token"), Some("owner-token-but-longer")] {
+            let (status, _) = send(&router, "GET", "/api/scripts", token, "").await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{:?}", token);
+        }
+    }
+
+    #[tokio::test]
+    async fn lists_the_scripts_the_client_may_see() {
+        let (router, greet, _) = router_with_scripts("list").await;
+
+        let (status, body) = send(&router, "GET", "/api/scripts", Some("owner-token"), "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(json::parse(&body).unwrap().len(), 2);
+
+        let (_, body) = send(&router, "GET", "/api/scripts", Some("ops-token"), "").await;
+        let scripts = json::parse(&body).unwrap();
+        assert_eq!(scripts.len(), 1);
+        assert_eq!(scripts[0]["id"], greet.as_str());
+        assert_eq!(scripts[0]["interpreter"], "template");
+        assert_eq!(scripts[0]["arguments"][1]["type"], "uinteger");
+    }
+
+    #[tokio::test]
+    async fn hidden_scripts_are_not_found() {
+        let (router, _, secret) = router_with_scripts("hidden").await;
+        let script = format!("/api/scripts/{}", secret);
+        let (status, _) = send(&router, "GET", &script, Some("ops-token"), "").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let run = format!("{}/run", script);
+        let (status, _) = send(&router, "POST", &run, Some("ops-token"), "").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, _) = send(&router, "GET", &script, Some("owner-token"), "").await;
+        assert_eq!(status, StatusCode::OK);
+        for inspection in ["/api/diagnostics", "/api/settings"] {
+            let (status, _) = send(&router, "GET", inspection, Some("ops-token"), "").await;
+            assert_eq!(status, StatusCode::FORBIDDEN, "{}", inspection);
+            let (status, _) = send(&router, "GET", inspection, Some("owner-token"), "").await;
+            assert_eq!(status, StatusCode::OK, "{}", inspection);
+        }
+    }
+
+    #[tokio::test]
+    async fn runs_with_defaults_for_left_out_arguments() {
+        let (router, greet, _) = router_with_scripts("run").await;
+        let run = format!("/api/scripts/{}/run", greet);
+        for (arguments, text) in [
+            ("", "world 1\n"),
+            ("[\"ada\", 2]", "ada 2\n"),
+            ("{\"who\": \"ada\"}", "ada 1\n"),
+            ("{\"times\": 3}", "world 3\n"),
+            ("{\"times\": 3, \"who\": null}", "world 3\n"),
+        ] {
+            let (status, events) = send(&router, "POST", &run, Some("ops-token"), arguments).await;
+            assert_eq!(status, StatusCode::OK, "{}", arguments);
+            assert!(events.starts_with("event: accepted"), "{}", events);
+            assert_eq!(rendered(&events), text, "{}", arguments);
+        }
+    }
+
+    #[tokio::test]
+    async fn refuses_wrong_arguments() {
+        let (router, greet, _) = router_with_scripts("arguments").await;
+        let run = format!("/api/scripts/{}/run", greet);
+        for arguments in [
+            "not json",
+            "\"ada\"",
+            "[1]",
+            "{\"times\": -1}",
+            "{\"nobody\": 1}",
+        ] {
+            let (status, body) = send(&router, "POST", &run, Some("ops-token"), arguments).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", arguments);
+            assert!(json::parse(&body).unwrap()["error"].is_string(), "{}", body);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use std::{
+    any::Any,
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime},
+};
+
+use flaunch_core::script_engine::{CallEvent, CallOutput, Script, ScriptEngine, ScriptEngineError};
+use tokio::sync::{mpsc, watch};
+
+/// finished runs kept around for clients to look at.
+const HISTORY: usize = 100;
+
+/// A script run, started through any of the daemon's APIs.
+#[derive(Debug, Clone)]
+pub struct Run {
+    pub id: u64,
+    pub script: Script,
+    pub started: SystemTime,
+    pub events: Vec<CallEvent>,
+    /// `None` while the script is still running.
+    pub result: Option<Result<CallOutput, ScriptEngineError>>,
+}
+
+#[derive(Debug, Clone)]
+pub enum RunUpdate {
+    Event(CallEvent),
+    Finished(Result<CallOutput, ScriptEngineError>),
+}
+
+/// Returned by `Runs::start` once the daemon is shutting down.
+#[derive(Debug)]
+pub struct ShuttingDown;
+
+impl std::fmt::Display for ShuttingDown {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "flaunchd is shutting down")
+    }
+}
+
+/// Starts scripts on the engine and remembers the latest runs.
+#[derive(Debug)]
+pub struct Runs {
+    engine: Arc<ScriptEngine>,
+    last_id: AtomicU64,
+    runs: Mutex<VecDeque<Run>>,
+    closed: AtomicBool,
+    /// number of runs that didn't finish yet.
+    running: watch::Sender<usize>,
+}
+
+impl Runs {
+    pub fn new(engine: Arc<ScriptEngine>) -> Self {
+        Runs {
+            engine,
+            last_id: AtomicU64::new(0),
+            runs: Mutex::new(VecDeque::new()),
+            closed: AtomicBool::new(false),
+            running: watch::channel(0).0,
+        }
+    }
+
+    /// Calls `script` and returns the id of the run with its updates. The
+    /// run continues when the receiver is dropped.
+    pub fn start(
+        self: &Arc<Self>,
+        script: Script,
+        args: Vec<Box<dyn Any + Send>>,
+    ) -> Result<(u64, mpsc::UnboundedReceiver<RunUpdate>), ShuttingDown> {
+        // counted before checking, `wait_idle` can't miss a run that starts
+        // while closing.
+        self.running.send_modify(|running| *running += 1);
+        if self.closed.load(Ordering::SeqCst) {
+            self.running.send_modify(|running| *running -= 1);
+            return Err(ShuttingDown);
+        }
+
+        let id = self.last_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let script_id = script.get_key().unwrap_or_default();
+        {
+            let mut runs = self.runs.lock().unwrap();
+            runs.push_front(Run {
+                id,
+                script,
+                started: SystemTime::now(),
+                events: Vec::new(),
+                result: None,
+            });
+            runs.truncate(HISTORY);
+        }
+
+        let (updates, receiver) = mpsc::unbounded_channel();
+        let runs = self.clone();
+        tokio::spawn(async move {
+            let (events, mut event_receiver) = mpsc::unbounded_channel::<CallEvent>();
+            let forward = async {
+                while let Some(event) = event_receiver.recv().await {
+                    runs.update(id, |run| run.events.push(event.clone()));
+                    let _ = updates.send(RunUpdate::Event(event));
+                }
+            };
+            let (result, _) = tokio::join!(runs.engine.call(script_id, args, events), forward);
+
+            runs.update(id, |run| run.result = Some(result.clone()));
+            let _ = updates.send(RunUpdate::Finished(result));
+            runs.running.send_modify(|running| *running -= 1);
+        });
+        Ok((id, receiver))
+    }
+
+    /// Refuses new runs from now on.
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+    }
+
+    /// Waits for the running scripts to finish, false when some are still
+    /// running after `deadline`.
+    pub async fn wait_idle(&self, deadline: Duration) -> bool {
+        let mut running = self.running.subscribe();
+        let idle = tokio::time::timeout(deadline, running.wait_for(|running| *running == 0))
+            .await
+            .is_ok();
+        idle
+    }
+
+    /// The latest runs, newest first.
+    pub fn list(&self) -> Vec<Run> {
+        self.runs.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn get(&self, id: u64) -> Option<Run> {
+        self.runs
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|run| run.id == id)
+            .cloned()
+    }
+
+    fn update(&self, id: u64, update: impl FnOnce(&mut Run)) {
+        if let Some(run) = self
+            .runs
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .find(|run| run.id == id)
+        {
+            update(run);
+        }
+    }
+}
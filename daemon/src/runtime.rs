@@ -0,0 +1,51 @@
+use std::{io, net::SocketAddr, path::Path, path::PathBuf};
+
+use flaunch_core::settings::JsonValue;
+pub use flaunch_core::settings::{runtime_dir, runtime_file, socket_path};
+
+pub fn create_runtime_dir() -> io::Result<PathBuf> {
+    let dir = runtime_dir();
+    std::fs::create_dir_all(&dir)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(dir)
+}
+
+/// Lets other users reach the socket in the runtime dir, without listing it.
+#[cfg(unix)]
+pub fn share_runtime_dir() -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(runtime_dir(), std::fs::Permissions::from_mode(0o711))
+}
+
+pub fn write_runtime_file(
+    tcp: Option<SocketAddr>,
+    tls: bool,
+    unix: Option<&Path>,
+) -> io::Result<()> {
+    let mut info = JsonValue::new_object();
+    info["pid"] = std::process::id().into();
+    info["version"] = env!("CARGO_PKG_VERSION").into();
+    if let Some(addr) = tcp {
+        info["tcp"] = addr.to_string().into();
+        info["tls"] = tls.into();
+    }
+    if let Some(path) = unix {
+        info["unix"] = path.to_string_lossy().to_string().into();
+    }
+
+    create_runtime_dir()?;
+    std::fs::write(runtime_file(), info.pretty(4))
+}
+
+/// Removes what `write_runtime_file` and the unix listener created, once
+/// the daemon stops.
+pub fn remove_runtime_files(unix: Option<&Path>) {
+    let _ = std::fs::remove_file(runtime_file());
+    if let Some(path) = unix {
+        let _ = std::fs::remove_file(path);
+    }
+}
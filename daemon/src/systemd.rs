@@ -0,0 +1,60 @@
+use std::{
+    io,
+    os::unix::{
+        io::{FromRawFd, RawFd},
+        net::{UnixDatagram, UnixListener},
+    },
+};
+
+use flaunch_core::logging::info;
+
+/// first fd passed by the service manager, see sd_listen_fds(3).
+const LISTEN_FDS_START: RawFd = 3;
+
+/// The unix socket systemd passed when flaunchd is socket activated, `None`
+/// when started any other way.
+pub fn activated_listener() -> io::Result<Option<UnixListener>> {
+    let for_us = std::env::var("LISTEN_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok())
+        == Some(std::process::id());
+    let fds = std::env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|fds| fds.parse::<RawFd>().ok())
+        .unwrap_or(0);
+    // the variables are left set, changing the environment isn't sound once
+    // the runtime's threads run. scripts started by the daemon have another
+    // pid and don't inherit the fds.
+    if !for_us || fds < 1 {
+        return Ok(None);
+    }
+
+    for fd in LISTEN_FDS_START..LISTEN_FDS_START + fds {
+        if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    info!("socket activated by systemd");
+    Ok(Some(unsafe { UnixListener::from_raw_fd(LISTEN_FDS_START) }))
+}
+
+/// Sends `state`, like `READY=1`, to the service manager when it asked for
+/// notifications, see sd_notify(3).
+pub fn notify(state: &str) -> io::Result<()> {
+    let path = match std::env::var_os("NOTIFY_SOCKET") {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    let socket = UnixDatagram::unbound()?;
+    match path.to_string_lossy().strip_prefix('@') {
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        None => {
+            socket.send_to(state.as_bytes(), &path)?;
+        }
+    }
+    Ok(())
+}
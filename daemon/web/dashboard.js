@@ -0,0 +1,324 @@
+// Dashboard on top of the json api of flaunchd, see daemon/src/rest.rs.
+
+const views = ['scripts', 'runs', 'diagnostics'];
+let scripts = [];
+let selectedScript = null;
+let selectedRun = null;
+
+// a link like http://[::1]:50051/#token=... logs in directly, the fragment
+// never reaches the server.
+const fragment = new URLSearchParams(location.hash.slice(1));
+if (fragment.has('token')) {
+    localStorage.setItem('flaunch-token', fragment.get('token'));
+    history.replaceState(null, '', '#scripts');
+}
+
+class Unauthorized extends Error { }
+
+async function api(path, options = {}) {
+    const response = await fetch(path, {
+        ...options,
+        headers: { 'authorization': 'Bearer ' + localStorage.getItem('flaunch-token') },
+    });
+    if (response.status === 401) {
+        throw new Unauthorized();
+    }
+    if (!response.ok) {
+        const body = await response.json().catch(() => ({}));
+        throw new Error(body.error || response.statusText);
+    }
+    return response;
+}
+
+function element(tag, properties = {}, ...children) {
+    const e = Object.assign(document.createElement(tag), properties);
+    e.append(...children.filter(c => c !== null && c !== undefined));
+    return e;
+}
+
+function show(view) {
+    for (const section of document.querySelectorAll('main > section')) {
+        section.hidden = section.id !== view;
+    }
+    for (const link of document.querySelectorAll('nav a')) {
+        link.classList.toggle('active', link.dataset.view === view);
+    }
+}
+
+async function route() {
+    const view = views.includes(location.hash.slice(1)) ? location.hash.slice(1) : 'scripts';
+    show(view);
+    try {
+        await Promise.all([refresh(view), refreshDiagnosticCount()]);
+    } catch (e) {
+        if (e instanceof Unauthorized) {
+            show('login');
+        } else {
+            console.error(e);
+        }
+    }
+}
+
+function refresh(view) {
+    switch (view) {
+        case 'scripts': return loadScripts();
+        case 'runs': return loadRuns();
+        case 'diagnostics': return loadDiagnostics();
+    }
+}
+
+// scripts
+
+async function loadScripts() {
+    scripts = await (await api('/api/scripts')).json();
+    renderScripts();
+}
+
+function renderScripts() {
+    const filter = document.getElementById('filter').value.toLowerCase();
+    const list = document.getElementById('script-list');
+    list.replaceChildren(...scripts
+        .filter(s => (s.name + ' ' + s.description + ' ' + s.tags.join(' ')).toLowerCase().includes(filter))
+        .map(s => {
+            const item = element('li', {}, s.name, element('br'), element('small', {}, s.interpreter + ' · ' + s.file));
+            item.classList.toggle('selected', s.id === selectedScript);
+            item.onclick = () => {
+                selectedScript = s.id;
+                renderScripts();
+            };
+            return item;
+        }));
+
+    const script = scripts.find(s => s.id === selectedScript);
+    document.getElementById('script-detail').replaceChildren(...(script ? scriptDetail(script) : []));
+}
+
+function scriptDetail(script) {
+    const form = element('form');
+    for (const argument of script.arguments) {
+        form.append(element('label', {},
+            element('span', {}, argument.name),
+            argumentInput(argument),
+            ' ', element('small', { className: 'muted' }, argument.description)));
+    }
+    const output = element('div');
+    form.append(element('button', { type: 'submit' }, 'Run'));
+    form.onsubmit = event => {
+        event.preventDefault();
+        run(script, form, output);
+    };
+
+    return [
+        element('h2', {}, script.name),
+        element('p', {}, script.description),
+        element('p', {}, ...script.tags.map(tag => element('span', { className: 'tag' }, tag))),
+        element('p', { className: 'muted' }, script.file),
+        form,
+        output,
+    ];
+}
+
+function argumentInput(argument) {
+    const input = element('input', { name: argument.name });
+    input.dataset.type = argument.type;
+    switch (argument.type) {
+        case 'boolean':
+            input.type = 'checkbox';
+            break;
+        case 'integer':
+        case 'uinteger':
+            input.type = 'number';
+            input.step = '1';
+            input.required = true;
+            if (argument.type === 'uinteger') {
+                input.min = '0';
+            }
+            break;
+        case 'float':
+            input.type = 'number';
+            input.step = 'any';
+            input.required = true;
+            break;
+        case 'list':
+            input.placeholder = 'comma separated';
+            break;
+    }
+    return input;
+}
+
+function argumentValues(form) {
+    const values = {};
+    for (const input of form.querySelectorAll('input')) {
+        switch (input.dataset.type) {
+            case 'boolean': values[input.name] = input.checked; break;
+            case 'integer':
+            case 'uinteger':
+            case 'float': values[input.name] = Number(input.value); break;
+            case 'list': values[input.name] = input.value.split(',').map(v => v.trim()).filter(v => v); break;
+            default: values[input.name] = input.value;
+        }
+    }
+    return values;
+}
+
+// runs are streamed as server-sent events, EventSource can't POST so the
+// stream is parsed here.
+async function run(script, form, output) {
+    const log = element('pre');
+    const status = element('p', { className: 'running' }, 'starting');
+    const result = element('div');
+    output.replaceChildren(status, log, result);
+
+    try {
+        const response = await api('/api/scripts/' + script.id + '/run', {
+            method: 'POST',
+            body: JSON.stringify(argumentValues(form)),
+        });
+        const reader = response.body.pipeThrough(new TextDecoderStream()).getReader();
+        let buffer = '';
+        for (; ;) {
+            const { value, done } = await reader.read();
+            if (done) {
+                break;
+            }
+            buffer += value;
+            let end;
+            while ((end = buffer.indexOf('\n\n')) >= 0) {
+                const event = parseEvent(buffer.slice(0, end));
+                buffer = buffer.slice(end + 2);
+                if (event) {
+                    showEvent(event.name, event.data, status, log, result);
+                }
+            }
+        }
+    } catch (e) {
+        status.className = 'error';
+        status.textContent = e instanceof Unauthorized ? 'not logged in' : e.message;
+    }
+    refreshDiagnosticCount().catch(console.error);
+}
+
+function parseEvent(text) {
+    let name = 'message';
+    const data = [];
+    for (const line of text.split('\n')) {
+        if (line.startsWith('event:')) {
+            name = line.slice(6).trim();
+        } else if (line.startsWith('data:')) {
+            data.push(line.slice(5).replace(/^ /, ''));
+        }
+    }
+    return data.length ? { name, data: JSON.parse(data.join('\n')) } : null;
+}
+
+function showEvent(name, data, status, log, result) {
+    switch (name) {
+        case 'accepted':
+            status.textContent = 'running as run ' + data.run_id;
+            break;
+        case 'stdout':
+        case 'stderr':
+            log.append(element('span', { className: name }, data.text + '\n'));
+            break;
+        case 'progress':
+            status.textContent = Math.round(data.fraction * 100) + '% ' + data.message;
+            break;
+        case 'result':
+            status.className = '';
+            status.textContent = 'finished';
+            result.replaceChildren(...outputElements(data));
+            break;
+        case 'error':
+            status.className = 'error';
+            status.textContent = 'failed';
+            result.replaceChildren(...errorElements(data));
+            break;
+    }
+}
+
+function outputElements(output) {
+    switch (output.type) {
+        case 'text':
+            return [element('pre', {}, output.text)];
+        case 'table':
+            return [element('table', {},
+                element('tr', {}, ...output.columns.map(c => element('th', {}, c))),
+                ...output.rows.map(row => element('tr', {},
+                    ...row.map(cell => element('td', {}, typeof cell === 'string' ? cell : JSON.stringify(cell))))))];
+        case 'response':
+            return [
+                element('p', {}, 'HTTP ' + output.status),
+                element('pre', { className: 'muted' }, output.headers.map(([name, value]) => name + ': ' + value).join('\n')),
+                element('pre', {}, output.body),
+            ];
+        default:
+            return [element('p', { className: 'muted' }, 'no result')];
+    }
+}
+
+function errorElements(error) {
+    return [
+        element('p', { className: 'error' }, error.message),
+        error.traceback ? element('pre', { className: 'error' }, error.traceback) : null,
+    ].filter(e => e);
+}
+
+// history
+
+async function loadRuns() {
+    const runs = await (await api('/api/runs')).json();
+    document.getElementById('run-list').replaceChildren(...runs.map(run => {
+        const started = new Date(run.started * 1000).toLocaleString();
+        const item = element('li', {}, run.script_name, ' ',
+            element('span', { className: run.state === 'failed' ? 'error' : run.state }, run.state),
+            element('br'), element('small', {}, '#' + run.id + ' · ' + started));
+        item.classList.toggle('selected', run.id === selectedRun);
+        item.onclick = () => {
+            selectedRun = run.id;
+            loadRuns().catch(console.error);
+        };
+        return item;
+    }));
+
+    const detail = document.getElementById('run-detail');
+    if (selectedRun === null) {
+        detail.replaceChildren();
+        return;
+    }
+    const run = await (await api('/api/runs/' + selectedRun)).json();
+    const log = element('pre', {}, ...run.events
+        .filter(e => e.type !== 'progress')
+        .map(e => element('span', { className: e.type }, e.text + '\n')));
+    detail.replaceChildren(
+        element('h2', {}, run.script_name),
+        element('p', { className: 'muted' }, 'run #' + run.id + ', ' + run.state),
+        log,
+        ...(run.result ? outputElements(run.result) : []),
+        ...(run.error ? errorElements(run.error) : []));
+}
+
+// diagnostics
+
+async function loadDiagnostics() {
+    const diagnostics = await (await api('/api/diagnostics')).json();
+    document.getElementById('diagnostic-list').replaceChildren(...diagnostics.map(d => element('li', {},
+        element('strong', {}, d.filename), ': ', d.message,
+        d.traceback ? element('pre', { className: 'error' }, d.traceback) : null)));
+    if (!diagnostics.length) {
+        document.getElementById('diagnostic-list').append(element('li', { className: 'muted' }, 'all scripts loaded'));
+    }
+}
+
+async function refreshDiagnosticCount() {
+    const diagnostics = await (await api('/api/diagnostics')).json();
+    document.getElementById('diagnostic-count').textContent = diagnostics.length ? '(' + diagnostics.length + ')' : '';
+}
+
+document.getElementById('filter').oninput = renderScripts;
+document.getElementById('login-form').onsubmit = event => {
+    event.preventDefault();
+    localStorage.setItem('flaunch-token', document.getElementById('token').value.trim());
+    route();
+};
+window.onhashchange = route;
+route();
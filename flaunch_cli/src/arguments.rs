@@ -0,0 +1,136 @@
+use std::any::Any;
+
+use flaunch_core::script_engine::{ArgumentType, Script};
+
+/// A command line value converted to the type of its argument.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Boolean(bool),
+    Int(i32),
+    Uint(u32),
+    Float(f32),
+    String(String),
+    List(Vec<String>),
+}
+
+impl Value {
+    /// the form `ScriptEngine::call` takes.
+    pub fn into_any(self) -> Box<dyn Any + Send> {
+        match self {
+            Value::Boolean(b) => Box::new(b),
+            Value::Int(i) => Box::new(i),
+            Value::Uint(u) => Box::new(u),
+            Value::Float(f) => Box::new(f),
+            Value::String(s) => Box::new(s),
+            Value::List(l) => Box::new(l),
+        }
+    }
+}
+
+/// Converts the command line values to the types the script asks for.
+pub fn parse(script: &Script, values: &[String]) -> Result<Vec<Value>, String> {
+    if values.len() > script.arguments.len() {
+        return Err(format!(
+            "{} takes {} arguments, got {}",
+            script.name,
+            script.arguments.len(),
+            values.len()
+        ));
+    }
+    script
+        .arguments
+        .iter()
+        .zip(values)
+        .map(|((name, argument_type, _), value)| {
+            parse_value(value, argument_type).ok_or_else(|| {
+                format!(
+                    "argument {} should be of type {}, got {:?}",
+                    name,
+                    type_name(argument_type),
+                    value
+                )
+            })
+        })
+        .collect()
+}
+
+fn parse_value(value: &str, argument_type: &ArgumentType) -> Option<Value> {
+    Some(match argument_type {
+        ArgumentType::Boolean(_) => Value::Boolean(parse_bool(value)?),
+        ArgumentType::Int(_) => Value::Int(value.parse().ok()?),
+        ArgumentType::Uint(_) => Value::Uint(value.parse().ok()?),
+        ArgumentType::Float(_) => Value::Float(value.parse().ok()?),
+        ArgumentType::String(_) => Value::String(value.to_string()),
+        ArgumentType::List(_) => Value::List(parse_list(value)),
+        ArgumentType::NotSpecified => {
+            if let Some(b) = parse_bool(value) {
+                Value::Boolean(b)
+            } else if let Ok(i) = value.parse() {
+                Value::Int(i)
+            } else if let Ok(f) = value.parse() {
+                Value::Float(f)
+            } else {
+                Value::String(value.to_string())
+            }
+        }
+    })
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "true" | "yes" | "on" => Some(true),
+        "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// lists are given comma separated, like the defaults of templates.
+fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// short type names for the listing and usage errors.
+pub fn type_name(argument_type: &ArgumentType) -> &'static str {
+    match argument_type {
+        ArgumentType::Boolean(_) => "bool",
+        ArgumentType::Int(_) => "int",
+        ArgumentType::Uint(_) => "uint",
+        ArgumentType::Float(_) => "float",
+        ArgumentType::String(_) => "string",
+        ArgumentType::List(_) => "list",
+        ArgumentType::NotSpecified => "any",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flaunch_core::script_engine::InterpreterType;
+
+    #[test]
+    fn arguments_get_the_type_of_the_script() {
+        let mut script = Script::new("greet".to_string(), InterpreterType::Template);
+        script.arguments = vec![
+            ("times".to_string(), ArgumentType::Uint(1), String::new()),
+            (
+                "who".to_string(),
+                ArgumentType::List(String::new()),
+                String::new(),
+            ),
+        ];
+
+        assert_eq!(
+            parse(&script, &["3".to_string(), "a,b".to_string()]),
+            Ok(vec![
+                Value::Uint(3),
+                Value::List(vec!["a".to_string(), "b".to_string()])
+            ])
+        );
+        assert!(parse(&script, &["-3".to_string()]).is_err());
+        assert!(parse(&script, &vec!["1".to_string(); 3]).is_err());
+    }
+}
@@ -0,0 +1,92 @@
+use std::{path::PathBuf, process::ExitCode};
+
+use flaunch_core::{
+    load_settings,
+    script_engine::{ParseError, ScriptEngine},
+    SettingKey,
+};
+use tokio::sync::mpsc;
+
+use crate::{arguments, find_script, print_diagnostics, print_event, print_output, Command};
+
+/// Runs the command on a script engine of its own, for when flaunchd isn't
+/// running.
+pub async fn execute(scripts_dir: Option<PathBuf>, command: Command) -> ExitCode {
+    let scripts_dir = match scripts_dir {
+        Some(dir) => dir,
+        None => load_settings()
+            .get_str(SettingKey::ScriptsDir)
+            .unwrap_or_default()
+            .into(),
+    };
+    let engine = ScriptEngine::default();
+    let diagnostics = match engine.load(&scripts_dir).await {
+        Ok(diagnostics) => diagnostics,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match command {
+        Command::List => {
+            report(&diagnostics);
+            crate::list(engine.scripts().await);
+            ExitCode::SUCCESS
+        }
+        Command::Run { name, arguments } => {
+            report(&diagnostics);
+            run(&engine, &name, &arguments).await
+        }
+        Command::Logs { follow: true } => crate::usage_error("--follow needs a running flaunchd"),
+        Command::Logs { follow: false } => {
+            print_diagnostics(&diagnostics);
+            ExitCode::SUCCESS
+        }
+    }
+}
+
+/// scripts that failed to load are mentioned, but don't stop the command.
+fn report(diagnostics: &[ParseError]) {
+    for diagnostic in diagnostics {
+        eprintln!("{}: {}", diagnostic.filename, diagnostic.message);
+    }
+}
+
+async fn run(engine: &ScriptEngine, name: &str, values: &[String]) -> ExitCode {
+    let scripts = engine.scripts().await;
+    let script = match find_script(&scripts, name) {
+        Ok(script) => script,
+        Err(code) => return code,
+    };
+    let arguments = match arguments::parse(script, values) {
+        Ok(arguments) => arguments
+            .into_iter()
+            .map(arguments::Value::into_any)
+            .collect(),
+        Err(e) => return crate::usage_error(&e),
+    };
+
+    let (events, mut receiver) = mpsc::unbounded_channel();
+    let printer = tokio::spawn(async move {
+        while let Some(event) = receiver.recv().await {
+            print_event(event);
+        }
+    });
+    let result = engine
+        .call(script.get_key().unwrap_or_default(), arguments, events)
+        .await;
+    // the sender is dropped with the call, all events are printed after this
+    let _ = printer.await;
+
+    match result {
+        Ok(output) => {
+            print_output(output);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
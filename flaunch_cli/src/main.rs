@@ -1,73 +1,142 @@
-// extern crate clap;
+mod arguments;
+mod local;
+mod remote;
 
-// use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
-// use flaunch_core::logging::*;
-// use flaunch_core::script_engine::ScriptEngine;
-// use flaunch_core::*;
+use std::{path::PathBuf, process::ExitCode};
 
-// fn main() {
-//     let modules = load_flaunch_core();
-//     let script_engine = modules.0;
-//     let settings = modules.1;
+use clap::{Parser, Subcommand};
+use flaunch_client::Client;
+use flaunch_core::{
+    load_logging,
+    logging::info,
+    script_engine::{CallEvent, CallOutput, ParseError, Script},
+};
 
-//     let scripts_path = settings.get_str(SettingKey::ScriptsDir).unwrap();
-//     let matches = get_app_cli(scripts_path);
-//     match matches.subcommand_name().unwrap() {
-//         "list" => list_subcommand(script_engine.context),
-//         "run" => run_subcommand(&matches, script_engine),
-//         _ => {}
-//     }
-// }
+/// Lists and runs the scripts in the scripts dir, through flaunchd when it
+/// is running.
+#[derive(Parser, Debug)]
+#[command(name = "flaunch", version)]
+struct Args {
+    /// don't use flaunchd, load and run the scripts in this process.
+    #[arg(long)]
+    local: bool,
+    /// load scripts from this dir instead of the scripts_dir setting,
+    /// implies --local.
+    #[arg(long)]
+    scripts_dir: Option<PathBuf>,
+    /// log what the script engine does.
+    #[arg(short, long)]
+    verbose: bool,
+    #[command(subcommand)]
+    command: Command,
+}
 
-// fn get_app_cli(scripts_path: &str) -> ArgMatches {
-//     App::new(app_meta::APP_INFO.name)
-//         .version(app_meta::VERSION)
-//         .author(app_meta::APP_INFO.author)
-//         .about(&*format!("run any script def in {}", scripts_path))
-//         .arg(
-//             Arg::with_name("config")
-//                 .short("c")
-//                 .long("config")
-//                 .value_name("FILE")
-//                 .help("Sets a custom config file")
-//                 .takes_value(true),
-//         )
-//         .subcommand(
-//             SubCommand::with_name("run")
-//                 .about("run an arbitrary script")
-//                 .arg(
-//                     Arg::with_name("Script Name")
-//                         .help("The configuration file to use")
-//                         .index(1)
-//                         .required(true),
-//                 ),
-//         )
-//         .subcommand(SubCommand::with_name("list").about("list all available scripts"))
-//         .setting(AppSettings::SubcommandRequired)
-//         .get_matches()
-// }
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// list all available scripts with their arguments.
+    List,
+    /// run a script, arguments are given in the order the script lists them.
+    Run {
+        name: String,
+        #[arg(allow_hyphen_values = true)]
+        arguments: Vec<String>,
+    },
+    /// show why scripts failed to load.
+    Logs {
+        /// keep watching, print again whenever the scripts change.
+        #[arg(short, long)]
+        follow: bool,
+    },
+}
 
-// fn list_subcommand(script_store: ScriptStore) {
-//     for name in script_store.names {
-//         println!("\t{}\t\t--\t{}", name.1, script_store.description[name.0]);
-//     }
-// }
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+    if args.verbose {
+        load_logging();
+    }
 
-// fn run_subcommand(matches: &ArgMatches, script_engine: ScriptEngine) {
-//     let script_name = matches.value_of("Script Name").unwrap();
+    if !args.local && args.scripts_dir.is_none() {
+        match Client::connect().await {
+            Ok(client) => return remote::execute(client, args.command).await,
+            Err(e) => info!("{}, running scripts in-process", e),
+        }
+    }
+    local::execute(args.scripts_dir, args.command).await
+}
 
-//     let arguments: Vec<Box<dyn std::any::Any>> = vec![Box::new("BAMI".to_string())];
-//     if let Some(key) = script_engine.find(script_name) {
-//         if script_engine.call(key, &arguments).unwrap() {
-//             info!("Called {} successfully!", script_name);
-//         }
-//     } else {
-//         println!("Script {} does not exist", script_name);
-//         println!("Available scripts: ");
-//         for name in script_engine.context.names {
-//             println!("{:?}", name.1);
-//         }
-//     }
-// }
+pub fn list(mut scripts: Vec<Script>) {
+    scripts.sort_by(|a, b| a.name.cmp(&b.name));
+    for script in &scripts {
+        println!("{}\t{}", script.name, script.description);
+        for (name, argument_type, description) in &script.arguments {
+            println!(
+                "    {}\t{}\t{}",
+                name,
+                arguments::type_name(argument_type),
+                description
+            );
+        }
+    }
+}
 
-fn main() {}
+pub fn find_script<'a>(scripts: &'a [Script], name: &str) -> Result<&'a Script, ExitCode> {
+    scripts.iter().find(|s| s.name == name).ok_or_else(|| {
+        usage_error(&format!(
+            "script {} does not exist, see `flaunch list`",
+            name
+        ))
+    })
+}
+
+/// exit code 2 tells mistakes on the command line from failing scripts.
+pub fn usage_error(message: &str) -> ExitCode {
+    eprintln!("{}", message);
+    ExitCode::from(2)
+}
+
+pub fn print_diagnostics(diagnostics: &[ParseError]) {
+    if diagnostics.is_empty() {
+        println!("all scripts loaded");
+    }
+    for diagnostic in diagnostics {
+        println!("{}: {}", diagnostic.filename, diagnostic.message);
+        for line in diagnostic.traceback.lines() {
+            println!("    {}", line);
+        }
+    }
+}
+
+pub fn print_event(event: CallEvent) {
+    match event {
+        CallEvent::Output(text) => println!("{}", text),
+        CallEvent::Error(text) => eprintln!("{}", text),
+        CallEvent::Progress(fraction, message) => {
+            eprintln!("[{:3.0}%] {}", fraction * 100.0, message)
+        }
+    }
+}
+
+pub fn print_output(output: CallOutput) {
+    match output {
+        CallOutput::Nothing => {}
+        CallOutput::Text(text) => println!("{}", text),
+        CallOutput::Table(table) => {
+            println!("{}", table.columns.join("\t"));
+            for row in table.rows {
+                let cells: Vec<String> = row
+                    .iter()
+                    .map(|cell| match cell.as_str() {
+                        Some(s) => s.to_string(),
+                        None => cell.dump(),
+                    })
+                    .collect();
+                println!("{}", cells.join("\t"));
+            }
+        }
+        CallOutput::Response(response) => {
+            println!("{}", response.status);
+            println!("{}", response.body);
+        }
+    }
+}
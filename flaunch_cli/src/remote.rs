@@ -0,0 +1,89 @@
+use std::process::ExitCode;
+
+use flaunch_client::{proto, Client, Error, RunEvent};
+use flaunch_core::script_engine::ParseError;
+use tokio_stream::StreamExt;
+
+use crate::{
+    arguments::{self, Value},
+    find_script, print_diagnostics, print_event, print_output, Command,
+};
+
+/// Runs the command on flaunchd, which has the scripts loaded already.
+pub async fn execute(mut client: Client, command: Command) -> ExitCode {
+    let result = match command {
+        Command::List => list(&mut client).await,
+        Command::Run { name, arguments } => run(&mut client, &name, &arguments).await,
+        Command::Logs { follow } => logs(&mut client, follow).await,
+    };
+    result.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        ExitCode::FAILURE
+    })
+}
+
+async fn list(client: &mut Client) -> Result<ExitCode, Error> {
+    crate::list(client.scripts().await?);
+    Ok(ExitCode::SUCCESS)
+}
+
+async fn run(client: &mut Client, name: &str, values: &[String]) -> Result<ExitCode, Error> {
+    let scripts = client.scripts().await?;
+    let script = match find_script(&scripts, name) {
+        Ok(script) => script,
+        Err(code) => return Ok(code),
+    };
+    let arguments = match arguments::parse(script, values) {
+        Ok(arguments) => arguments.into_iter().map(argument_value).collect(),
+        Err(e) => return Ok(crate::usage_error(&e)),
+    };
+
+    let mut events = client
+        .run(script.get_key().unwrap_or_default(), arguments)
+        .await?;
+    while let Some(event) = events.next().await {
+        match event? {
+            RunEvent::Accepted(_) => {}
+            RunEvent::Event(event) => print_event(event),
+            RunEvent::Finished(Ok(output)) => {
+                print_output(output);
+                return Ok(ExitCode::SUCCESS);
+            }
+            RunEvent::Finished(Err(e)) => {
+                eprintln!("{}", e);
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+    }
+    Err(Error::InvalidResponse(
+        "the run ended without a result".to_string(),
+    ))
+}
+
+/// Prints why scripts failed to load, with `follow` again on every change.
+async fn logs(client: &mut Client, follow: bool) -> Result<ExitCode, Error> {
+    if !follow {
+        print_diagnostics(&client.diagnostics().await?);
+        return Ok(ExitCode::SUCCESS);
+    }
+    let mut updates = client
+        .script_engine()
+        .watch_diagnostics(())
+        .await?
+        .into_inner();
+    while let Some(diagnostics) = updates.next().await {
+        print_diagnostics(&Vec::<ParseError>::from(diagnostics?));
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn argument_value(value: Value) -> proto::ArgumentValue {
+    match value {
+        Value::Boolean(b) => b.into(),
+        Value::Int(i) => i.into(),
+        Value::Uint(u) => u.into(),
+        Value::Float(f) => f.into(),
+        Value::String(s) => s.into(),
+        Value::List(l) => l.into(),
+    }
+}
@@ -0,0 +1,12 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tonic_prost_build::configure()
+        .build_server(false)
+        .compile_protos(
+            &[
+                "../daemon/proto/flaunch.proto",
+                "../daemon/proto/flaunch_v2.proto",
+            ],
+            &["../daemon/proto"],
+        )?;
+    Ok(())
+}
@@ -0,0 +1,248 @@
+use flaunch_core::script_engine::{
+    ArgumentConstraint, ArgumentType, CallError, CallEvent, CallOutput, InterpreterType,
+    ParseError, Response, Script, Table,
+};
+
+use crate::{parse_value, proto, Error};
+
+/// What the daemon reports while running a script.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RunEvent {
+    /// the id of the run, see the runs of the json api.
+    Accepted(u64),
+    Event(CallEvent),
+    Finished(Result<CallOutput, CallError>),
+}
+
+impl TryFrom<proto::RunEvent> for RunEvent {
+    type Error = Error;
+
+    fn try_from(event: proto::RunEvent) -> Result<Self, Error> {
+        use proto::run_event::Event;
+        Ok(match event.event {
+            Some(Event::Accepted(accepted)) => RunEvent::Accepted(accepted.run_id),
+            Some(Event::Stdout(text)) => RunEvent::Event(CallEvent::Output(text)),
+            Some(Event::Stderr(text)) => RunEvent::Event(CallEvent::Error(text)),
+            Some(Event::Progress(progress)) => {
+                RunEvent::Event(CallEvent::Progress(progress.fraction, progress.message))
+            }
+            Some(Event::Result(result)) => RunEvent::Finished(Ok(result.try_into()?)),
+            Some(Event::Error(error)) => RunEvent::Finished(Err(CallError::Exception {
+                message: error.message,
+                traceback: error.traceback,
+            })),
+            None => return Err(Error::InvalidResponse("empty run event".to_string())),
+        })
+    }
+}
+
+impl TryFrom<proto::RunResult> for CallOutput {
+    type Error = Error;
+
+    fn try_from(result: proto::RunResult) -> Result<Self, Error> {
+        use proto::run_result::Output;
+        Ok(match result.output {
+            None | Some(Output::Nothing(())) => CallOutput::Nothing,
+            Some(Output::Text(text)) => CallOutput::Text(text),
+            Some(Output::Table(table)) => CallOutput::Table(Table {
+                columns: table.columns,
+                rows: table
+                    .rows
+                    .iter()
+                    .map(|row| row.cells.iter().map(|cell| parse_value(cell)).collect())
+                    .collect::<Result<_, _>>()?,
+            }),
+            Some(Output::Response(response)) => CallOutput::Response(Response {
+                status: response.status as u16,
+                headers: response
+                    .headers
+                    .into_iter()
+                    .map(|header| (header.name, header.value))
+                    .collect(),
+                body: response.body,
+            }),
+        })
+    }
+}
+
+impl TryFrom<proto::v2::Script> for Script {
+    type Error = Error;
+
+    fn try_from(s: proto::v2::Script) -> Result<Self, Error> {
+        let interpreter_type = InterpreterType::from_name(&s.interpreter).ok_or_else(|| {
+            Error::InvalidResponse(format!("unknown interpreter {:?}", s.interpreter))
+        })?;
+        let mut script = Script::new(s.name, interpreter_type);
+        script.description = s.description;
+        script.file = s.file.into();
+        script.tags = s.tags;
+        for argument in s.arguments {
+            let argument_type = argument_type(
+                argument.argument_type(),
+                argument.default_value.unwrap_or_default(),
+            );
+            let constraint = ArgumentConstraint {
+                choices: argument.choices,
+                minimum: argument.minimum,
+                maximum: argument.maximum,
+            };
+            if constraint != ArgumentConstraint::default() {
+                script.constraints.insert(argument.name.clone(), constraint);
+            }
+            script
+                .arguments
+                .push((argument.name, argument_type, argument.description));
+        }
+        Ok(script)
+    }
+}
+
+/// The core keeps the default of an argument in its type.
+fn argument_type(argument_type: proto::ArgumentType, default: String) -> ArgumentType {
+    match argument_type {
+        proto::ArgumentType::Boolean => ArgumentType::Boolean(default),
+        proto::ArgumentType::Integer => ArgumentType::Int(default.parse().unwrap_or_default()),
+        proto::ArgumentType::Uinteger => ArgumentType::Uint(default.parse().unwrap_or_default()),
+        proto::ArgumentType::Float => ArgumentType::Float(default.parse().unwrap_or_default()),
+        proto::ArgumentType::String => ArgumentType::String(default),
+        proto::ArgumentType::List => ArgumentType::List(default),
+        proto::ArgumentType::Notspecified => ArgumentType::NotSpecified,
+    }
+}
+
+impl From<proto::Diagnostics> for Vec<ParseError> {
+    fn from(diagnostics: proto::Diagnostics) -> Self {
+        diagnostics
+            .diagnostics
+            .into_iter()
+            .map(|d| ParseError {
+                filename: d.filename,
+                message: d.message,
+                traceback: d.traceback,
+            })
+            .collect()
+    }
+}
+
+fn value(value: proto::argument_value::Value) -> proto::ArgumentValue {
+    proto::ArgumentValue { value: Some(value) }
+}
+
+impl From<bool> for proto::ArgumentValue {
+    fn from(b: bool) -> Self {
+        value(proto::argument_value::Value::Boolean(b))
+    }
+}
+
+impl From<i32> for proto::ArgumentValue {
+    fn from(i: i32) -> Self {
+        value(proto::argument_value::Value::Integer(i))
+    }
+}
+
+impl From<u32> for proto::ArgumentValue {
+    fn from(u: u32) -> Self {
+        value(proto::argument_value::Value::Uinteger(u))
+    }
+}
+
+impl From<f32> for proto::ArgumentValue {
+    fn from(f: f32) -> Self {
+        value(proto::argument_value::Value::Float(f))
+    }
+}
+
+impl From<String> for proto::ArgumentValue {
+    fn from(s: String) -> Self {
+        value(proto::argument_value::Value::String(s))
+    }
+}
+
+impl From<&str> for proto::ArgumentValue {
+    fn from(s: &str) -> Self {
+        s.to_string().into()
+    }
+}
+
+impl From<Vec<String>> for proto::ArgumentValue {
+    fn from(values: Vec<String>) -> Self {
+        value(proto::argument_value::Value::List(proto::StringList {
+            values,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn script_keeps_its_key() {
+        let mut expected = Script::new("greet".to_string(), InterpreterType::Template);
+        expected.file = "/scripts/greet.tera".into();
+        expected.arguments = vec![
+            (
+                "who".to_string(),
+                ArgumentType::String(String::new()),
+                "whom to greet".to_string(),
+            ),
+            ("times".to_string(), ArgumentType::Uint(2), String::new()),
+        ];
+        expected.constraints.insert(
+            "times".to_string(),
+            ArgumentConstraint {
+                choices: Vec::new(),
+                minimum: Some(1.0),
+                maximum: Some(3.0),
+            },
+        );
+
+        let script = Script::try_from(proto::v2::Script {
+            id: expected.get_key().unwrap().to_string(),
+            name: "greet".to_string(),
+            file: "/scripts/greet.tera".to_string(),
+            interpreter: "template".to_string(),
+            arguments: vec![
+                proto::v2::ScriptArgument {
+                    name: "who".to_string(),
+                    argument_type: proto::ArgumentType::String as i32,
+                    description: "whom to greet".to_string(),
+                    required: true,
+                    ..Default::default()
+                },
+                proto::v2::ScriptArgument {
+                    name: "times".to_string(),
+                    argument_type: proto::ArgumentType::Uinteger as i32,
+                    default_value: Some("2".to_string()),
+                    minimum: Some(1.0),
+                    maximum: Some(3.0),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(script, expected);
+        assert_eq!(script.get_key(), expected.get_key());
+    }
+
+    #[test]
+    fn table_cells_are_json() {
+        let result = proto::RunResult {
+            output: Some(proto::run_result::Output::Table(proto::Table {
+                columns: vec!["name".to_string(), "count".to_string()],
+                rows: vec![proto::Row {
+                    cells: vec!["\"a\"".to_string(), "3".to_string()],
+                }],
+            })),
+        };
+        let output = CallOutput::try_from(result).unwrap();
+        assert_eq!(
+            output,
+            CallOutput::Table(Table {
+                columns: vec!["name".to_string(), "count".to_string()],
+                rows: vec![vec!["a".into(), 3.into()]],
+            })
+        );
+    }
+}
@@ -0,0 +1,295 @@
+//! Client for the gRPC api of flaunchd, see `daemon/proto`.
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), flaunch_client::Error> {
+//! let mut client = flaunch_client::Client::connect().await?;
+//! for script in client.scripts().await? {
+//!     println!("{} {}", script.name, script.description);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+mod convert;
+
+use std::{io, path::PathBuf};
+
+use flaunch_core::{
+    script_engine::{ParseError, Script},
+    settings::{auth_token_file, runtime_file, JsonValue},
+};
+use tokio_stream::{Stream, StreamExt};
+use tonic::{
+    metadata::{Ascii, MetadataValue},
+    service::{interceptor::InterceptedService, Interceptor},
+    transport::{Channel, ClientTlsConfig, Endpoint},
+};
+
+pub use convert::RunEvent;
+
+/// The types generated from the flaunchd protos.
+pub mod proto {
+    tonic::include_proto!("flaunch");
+
+    pub mod v2 {
+        tonic::include_proto!("flaunch.v2");
+    }
+}
+
+/// Where a running flaunchd can be reached, as written to its runtime file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DaemonInfo {
+    pub pid: u32,
+    pub version: String,
+    pub tcp: Option<String>,
+    pub tls: bool,
+    pub unix: Option<PathBuf>,
+}
+
+impl DaemonInfo {
+    /// Reads the runtime file of flaunchd, `None` when it isn't running.
+    pub fn discover() -> Option<DaemonInfo> {
+        DaemonInfo::parse(&std::fs::read_to_string(runtime_file()).ok()?)
+    }
+
+    pub fn parse(contents: &str) -> Option<DaemonInfo> {
+        let info = json::parse(contents).ok()?;
+        Some(DaemonInfo {
+            pid: info["pid"].as_u32()?,
+            version: info["version"].as_str().unwrap_or_default().to_string(),
+            tcp: info["tcp"].as_str().map(str::to_string),
+            tls: info["tls"].as_bool().unwrap_or_default(),
+            unix: info["unix"].as_str().map(PathBuf::from),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// there's no runtime file, or it doesn't say where to connect.
+    NotRunning,
+    Io(io::Error),
+    Transport(tonic::transport::Error),
+    Status(tonic::Status),
+    /// the daemon sent something this client doesn't understand.
+    InvalidResponse(String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::NotRunning => write!(f, "flaunchd is not running"),
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Transport(e) => write!(f, "cannot connect to flaunchd: {}", e),
+            Error::Status(status) => write!(f, "{}", status.message()),
+            Error::InvalidResponse(message) => write!(f, "invalid response: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<tonic::transport::Error> for Error {
+    fn from(e: tonic::transport::Error) -> Self {
+        Error::Transport(e)
+    }
+}
+
+impl From<tonic::Status> for Error {
+    fn from(status: tonic::Status) -> Self {
+        Error::Status(status)
+    }
+}
+
+/// Adds the token to every request, tcp connections need one.
+#[derive(Debug, Clone)]
+pub struct Auth(Option<MetadataValue<Ascii>>);
+
+impl Interceptor for Auth {
+    fn call(
+        &mut self,
+        mut request: tonic::Request<()>,
+    ) -> Result<tonic::Request<()>, tonic::Status> {
+        if let Some(authorization) = &self.0 {
+            request
+                .metadata_mut()
+                .insert("authorization", authorization.clone());
+        }
+        Ok(request)
+    }
+}
+
+type AuthChannel = InterceptedService<Channel, Auth>;
+
+/// Talks to flaunchd, converting from and to the `flaunch_core` types.
+/// Cloning is cheap, clones share the connection.
+#[derive(Debug, Clone)]
+pub struct Client {
+    script_engine: proto::v2::script_engine_client::ScriptEngineClient<AuthChannel>,
+    settings: proto::settings_client::SettingsClient<AuthChannel>,
+    daemon: proto::daemon_client::DaemonClient<AuthChannel>,
+}
+
+impl Client {
+    /// Connects to the running daemon, on its unix socket when it serves
+    /// one, otherwise over tcp with the token next to the settings.
+    pub async fn connect() -> Result<Client, Error> {
+        Client::connect_to(&DaemonInfo::discover().ok_or(Error::NotRunning)?).await
+    }
+
+    pub async fn connect_to(info: &DaemonInfo) -> Result<Client, Error> {
+        #[cfg(unix)]
+        if let Some(path) = &info.unix {
+            return Client::connect_unix(path.clone()).await;
+        }
+        match &info.tcp {
+            Some(addr) => {
+                let token = std::fs::read_to_string(auth_token_file())?;
+                Client::connect_tcp(addr, info.tls, token.trim()).await
+            }
+            None => Err(Error::NotRunning),
+        }
+    }
+
+    /// The daemon identifies clients on its socket by their user, no token
+    /// needed.
+    #[cfg(unix)]
+    pub async fn connect_unix(path: PathBuf) -> Result<Client, Error> {
+        // the uri is only used for the requests, the connector dials the socket
+        let channel = Endpoint::from_static("http://localhost")
+            .connect_with_connector(tower::service_fn(move |_: tonic::transport::Uri| {
+                let path = path.clone();
+                async move {
+                    Ok::<_, io::Error>(hyper_util::rt::TokioIo::new(
+                        tokio::net::UnixStream::connect(path).await?,
+                    ))
+                }
+            }))
+            .await?;
+        Client::with_channel(channel, None)
+    }
+
+    /// Connects to `addr`, like `[::1]:50051`, checking a tls certificate
+    /// against the roots of the system.
+    pub async fn connect_tcp(addr: &str, tls: bool, token: &str) -> Result<Client, Error> {
+        let scheme = if tls { "https" } else { "http" };
+        let mut endpoint = Endpoint::from_shared(format!("{}://{}", scheme, addr))?;
+        if tls {
+            endpoint = endpoint.tls_config(ClientTlsConfig::new().with_native_roots())?;
+        }
+        Client::with_channel(endpoint.connect().await?, Some(token))
+    }
+
+    /// Uses a channel set up by the caller, e.g. with its own tls config.
+    pub fn with_channel(channel: Channel, token: Option<&str>) -> Result<Client, Error> {
+        let authorization = token
+            .map(|token| format!("Bearer {}", token).parse())
+            .transpose()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid token"))?;
+        let auth = Auth(authorization);
+        Ok(Client {
+            script_engine: proto::v2::script_engine_client::ScriptEngineClient::with_interceptor(
+                channel.clone(),
+                auth.clone(),
+            ),
+            settings: proto::settings_client::SettingsClient::with_interceptor(
+                channel.clone(),
+                auth.clone(),
+            ),
+            daemon: proto::daemon_client::DaemonClient::with_interceptor(channel, auth),
+        })
+    }
+
+    /// The generated client, for calls this one doesn't wrap.
+    pub fn script_engine(
+        &mut self,
+    ) -> &mut proto::v2::script_engine_client::ScriptEngineClient<AuthChannel> {
+        &mut self.script_engine
+    }
+
+    /// Version and capabilities of the daemon, to check before relying on
+    /// a feature.
+    pub async fn info(&mut self) -> Result<proto::Info, Error> {
+        Ok(self.daemon.get_info(()).await?.into_inner())
+    }
+
+    /// The scripts this client may see. Their `get_key` is the id the
+    /// daemon knows them by.
+    pub async fn scripts(&mut self) -> Result<Vec<Script>, Error> {
+        let mut stream = self.script_engine.get_all(()).await?.into_inner();
+        let mut scripts = Vec::new();
+        while let Some(script) = stream.next().await {
+            scripts.push(Script::try_from(script?)?);
+        }
+        Ok(scripts)
+    }
+
+    /// Starts the script with id `script_id`, the stream starts with
+    /// `RunEvent::Accepted` and ends with `RunEvent::Finished`.
+    pub async fn run(
+        &mut self,
+        script_id: u64,
+        arguments: Vec<proto::ArgumentValue>,
+    ) -> Result<impl Stream<Item = Result<RunEvent, Error>>, Error> {
+        let request = proto::v2::RunRequest {
+            script_id: script_id.to_string(),
+            arguments,
+        };
+        let stream = self.script_engine.run(request).await?.into_inner();
+        Ok(stream.map(|event| RunEvent::try_from(event?)))
+    }
+
+    /// Why files in the scripts dir failed to load.
+    pub async fn diagnostics(&mut self) -> Result<Vec<ParseError>, Error> {
+        let diagnostics = self.script_engine.get_diagnostics(()).await?.into_inner();
+        Ok(diagnostics.into())
+    }
+
+    pub async fn settings(&mut self) -> Result<Vec<(String, JsonValue)>, Error> {
+        let settings = self.settings.get_settings(()).await?.into_inner();
+        settings
+            .settings
+            .into_iter()
+            .map(|setting| Ok((setting.key, parse_value(&setting.value)?)))
+            .collect()
+    }
+
+    /// Returns the value as stored, owner only.
+    pub async fn set_setting(&mut self, key: &str, value: &JsonValue) -> Result<JsonValue, Error> {
+        let setting = proto::Setting {
+            key: key.to_string(),
+            value: value.dump(),
+            needs_restart: false,
+        };
+        parse_value(&self.settings.set_setting(setting).await?.into_inner().value)
+    }
+}
+
+fn parse_value(value: &str) -> Result<JsonValue, Error> {
+    json::parse(value).map_err(|e| Error::InvalidResponse(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_runtime_file() {
+        let info = DaemonInfo::parse(
+            r#"{"pid": 42, "version": "0.1.0", "tcp": "[::1]:50051", "tls": false, "unix": "/run/user/1000/flaunch/flaunchd.sock"}"#,
+        )
+        .unwrap();
+        assert_eq!(info.pid, 42);
+        assert_eq!(info.tcp.as_deref(), Some("[::1]:50051"));
+        assert_eq!(
+            info.unix,
+            Some(PathBuf::from("/run/user/1000/flaunch/flaunchd.sock"))
+        );
+        assert_eq!(DaemonInfo::parse("{}"), None);
+    }
+}
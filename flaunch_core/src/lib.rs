@@ -14,6 +14,31 @@ pub enum SettingKey {
     // path to the folder to watch. containing the actual scripts.
     ScriptsDir,
     FolderScan,
+    // local directory with wheels to install script dependencies from.
+    PythonWheelhouse,
+    // package index mirror to install script dependencies from.
+    PythonPackageIndex,
+    // sqlite database used by .sql scripts without a `-- database:` header.
+    SqliteDatabase,
+    // address flaunchd serves on, empty to not listen on tcp.
+    ListenAddress,
+    // serve on a unix socket under $XDG_RUNTIME_DIR as well.
+    UnixSocket,
+    // pem certificate and private key, serves tcp over tls when both are set.
+    TlsCertificate,
+    TlsKey,
+    // serve a json api under /api next to grpc, on the same endpoints.
+    RestApi,
+    // serve the web dashboard, turns on the json api as well.
+    Dashboard,
+}
+
+impl SettingKey {
+    /// settings flaunchd only reads when it starts, changing them takes
+    /// effect once it is restarted.
+    pub fn needs_restart(&self) -> bool {
+        !matches!(self, SettingKey::ScriptsDir | SettingKey::FolderScan)
+    }
 }
 
 pub fn app_setting_defaults() -> Vec<KeyWithDefault<SettingKey>> {
@@ -36,6 +61,52 @@ pub fn app_setting_defaults() -> Vec<KeyWithDefault<SettingKey>> {
         JsonValue::Boolean(true),
     ));
 
+    // no wheelhouse or package index, scripts with dependencies fail to load
+    dict.push((
+        SettingKey::PythonWheelhouse,
+        "python_wheelhouse",
+        JsonValue::String(String::new()),
+    ));
+    dict.push((
+        SettingKey::PythonPackageIndex,
+        "python_package_index",
+        JsonValue::String(String::new()),
+    ));
+
+    // no default database, .sql files need to declare their own
+    dict.push((
+        SettingKey::SqliteDatabase,
+        "sqlite_database",
+        JsonValue::String(String::new()),
+    ));
+
+    // local only, the port can be 0 to let the system pick one
+    dict.push((
+        SettingKey::ListenAddress,
+        "listen_address",
+        JsonValue::String("[::1]:50051".to_string()),
+    ));
+    dict.push((
+        SettingKey::UnixSocket,
+        "unix_socket",
+        JsonValue::Boolean(false),
+    ));
+
+    // no tls, fine as long as flaunchd only listens locally
+    dict.push((
+        SettingKey::TlsCertificate,
+        "tls_certificate",
+        JsonValue::String(String::new()),
+    ));
+    dict.push((
+        SettingKey::TlsKey,
+        "tls_key",
+        JsonValue::String(String::new()),
+    ));
+
+    dict.push((SettingKey::RestApi, "rest_api", JsonValue::Boolean(false)));
+    dict.push((SettingKey::Dashboard, "dashboard", JsonValue::Boolean(false)));
+
     dict
 }
 
@@ -1,4 +1,4 @@
-pub use log::{debug, error, warn};
+pub use log::{debug, error, info, warn};
 use log::{Level, Metadata, Record};
 pub use log::{LevelFilter, SetLoggerError};
 
@@ -19,7 +19,9 @@ impl log::Log for TerminalLogger {
         );
     }
 
-    fn flush(&self) {}
+    fn flush(&self) {
+        let _ = std::io::Write::flush(&mut std::io::stdout());
+    }
 }
 
 static LOGGER: TerminalLogger = TerminalLogger;
@@ -28,6 +30,11 @@ pub fn init_logging(max_log_level: LevelFilter) -> Result<(), SetLoggerError> {
     log::set_logger(&LOGGER).map(|()| log::set_max_level(max_log_level))
 }
 
+/// writes out buffered log lines, e.g. before the process exits.
+pub fn flush_logging() {
+    log::logger().flush();
+}
+
 // #[macro_export]
 // macro_rules! info {
 //     ($msg:literal) => {
@@ -0,0 +1,737 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::time::Duration;
+
+use crate::script_engine::interpreter::*;
+use crate::script_engine::*;
+
+use chrono::{DateTime, Local, Months, TimeDelta, TimeZone, Utc};
+use ureq::http;
+
+const SEPARATOR: &str = "###";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Exposes the requests of `.http` files, in the format used by the REST
+/// clients of VS Code and JetBrains, as scripts.
+///
+/// ```http
+/// @host = http://localhost:8080
+///
+/// ### get_user
+/// # fetches a single user
+/// # @arg id int id of the user
+/// GET {{host}}/users/{{id}}
+/// Accept: application/json
+/// ```
+///
+/// Every `{{variable}}` becomes an argument, `@variable = value` lines
+/// provide defaults and `# @arg name type description` comments type them.
+/// Arguments with a default follow the ones without. The dynamic variables
+/// `$guid`, `$timestamp`, `$datetime`, `$localDatetime`, `$randomInt` and
+/// `$processEnv` are filled in when the request is sent, requests using
+/// other ones are parse errors.
+#[derive(Debug, Default)]
+pub struct HttpInterpreter;
+
+#[derive(Debug, Clone, Default)]
+struct Request {
+    name: String,
+    description: Vec<String>,
+    method: String,
+    url: String,
+    headers: Vec<(String, String)>,
+    body: String,
+    /// type names and descriptions given by `# @arg` comments
+    annotations: HashMap<String, (String, String)>,
+}
+
+impl Request {
+    /// all `{{variable}}` placeholders in order of appearance
+    fn placeholders(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        let parts = std::iter::once(&self.url)
+            .chain(self.headers.iter().map(|(_, v)| v))
+            .chain(std::iter::once(&self.body));
+        for part in parts {
+            expand(part, |name| {
+                // `{{$guid}}` and friends are dynamic variables, not arguments
+                if !name.starts_with('$') && !names.iter().any(|n| n == name) {
+                    names.push(name.to_string());
+                }
+                None
+            });
+        }
+        names
+    }
+
+    /// all `{{$variable}}` dynamic variables, with their options
+    fn dynamic_variables(&self) -> Vec<String> {
+        let mut expressions = Vec::new();
+        let parts = std::iter::once(&self.url)
+            .chain(self.headers.iter().map(|(_, v)| v))
+            .chain(std::iter::once(&self.body));
+        for part in parts {
+            expand(part, |expression| {
+                if expression.starts_with('$') {
+                    expressions.push(expression.to_string());
+                }
+                None
+            });
+        }
+        expressions
+    }
+}
+
+/// Value of a dynamic variable like `$guid` or `$randomInt 1 10`, as the
+/// REST clients define them. Fails for unknown variables or options.
+fn dynamic_variable(
+    expression: &str,
+    variables: &HashMap<String, String>,
+) -> Result<String, String> {
+    let mut parts = expression.split_whitespace();
+    let name = parts.next().unwrap_or_default();
+    let options: Vec<&str> = parts.collect();
+    match (name, options.as_slice()) {
+        ("$guid", []) | ("$uuid", []) => guid(),
+        ("$timestamp", offset) => Ok(shifted(Utc::now(), offset)?.timestamp().to_string()),
+        ("$datetime", [format, offset @ ..]) => {
+            format_datetime(shifted(Utc::now(), offset)?, format)
+        }
+        ("$localDatetime", [format, offset @ ..]) => {
+            format_datetime(shifted(Local::now(), offset)?, format)
+        }
+        ("$randomInt", [min, max]) => {
+            let invalid = |_| format!("invalid {}", expression);
+            let min: i64 = min.parse().map_err(invalid)?;
+            let max: i64 = max.parse().map_err(invalid)?;
+            if min >= max {
+                return Err(format!("empty range in {}", expression));
+            }
+            let mut bytes = [0u8; 8];
+            getrandom::fill(&mut bytes).map_err(|e| e.to_string())?;
+            let range = max.abs_diff(min);
+            let offset = u64::from_le_bytes(bytes) % range;
+            Ok(min.wrapping_add(offset as i64).to_string())
+        }
+        ("$processEnv", [name]) => {
+            // `%name` takes the name of the environment variable from a file variable
+            let name = match name.strip_prefix('%') {
+                Some(variable) => variables.get(variable).cloned().unwrap_or_default(),
+                None => name.to_string(),
+            };
+            Ok(std::env::var(name).unwrap_or_default())
+        }
+        _ => Err(format!("unsupported dynamic variable {}", expression)),
+    }
+}
+
+fn guid() -> Result<String, String> {
+    let mut bytes = [0u8; 16];
+    getrandom::fill(&mut bytes).map_err(|e| e.to_string())?;
+    // version 4, variant 1
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    Ok(format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    ))
+}
+
+/// `time` moved by an `amount unit` offset, like `-1 d`.
+fn shifted<Tz: TimeZone>(time: DateTime<Tz>, offset: &[&str]) -> Result<DateTime<Tz>, String> {
+    let (amount, unit) = match offset {
+        [] => return Ok(time),
+        [amount, unit] => (amount, unit),
+        _ => return Err(format!("invalid offset {}", offset.join(" "))),
+    };
+    let amount: i64 = amount
+        .parse()
+        .map_err(|_| format!("invalid offset {} {}", amount, unit))?;
+    let months = |months: Option<i64>| {
+        let months = months?;
+        let shift = Months::new(u32::try_from(months.unsigned_abs()).ok()?);
+        if months < 0 {
+            time.clone().checked_sub_months(shift)
+        } else {
+            time.clone().checked_add_months(shift)
+        }
+    };
+    let delta = |delta: Option<TimeDelta>| time.clone().checked_add_signed(delta?);
+    let shifted = match *unit {
+        "y" => months(amount.checked_mul(12)),
+        "M" => months(Some(amount)),
+        "w" => delta(TimeDelta::try_weeks(amount)),
+        "d" => delta(TimeDelta::try_days(amount)),
+        "h" => delta(TimeDelta::try_hours(amount)),
+        "m" => delta(TimeDelta::try_minutes(amount)),
+        "s" => delta(TimeDelta::try_seconds(amount)),
+        "ms" => delta(TimeDelta::try_milliseconds(amount)),
+        _ => return Err(format!("unknown offset unit {}", unit)),
+    };
+    shifted.ok_or_else(|| format!("offset {} {} out of range", amount, unit))
+}
+
+fn format_datetime<Tz: TimeZone>(time: DateTime<Tz>, format: &str) -> Result<String, String>
+where
+    Tz::Offset: std::fmt::Display,
+{
+    match format {
+        "rfc1123" => Ok(time.to_rfc2822()),
+        "iso8601" => Ok(time.to_rfc3339()),
+        _ => Err(format!("unsupported datetime format {}", format)),
+    }
+}
+
+/// Replaces every `{{name}}` by `value(name)` in a single pass, so replaced
+/// text isn't expanded again. Placeholders without a value are kept.
+fn expand(text: &str, mut value: impl FnMut(&str) -> Option<String>) -> String {
+    let mut expanded = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let end = match rest[start..].find("}}") {
+            Some(end) => start + end,
+            None => break,
+        };
+        expanded.push_str(&rest[..start]);
+        match value(rest[start + 2..end].trim()) {
+            Some(value) => expanded.push_str(&value),
+            None => expanded.push_str(&rest[start..end + 2]),
+        }
+        rest = &rest[end + 2..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// Expands the references between file variables, like `@base = {{host}}/api`.
+/// References that form a cycle are left as they are.
+fn resolve_variables(variables: &mut HashMap<String, String>) {
+    for _ in 0..variables.len() {
+        let previous = variables.clone();
+        for value in variables.values_mut() {
+            *value = expand(value, |name| previous.get(name).cloned());
+        }
+        if *variables == previous {
+            break;
+        }
+    }
+}
+
+fn argument_type(name: &str, default: String) -> ArgumentType {
+    match name {
+        "int" => ArgumentType::Int(default.parse().unwrap_or_default()),
+        "uint" => ArgumentType::Uint(default.parse().unwrap_or_default()),
+        "float" => ArgumentType::Float(default.parse().unwrap_or_default()),
+        "bool" => ArgumentType::Boolean(default),
+        "list" => ArgumentType::List(default),
+        _ => ArgumentType::String(default),
+    }
+}
+
+fn comment(line: &str) -> Option<&str> {
+    line.strip_prefix('#')
+        .or_else(|| line.strip_prefix("//"))
+        .map(str::trim)
+}
+
+/// Splits a file in its file variables and requests.
+fn parse_requests(source: &str) -> (HashMap<String, String>, Vec<Request>) {
+    let mut variables = HashMap::new();
+    let mut requests = vec![Request::default()];
+    let mut in_headers = false;
+    let mut arg_types: Vec<(String, String, String)> = Vec::new();
+
+    for line in source.lines() {
+        let trimmed = line.trim();
+        let request = requests.last_mut().unwrap();
+
+        if let Some(name) = trimmed.strip_prefix(SEPARATOR) {
+            requests.push(Request {
+                name: name.trim().to_string(),
+                ..Default::default()
+            });
+            in_headers = false;
+            continue;
+        }
+
+        if request.method.is_empty() {
+            if let Some(comment) = comment(trimmed) {
+                if let Some(name) = comment.strip_prefix("@name") {
+                    request.name = name.trim().to_string();
+                } else if let Some(arg) = comment.strip_prefix("@arg") {
+                    let mut parts = arg.trim().splitn(3, char::is_whitespace);
+                    let name = parts.next().unwrap_or_default().to_string();
+                    let typ = parts.next().unwrap_or_default().to_string();
+                    let description = parts.next().unwrap_or_default().trim().to_string();
+                    arg_types.push((name, typ, description));
+                } else if !comment.is_empty() {
+                    request.description.push(comment.to_string());
+                }
+            } else if let Some(variable) = trimmed.strip_prefix('@') {
+                if let Some((name, value)) = variable.split_once('=') {
+                    variables.insert(name.trim().to_string(), value.trim().to_string());
+                }
+            } else if !trimmed.is_empty() {
+                // the url runs up to the http version, `{{$randomInt 1 9}}` has spaces
+                let line = match trimmed.rsplit_once(char::is_whitespace) {
+                    Some((line, version)) if version.starts_with("HTTP/") => line.trim_end(),
+                    _ => trimmed,
+                };
+                let (first, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+                if first.contains("://") || first.starts_with("{{") {
+                    request.method = "GET".to_string();
+                    request.url = line.to_string();
+                } else {
+                    request.method = first.to_uppercase();
+                    request.url = rest.trim().to_string();
+                }
+                for (name, typ, description) in arg_types.drain(..) {
+                    request.annotations.insert(name, (typ, description));
+                }
+                in_headers = true;
+            }
+        } else if in_headers {
+            match trimmed.split_once(':') {
+                Some((name, value)) if !trimmed.is_empty() => request
+                    .headers
+                    .push((name.trim().to_string(), value.trim().to_string())),
+                _ => in_headers = false,
+            }
+        } else {
+            request.body.push_str(line);
+            request.body.push('\n');
+        }
+    }
+
+    for request in &mut requests {
+        request.body = request.body.trim_end().to_string();
+    }
+    requests.retain(|r| !r.method.is_empty());
+    resolve_variables(&mut variables);
+    (variables, requests)
+}
+
+impl Interpreter for HttpInterpreter {
+    fn parse(&self, content: &[u8], file: &Path) -> ParseResult {
+        let mut scripts = Vec::new();
+        let mut errors = Vec::new();
+
+        let (variables, requests) = parse_requests(&String::from_utf8_lossy(content));
+        let mut call = HttpCallable {
+            variables,
+            requests: HashMap::new(),
+        };
+
+        for (index, request) in requests.into_iter().enumerate() {
+            let name = if request.name.is_empty() {
+                format!(
+                    "{}_{}",
+                    file.file_stem().unwrap_or_default().to_string_lossy(),
+                    index
+                )
+            } else {
+                request.name.clone()
+            };
+
+            let unsupported = request
+                .dynamic_variables()
+                .iter()
+                .find_map(|expression| dynamic_variable(expression, &call.variables).err());
+            if let Some(message) = unsupported {
+                errors.push(ParseError {
+                    filename: file.to_string_lossy().to_string(),
+                    message: format!("{}: {}", name, message),
+                    traceback: String::new(),
+                });
+                continue;
+            }
+
+            let mut script = Script::new(name, InterpreterType::Http);
+            script.file = file.to_path_buf();
+            script.description = request.description.join(" ");
+            let mut placeholders = request.placeholders();
+            placeholders.sort_by_key(|p| call.variables.contains_key(p));
+            for placeholder in placeholders {
+                let default = call.variables.get(&placeholder).cloned();
+                let default = default.unwrap_or_default();
+                let (typ, description) = match request.annotations.get(&placeholder) {
+                    Some((typ, description)) => (argument_type(typ, default), description.clone()),
+                    None => (ArgumentType::String(default), String::new()),
+                };
+                script.arguments.push((placeholder, typ, description));
+            }
+
+            call.requests.insert(
+                script.get_key().unwrap(),
+                (request, script.arguments.clone()),
+            );
+            scripts.push(script);
+        }
+
+        let rc: Arc<dyn Callable> = Arc::new(call);
+        let callables = scripts
+            .iter()
+            .map(|s| (s.get_key().unwrap(), rc.clone()))
+            .collect();
+
+        (scripts, callables, errors)
+    }
+}
+
+type Arguments = Vec<(String, ArgumentType, String)>;
+
+#[derive(Debug)]
+pub struct HttpCallable {
+    variables: HashMap<String, String>,
+    requests: HashMap<u64, (Request, Arguments)>,
+}
+
+pub(crate) fn to_text(arg: &dyn Any) -> Option<String> {
+    if let Some(s) = arg.downcast_ref::<String>() {
+        Some(s.clone())
+    } else if let Some(i) = arg.downcast_ref::<i32>() {
+        Some(i.to_string())
+    } else if let Some(u) = arg.downcast_ref::<u32>() {
+        Some(u.to_string())
+    } else if let Some(f) = arg.downcast_ref::<f32>() {
+        Some(f.to_string())
+    } else if let Some(b) = arg.downcast_ref::<bool>() {
+        Some(b.to_string())
+    } else {
+        arg.downcast_ref::<Vec<String>>().map(|l| l.join(","))
+    }
+}
+
+/// Replaces the placeholders in the order of `values`.
+fn substitute(text: &str, values: &[(&str, String)]) -> String {
+    let mut text = text.to_string();
+    for (name, value) in values {
+        for placeholder in [format!("{{{{{}}}}}", name), format!("{{{{ {} }}}}", name)] {
+            text = text.replace(&placeholder, value);
+        }
+    }
+    text
+}
+
+/// `text` with the placeholders replaced by `values`, and the dynamic
+/// variables by a fresh value each.
+fn fill(
+    text: &str,
+    values: &[(&str, String)],
+    variables: &HashMap<String, String>,
+) -> Result<String, String> {
+    let mut error = None;
+    let text = expand(&substitute(text, values), |expression| {
+        if !expression.starts_with('$') {
+            return None;
+        }
+        dynamic_variable(expression, variables)
+            .map_err(|e| error.get_or_insert(e).clone())
+            .ok()
+    });
+    error.map_or(Ok(text), Err)
+}
+
+fn send(
+    request: &Request,
+    values: &[(&str, String)],
+    variables: &HashMap<String, String>,
+) -> Result<Response, String> {
+    let mut builder = http::Request::builder()
+        .method(request.method.as_str())
+        .uri(fill(&request.url, values, variables)?);
+    for (name, value) in &request.headers {
+        builder = builder.header(name, fill(value, values, variables)?);
+    }
+    let http_request = builder
+        .body(fill(&request.body, values, variables)?)
+        .map_err(|e| e.to_string())?;
+
+    let config = ureq::Agent::config_builder()
+        .http_status_as_error(false)
+        .timeout_connect(Some(CONNECT_TIMEOUT))
+        .timeout_global(Some(TIMEOUT))
+        .build();
+    let response = ureq::Agent::new_with_config(config)
+        .run(http_request)
+        .map_err(|e| e.to_string())?;
+
+    let status = response.status().as_u16();
+    let headers = response
+        .headers()
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.to_string(),
+                String::from_utf8_lossy(value.as_bytes()).to_string(),
+            )
+        })
+        .collect();
+    let body = response
+        .into_body()
+        .read_to_string()
+        .map_err(|e| e.to_string())?;
+
+    Ok(Response {
+        status,
+        headers,
+        body,
+    })
+}
+
+impl Callable for HttpCallable {
+    fn call(
+        &self,
+        key: u64,
+        args: &[Box<dyn Any + Send>],
+        _events: &CallEvents,
+    ) -> Result<CallOutput, CallError> {
+        let (request, arguments) = self
+            .requests
+            .get(&key)
+            .ok_or(CallError::KeyNotPresent(key))?;
+        if args.len() > arguments.len() {
+            return Err(CallError::WrongArguments);
+        }
+
+        let mut values = Vec::new();
+        for (index, (name, _, _)) in arguments.iter().enumerate() {
+            let value = match args.get(index) {
+                Some(arg) => to_text(arg.as_ref()).ok_or(CallError::WrongArguments)?,
+                None => self
+                    .variables
+                    .get(name)
+                    .cloned()
+                    .ok_or(CallError::WrongArguments)?,
+            };
+            values.push((name.as_str(), value));
+        }
+
+        send(request, &values, &self.variables)
+            .map(CallOutput::Response)
+            .map_err(|message| CallError::Exception {
+                message,
+                traceback: format!("{} {}", request.method, request.url),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+
+    const REQUESTS: &str = concat!(
+        "@host = http://localhost:8080\n",
+        "\n",
+        "### get_user\n",
+        "# fetches a single user\n",
+        "# @arg id int id of the user\n",
+        "GET {{host}}/users/{{id}} HTTP/1.1\n",
+        "Accept: application/json\n",
+        "\n",
+        "###\n",
+        "# @name create_user\n",
+        "POST {{host}}/users\n",
+        "Content-Type: application/json\n",
+        "\n",
+        "{\"name\": \"{{name}}\", \"id\": \"{{$guid}}\"}\n",
+        "\n",
+    );
+
+    #[test]
+    fn parse_requests_and_placeholders() {
+        let (scripts, callables, errors) =
+            HttpInterpreter.parse(REQUESTS.as_bytes(), &PathBuf::from("/my/users.http"));
+
+        assert!(errors.is_empty());
+        assert_eq!(callables.len(), 2);
+        assert_eq!(scripts[0].name, "get_user");
+        assert_eq!(scripts[0].description, "fetches a single user");
+        assert_eq!(
+            scripts[0].arguments,
+            vec![
+                (
+                    "id".to_string(),
+                    ArgumentType::Int(0),
+                    "id of the user".to_string()
+                ),
+                (
+                    "host".to_string(),
+                    ArgumentType::String("http://localhost:8080".to_string()),
+                    String::new()
+                ),
+            ]
+        );
+        assert_eq!(scripts[1].name, "create_user");
+        assert_eq!(scripts[1].arguments.len(), 2);
+        assert_eq!(scripts[1].arguments[0].0, "name");
+    }
+
+    #[test]
+    fn file_variables_are_resolved() {
+        let source = concat!(
+            "@base = {{scheme}}://{{host}}\n",
+            "@host = {{name}}:8080\n",
+            "@name = localhost\n",
+            "@scheme = http\n",
+            "@loop = {{loop}}\n",
+            "GET {{base}}/users\n",
+        );
+        let (variables, _) = parse_requests(source);
+        assert_eq!(variables["host"], "localhost:8080");
+        assert_eq!(variables["loop"], "{{loop}}");
+
+        let (scripts, _, _) =
+            HttpInterpreter.parse(source.as_bytes(), &PathBuf::from("/my/nested.http"));
+        assert_eq!(
+            scripts[0].arguments,
+            vec![(
+                "base".to_string(),
+                ArgumentType::String("http://localhost:8080".to_string()),
+                String::new()
+            )]
+        );
+    }
+
+    #[test]
+    fn substitute_in_declaration_order() {
+        let values = [
+            ("outer", "{{inner}}".to_string()),
+            ("inner", "x".to_string()),
+        ];
+        assert_eq!(substitute("{{outer}}/{{ inner }}", &values), "x/x");
+        let values = [
+            ("inner", "x".to_string()),
+            ("outer", "{{inner}}".to_string()),
+        ];
+        assert_eq!(substitute("{{outer}}/{{ inner }}", &values), "{{inner}}/x");
+    }
+
+    /// Answers one request with `response`, returns the address and the request.
+    fn serve_once(
+        response: &'static str,
+    ) -> (std::net::SocketAddr, std::thread::JoinHandle<String>) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0u8; 1024];
+            // headers and body may arrive in separate reads
+            loop {
+                let read = stream.read(&mut buffer).unwrap();
+                request.extend_from_slice(&buffer[..read]);
+                let text = String::from_utf8_lossy(&request).to_string();
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let length = head
+                        .lines()
+                        .find_map(|l| {
+                            l.to_lowercase()
+                                .strip_prefix("content-length:")
+                                .map(|v| v.trim().parse().unwrap())
+                        })
+                        .unwrap_or(0);
+                    if read == 0 || body.len() >= length {
+                        break;
+                    }
+                }
+            }
+            stream.write_all(response.as_bytes()).unwrap();
+            String::from_utf8_lossy(&request).to_string()
+        });
+        (address, server)
+    }
+
+    #[test]
+    fn dynamic_variables() {
+        let variables = HashMap::from([("var".to_string(), "FLAUNCH_TEST_ENV".to_string())]);
+        let value = |expression| dynamic_variable(expression, &variables);
+
+        let guid = value("$guid").unwrap();
+        assert_eq!(guid.len(), 36);
+        assert_eq!(&guid[14..15], "4");
+        let now = Utc::now().timestamp();
+        let yesterday: i64 = value("$timestamp -1 d").unwrap().parse().unwrap();
+        assert!((now - 86400 - yesterday).abs() < 5);
+        let random: i64 = value("$randomInt -3 3").unwrap().parse().unwrap();
+        assert!((-3..3).contains(&random));
+        assert!(value("$datetime iso8601 1 y").unwrap().contains('T'));
+        assert!(value("$localDatetime rfc1123").is_ok());
+
+        std::env::set_var("FLAUNCH_TEST_ENV", "set");
+        assert_eq!(value("$processEnv FLAUNCH_TEST_ENV").unwrap(), "set");
+        assert_eq!(value("$processEnv %var").unwrap(), "set");
+
+        assert!(value("$dotenv KEY").is_err());
+        assert!(value("$randomInt 3 1").is_err());
+        assert!(value("$datetime \"DD-MM-YYYY\"").is_err());
+        assert!(value("$timestamp 1 parsec").is_err());
+    }
+
+    #[test]
+    fn unsupported_dynamic_variable_is_parse_error() {
+        let (scripts, _, errors) = HttpInterpreter.parse(
+            b"GET http://localhost/{{$dotenv KEY}}\n",
+            &PathBuf::from("/my/env.http"),
+        );
+        assert!(scripts.is_empty());
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message.contains("$dotenv"));
+    }
+
+    #[test]
+    fn call_fills_dynamic_variables() {
+        let (address, server) = serve_once("HTTP/1.1 201 Created\r\nContent-Length: 0\r\n\r\n");
+        let (scripts, callables, _) =
+            HttpInterpreter.parse(REQUESTS.as_bytes(), &PathBuf::from("/my/users.http"));
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let args: Vec<Box<dyn Any + Send>> = vec![
+            Box::new("sven".to_string()),
+            Box::new(format!("http://{}", address)),
+        ];
+        callables[1]
+            .1
+            .call(scripts[1].get_key().unwrap(), &args, &tx)
+            .unwrap();
+
+        let request = server.join().unwrap();
+        assert!(request.contains("\"name\": \"sven\""), "{}", request);
+        assert!(!request.contains("{{"), "{}", request);
+    }
+
+    #[test]
+    fn call_sends_request() {
+        let (address, server) =
+            serve_once("HTTP/1.1 404 Not Found\r\nX-Test: yes\r\nContent-Length: 7\r\n\r\nmissing");
+
+        let (scripts, callables, _) =
+            HttpInterpreter.parse(REQUESTS.as_bytes(), &PathBuf::from("/my/users.http"));
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let args: Vec<Box<dyn Any + Send>> =
+            vec![Box::new(42i32), Box::new(format!("http://{}", address))];
+        let output = callables[0]
+            .1
+            .call(scripts[0].get_key().unwrap(), &args, &tx)
+            .unwrap();
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("GET /users/42 HTTP/1.1"));
+        match output {
+            CallOutput::Response(response) => {
+                assert_eq!(response.status, 404);
+                assert_eq!(response.body, "missing");
+                assert!(response
+                    .headers
+                    .contains(&("x-test".to_string(), "yes".to_string())));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
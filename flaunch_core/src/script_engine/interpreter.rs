@@ -1,22 +1,133 @@
 use crate::script_engine::*;
+use futures::future::BoxFuture;
+use futures::FutureExt;
 use once_cell::sync::OnceCell;
+use std::any::Any;
 use std::boxed::Box;
 use std::hash::Hasher;
-use std::{any::Any, collections::hash_map::DefaultHasher, hash::Hash};
+use tokio::sync::mpsc::UnboundedSender;
 
+use super::http_interpreter::HttpInterpreter;
+use super::nb_interpreter::NbInterpreter;
 use super::py_interpreter::PyInterpreter;
-#[derive(Hash, Debug, Clone)]
+use super::rs_interpreter::RsInterpreter;
+use super::sql_interpreter::SqlInterpreter;
+use super::tpl_interpreter::TplInterpreter;
+use crate::settings::JsonValue;
+#[derive(Hash, Debug, Clone, PartialEq)]
 pub enum InterpreterType {
     Python,
+    Notebook,
+    Sql,
+    Http,
+    Rust,
+    Template,
 }
 
-pub trait Callable: Debug + Send + Sync {
-    fn call(&self, key: u64, args: &[Box<dyn Any>]) -> Result<(), CallError>;
+impl InterpreterType {
+    /// identifier of the interpreter for clients.
+    pub fn name(&self) -> &'static str {
+        match self {
+            InterpreterType::Python => "python",
+            InterpreterType::Notebook => "notebook",
+            InterpreterType::Sql => "sql",
+            InterpreterType::Http => "http",
+            InterpreterType::Rust => "rust",
+            InterpreterType::Template => "template",
+        }
+    }
+
+    /// every interpreter, they are all built in.
+    pub fn all() -> Vec<InterpreterType> {
+        vec![
+            InterpreterType::Python,
+            InterpreterType::Notebook,
+            InterpreterType::Sql,
+            InterpreterType::Http,
+            InterpreterType::Rust,
+            InterpreterType::Template,
+        ]
+    }
+
+    pub fn from_name(name: &str) -> Option<InterpreterType> {
+        InterpreterType::all()
+            .into_iter()
+            .find(|interpreter| interpreter.name() == name)
+    }
+}
+
+pub trait Callable: Debug + Send + Sync + 'static {
+    /// Runs the script identified by `key`. Intermediate results are sent to
+    /// `events` while the script is running, the final value is returned.
+    fn call(
+        &self,
+        key: u64,
+        args: &[Box<dyn Any + Send>],
+        events: &CallEvents,
+    ) -> Result<CallOutput, CallError>;
+
+    /// Like `call`, but without blocking the async runtime. By default `call`
+    /// runs on the blocking thread pool, callables with a runtime of their own
+    /// await it instead.
+    fn call_async(
+        self: Arc<Self>,
+        key: u64,
+        args: Vec<Box<dyn Any + Send>>,
+        events: CallEvents,
+    ) -> BoxFuture<'static, Result<CallOutput, CallError>> {
+        tokio::task::spawn_blocking(move || self.call(key, &args, &events))
+            .map(|result| {
+                result.unwrap_or_else(|e| {
+                    Err(CallError::Exception {
+                        message: e.to_string(),
+                        traceback: String::new(),
+                    })
+                })
+            })
+            .boxed()
+    }
+}
+
+/// Value a script returns once it completes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CallOutput {
+    Nothing,
+    Text(String),
+    Table(Table),
+    Response(Response),
+}
+
+/// Tabular result, e.g. the rows returned by a query.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Table {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<JsonValue>>,
 }
 
+/// Response to a request sent by a script.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+/// Events emitted by a script while it is still running.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CallEvent {
+    /// intermediate output, e.g. a value yielded by a generator.
+    Output(String),
+    /// diagnostic output, e.g. a line written to stderr.
+    Error(String),
+    /// fraction of the work done (0.0 - 1.0) and a message.
+    Progress(f32, String),
+}
+
+pub type CallEvents = UnboundedSender<CallEvent>;
+
 /// Result structure containing found script details.
 /// Returned as part of the `Interpreter::parse` function
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Script {
     /// required field
     pub name: String,
@@ -24,9 +135,23 @@ pub struct Script {
     pub arguments: Vec<(String, ArgumentType, String)>,
     pub file: PathBuf,
     pub interpreter_type: InterpreterType,
+    /// free form labels, e.g. to select scripts in an access policy.
+    pub tags: Vec<String>,
+    /// by argument name, only for arguments the script restricts.
+    pub constraints: HashMap<String, ArgumentConstraint>,
 }
 unsafe impl Send for Script {}
 
+/// Values an argument is restricted to, as declared by the script.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ArgumentConstraint {
+    /// the values to choose from, empty when any value of the type goes.
+    pub choices: Vec<String>,
+    /// bounds of numeric arguments, inclusive.
+    pub minimum: Option<f64>,
+    pub maximum: Option<f64>,
+}
+
 impl Script {
     pub fn new(name: String, interpreter_type: InterpreterType) -> Script {
         Script {
@@ -35,22 +160,50 @@ impl Script {
             arguments: Vec::new(),
             file: PathBuf::new(),
             interpreter_type: interpreter_type,
+            tags: Vec::new(),
+            constraints: HashMap::new(),
         }
     }
 
+    /// Identifies the script by its interpreter, file and name. Stays the
+    /// same across builds and platforms, clients may store it.
     pub fn get_key(&self) -> Option<u64> {
         if self.name.is_empty() {
             return None;
         }
 
-        let mut hasher = DefaultHasher::new();
-        self.name.hash(&mut hasher);
-        self.file.hash(&mut hasher);
-        self.interpreter_type.hash(&mut hasher);
+        let mut hasher = Fnv1a::default();
+        hasher.write(self.interpreter_type.name().as_bytes());
+        hasher.write_u8(0);
+        hasher.write(self.file.to_string_lossy().as_bytes());
+        hasher.write_u8(0);
+        hasher.write(self.name.as_bytes());
         Some(hasher.finish())
     }
 }
 
+/// 64 bit FNV-1a, unlike `DefaultHasher` its output is specified.
+pub(crate) struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a(0xcbf29ce484222325)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
 #[derive(Default, Clone, PartialEq, Debug)]
 pub struct ParseError {
     pub filename: String,
@@ -58,15 +211,28 @@ pub struct ParseError {
     pub traceback: String,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum CallError {
     KeyNotPresent(u64),
     WrongArguments,
+    Exception { message: String, traceback: String },
+}
+
+impl std::fmt::Display for CallError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CallError::KeyNotPresent(key) => write!(f, "no callable for key {}", key),
+            CallError::WrongArguments => write!(f, "wrong arguments"),
+            CallError::Exception { message, traceback } => {
+                write!(f, "{}\n{}", message, traceback)
+            }
+        }
+    }
 }
 
 pub type ParseResult = (Vec<Script>, Vec<(u64, Arc<dyn Callable>)>, Vec<ParseError>);
 
-pub trait Interpreter {
+pub trait Interpreter: Sync {
     /// Parses content of a given file and returns a list of found scripts.
     /// This function should be dumb and straight forward.
     /// The script engine will figure out itself the diff and update accordingly.
@@ -82,14 +248,47 @@ pub trait Interpreter {
     /// behavior
     /// `Vec<ParseError>` contains a list of parse errors found by the interpreter runtime
     fn parse(&self, content: &[u8], file: &Path) -> ParseResult;
+
+    /// Like `parse`, but without blocking the async runtime. By default
+    /// `parse` runs on the blocking thread pool.
+    fn parse_async(
+        &'static self,
+        content: Vec<u8>,
+        file: PathBuf,
+    ) -> BoxFuture<'static, ParseResult> {
+        tokio::task::spawn_blocking(move || self.parse(&content, &file))
+            .map(|result| {
+                result.unwrap_or_else(|e| {
+                    error!("parsing failed {}", e);
+                    ParseResult::default()
+                })
+            })
+            .boxed()
+    }
 }
 
 pub async fn read_and_parse_file(file: PathBuf) -> ParseResult {
-    let content = std::fs::read(&file).unwrap();
-    match select_interpreter_for_file(&file) {
-        Ok(it) => it.parse(&content, &file),
-        Err(e) => {
+    // interpreters are created on first use, which may block, e.g. python.
+    let selected = {
+        let file = file.clone();
+        tokio::task::spawn_blocking(move || select_interpreter_for_file(&file)).await
+    };
+    let interpreter = match selected {
+        Ok(Ok(it)) => it,
+        Ok(Err(e)) => {
             debug!("{}", e);
+            return ParseResult::default();
+        }
+        Err(e) => {
+            error!("parsing failed {}", e);
+            return ParseResult::default();
+        }
+    };
+
+    match tokio::fs::read(&file).await {
+        Ok(content) => interpreter.parse_async(content, file).await,
+        Err(e) => {
+            error!("could not read {}: {}", file.to_string_lossy(), e);
             ParseResult::default()
         }
     }
@@ -98,14 +297,25 @@ pub async fn read_and_parse_file(file: PathBuf) -> ParseResult {
 pub fn select_interpreter_for_file(
     file: &Path,
 ) -> Result<&'static dyn Interpreter, ScriptEngineError> {
-    let file_ext = file.extension().unwrap().to_str().unwrap();
+    let file_ext = file
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default();
 
     match file_ext {
-        "py" => Ok(PYINTERPRETER.get_or_init(|| PyInterpreter::default())),
+        "py" => Ok(PYINTERPRETER.get_or_init(PyInterpreter::default)),
+        "ipynb" => Ok(NBINTERPRETER.get_or_init(NbInterpreter::default)),
+        "sql" => Ok(SQLINTERPRETER.get_or_init(SqlInterpreter::default)),
+        "http" | "rest" => Ok(&HttpInterpreter),
+        "rs" => Ok(RSINTERPRETER.get_or_init(RsInterpreter::default)),
+        "tera" | "j2" | "jinja" | "jinja2" => Ok(&TplInterpreter),
         _ => Err(ScriptEngineError::InterpreterNotAvailable(
-            file.extension().unwrap().to_os_string(),
+            file.extension().unwrap_or_default().to_os_string(),
         )),
     }
 }
 
 static PYINTERPRETER: OnceCell<PyInterpreter> = OnceCell::new();
+static NBINTERPRETER: OnceCell<NbInterpreter> = OnceCell::new();
+static SQLINTERPRETER: OnceCell<SqlInterpreter> = OnceCell::new();
+static RSINTERPRETER: OnceCell<RsInterpreter> = OnceCell::new();
@@ -1,16 +1,27 @@
+mod http_interpreter;
 mod interpreter;
+mod nb_interpreter;
+mod py_dependencies;
+mod py_executor;
 mod py_interpreter;
+mod rs_interpreter;
+mod sql_interpreter;
+mod tpl_interpreter;
 use crate::logging::*;
 
 use futures::select;
 use futures::stream::FuturesUnordered;
 use futures::FutureExt;
 use futures::StreamExt;
-pub use interpreter::Script;
+pub use interpreter::{
+    ArgumentConstraint, CallError, CallEvent, CallEvents, CallOutput, InterpreterType, ParseError, Response, Script,
+    Table,
+};
 use log::info;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 use std::fmt::Display;
+use std::panic::AssertUnwindSafe;
 use std::path::Path;
 use std::sync::Arc;
 use std::{any::Any, boxed::Box, ffi::OsString, fs::DirEntry};
@@ -22,7 +33,6 @@ use tokio::sync::RwLock;
 use std::{path::PathBuf, vec::Vec};
 
 use self::interpreter::Callable;
-use self::interpreter::ParseError;
 
 #[derive(Clone, PartialEq, Debug)]
 pub enum ArgumentType {
@@ -61,17 +71,25 @@ pub struct ScriptEngine {
     script_receiver: Receiver<ScriptChange>,
     call_map: RwLock<HashMap<u64, Arc<dyn Callable>>>,
     scripts: RwLock<HashMap<u64, Script>>,
+    /// parse errors of the last load of every file.
+    diagnostics: RwLock<HashMap<PathBuf, Vec<ParseError>>>,
+    diagnostics_sender: Sender<Vec<ParseError>>,
+    diagnostics_receiver: Receiver<Vec<ParseError>>,
 }
 
 impl Default for ScriptEngine {
     fn default() -> Self {
         let (s, r) = watch::channel(ScriptChange::Deleted(0));
+        let (ds, dr) = watch::channel(Vec::new());
 
         ScriptEngine {
             script_sender: s,
             script_receiver: r,
             call_map: RwLock::new(HashMap::new()),
             scripts: RwLock::new(HashMap::new()),
+            diagnostics: RwLock::new(HashMap::new()),
+            diagnostics_sender: ds,
+            diagnostics_receiver: dr,
         }
     }
 }
@@ -81,9 +99,22 @@ impl ScriptEngine {
         self.script_receiver.clone()
     }
 
+    /// Current parse errors of all loaded files.
+    pub fn diagnostics(&self) -> Vec<ParseError> {
+        self.diagnostics_receiver.borrow().clone()
+    }
+
+    /// Notified with all current parse errors whenever they change.
+    pub fn observe_diagnostics(&self) -> watch::Receiver<Vec<ParseError>> {
+        self.diagnostics_receiver.clone()
+    }
+
+    /// Parses all files in `scripts_path`. Scripts loaded earlier from this
+    /// directory that are no longer found are removed.
     pub async fn load(&self, scripts_path: &Path) -> Result<Vec<ParseError>, ScriptEngineError> {
         let files = get_files_of_dir(scripts_path)?;
         if files.is_empty() {
+            self.unload(scripts_path).await;
             return Err(ScriptEngineError::NoScriptsFound(
                 scripts_path.to_path_buf(),
             ));
@@ -92,25 +123,76 @@ impl ScriptEngine {
         let mut parse_fut = FuturesUnordered::new();
         for file in files {
             info!("loading {}", file.to_string_lossy());
-            let parse_task = interpreter::read_and_parse_file(file).fuse();
+            let parse_task = interpreter::read_and_parse_file(file.clone())
+                .map(move |result| (file, result))
+                .fuse();
             parse_fut.push(parse_task);
         }
 
-        let mut errors: Vec<ParseError> = Vec::new();
+        let mut errors: Vec<(PathBuf, Vec<ParseError>)> = Vec::new();
+        let mut loaded = HashSet::new();
 
         loop {
             select! {
-                parse_res = parse_fut.select_next_some() => {
+                (file, parse_res) = parse_fut.select_next_some() => {
                     let (scripts, callables, err) = parse_res;
-                    errors.extend(err);
+                    errors.push((file, err));
+                    loaded.extend(scripts.iter().filter_map(Script::get_key));
                     self.insert_callables(callables).await;
-                    self.process_new_scripts(scripts);
+                    self.process_new_scripts(scripts).await;
                 }
                 complete => break,
             }
         }
 
-        Ok(errors)
+        self.remove_stale_scripts(scripts_path, &loaded).await;
+        Ok(self.update_diagnostics(scripts_path, errors).await)
+    }
+
+    /// Replaces the diagnostics of the files in `scripts_path` and returns
+    /// the new ones.
+    async fn update_diagnostics(
+        &self,
+        scripts_path: &Path,
+        errors: Vec<(PathBuf, Vec<ParseError>)>,
+    ) -> Vec<ParseError> {
+        let mut diagnostics = self.diagnostics.write().await;
+        diagnostics.retain(|file, _| !file.starts_with(scripts_path));
+        let new_errors = errors
+            .iter()
+            .flat_map(|(_, errors)| errors.iter().cloned())
+            .collect();
+        diagnostics.extend(errors.into_iter().filter(|(_, errors)| !errors.is_empty()));
+
+        let mut all: Vec<ParseError> = diagnostics.values().flatten().cloned().collect();
+        all.sort_by(|a, b| a.filename.cmp(&b.filename));
+        if *self.diagnostics_receiver.borrow() != all {
+            let _ = self.diagnostics_sender.send(all);
+        }
+        new_errors
+    }
+
+    /// Removes all scripts and diagnostics loaded from `scripts_path`.
+    pub async fn unload(&self, scripts_path: &Path) {
+        self.remove_stale_scripts(scripts_path, &HashSet::new())
+            .await;
+        self.update_diagnostics(scripts_path, Vec::new()).await;
+    }
+
+    async fn remove_stale_scripts(&self, scripts_path: &Path, loaded: &HashSet<u64>) {
+        let mut scripts = self.scripts.write().await;
+        let stale: Vec<u64> = scripts
+            .iter()
+            .filter(|(key, s)| s.file.starts_with(scripts_path) && !loaded.contains(key))
+            .map(|(key, _)| *key)
+            .collect();
+
+        for key in stale {
+            info!("removing script {}", key);
+            scripts.remove(&key);
+            self.call_map.write().await.remove(&key);
+            self.script_sender.send(ScriptChange::Deleted(key)).unwrap();
+        }
     }
 
     pub async fn scripts(&self) -> Vec<Script> {
@@ -121,25 +203,46 @@ impl ScriptEngine {
         self.call_map.write().await.extend(callables.into_iter())
     }
 
-    fn process_new_scripts(&self, scripts: Vec<Script>) {
+    async fn process_new_scripts(&self, scripts: Vec<Script>) {
+        self.scripts.write().await.extend(
+            scripts
+                .iter()
+                .filter_map(|s| Some((s.get_key()?, s.clone()))),
+        );
         self.script_sender
             .send(ScriptChange::NewOrUpdated(scripts))
             .unwrap();
     }
 
+    /// Calls a script without blocking the async runtime, long running
+    /// scripts run on the blocking thread pool or their interpreter's own
+    /// thread.
     pub async fn call(
         &self,
         script_key: u64,
-        args: &[Box<dyn Any>],
-    ) -> Result<bool, ScriptEngineError> {
-        if let Some(c) = self.call_map.read().await.get(&script_key) {
-            info!("{}= Calling script:{}", module_path!(), script_key);
-            self.return_on_invalid_arguments(&script_key, args.len())?;
-            c.call(script_key, args).unwrap();
-            return Ok(true);
-        } else {
-            return Err(ScriptEngineError::ScriptKeyDoesNotExist(script_key));
-        }
+        args: Vec<Box<dyn Any + Send>>,
+        events: CallEvents,
+    ) -> Result<CallOutput, ScriptEngineError> {
+        let callable = self
+            .call_map
+            .read()
+            .await
+            .get(&script_key)
+            .cloned()
+            .ok_or(ScriptEngineError::ScriptKeyDoesNotExist(script_key))?;
+
+        info!("{}= Calling script:{}", module_path!(), script_key);
+        self.return_on_invalid_arguments(&script_key, args.len())?;
+        AssertUnwindSafe(callable.call_async(script_key, args, events))
+            .catch_unwind()
+            .await
+            .unwrap_or_else(|payload| {
+                Err(CallError::Exception {
+                    message: panic_message(payload.as_ref()),
+                    traceback: String::new(),
+                })
+            })
+            .map_err(|e| ScriptEngineError::CallFailed(script_key, e))
     }
 
     fn return_on_invalid_arguments(
@@ -159,6 +262,14 @@ impl ScriptEngine {
     }
 }
 
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|m| m.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "script panicked".to_string())
+}
+
 fn get_files_of_dir(dir: &Path) -> Result<Vec<PathBuf>, ScriptEngineError> {
     let filter = |d: std::io::Result<DirEntry>| match d {
         Ok(entry) => Some(entry.path()),
@@ -184,6 +295,7 @@ pub enum ScriptEngineError {
     InterpreterNotAvailable(OsString),
     MissingArguments(Vec<String>, usize),
     NoScriptsFound(PathBuf),
+    CallFailed(u64, CallError),
 }
 
 impl<'a> std::fmt::Display for ScriptEngineError {
@@ -201,6 +313,9 @@ impl<'a> std::fmt::Display for ScriptEngineError {
             ScriptEngineError::NoScriptsFound(directory) => {
                 write!(f, "no scripts found in {}", directory.to_string_lossy())
             }
+            ScriptEngineError::CallFailed(key, reason) => {
+                write!(f, "calling {} failed: {}", key, reason)
+            }
         }
     }
 }
@@ -276,3 +391,56 @@ impl<'a> std::fmt::Display for ScriptEngineError {
 //         ]);
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reload_removes_deleted_scripts() {
+        let dir = std::env::temp_dir().join(format!("flaunch_engine_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.tera"), "+++\nname = \"a\"\n+++\na\n").unwrap();
+        std::fs::write(dir.join("b.tera"), "+++\nname = \"b\"\n+++\nb\n").unwrap();
+
+        let engine = ScriptEngine::default();
+        engine.load(&dir).await.unwrap();
+        assert_eq!(engine.scripts().await.len(), 2);
+
+        std::fs::remove_file(dir.join("b.tera")).unwrap();
+        let changes = engine.observe();
+        engine.load(&dir).await.unwrap();
+        let scripts = engine.scripts().await;
+        assert_eq!(scripts.len(), 1);
+        assert_eq!(scripts[0].name, "a");
+        assert!(matches!(*changes.borrow(), ScriptChange::Deleted(_)));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn script_keys_are_stable() {
+        let mut script = Script::new("a".to_string(), InterpreterType::Python);
+        script.file = PathBuf::from("/scripts/a.py");
+        assert_eq!(script.get_key(), Some(9868607434251955948));
+        script.interpreter_type = InterpreterType::Template;
+        assert_ne!(script.get_key(), Some(9868607434251955948));
+    }
+
+    #[tokio::test]
+    async fn diagnostics_follow_the_last_load() {
+        let dir = std::env::temp_dir().join(format!("flaunch_diagnostics_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.tera"), "+++\nname = \"a\"\n+++\n{{ a\n").unwrap();
+
+        let engine = ScriptEngine::default();
+        let errors = engine.load(&dir).await.unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(engine.diagnostics(), errors);
+        assert!(engine.diagnostics()[0].filename.ends_with("a.tera"));
+
+        std::fs::write(dir.join("a.tera"), "+++\nname = \"a\"\n+++\n{{ a }}\n").unwrap();
+        engine.load(&dir).await.unwrap();
+        assert!(engine.diagnostics().is_empty());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
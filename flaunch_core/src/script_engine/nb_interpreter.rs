@@ -0,0 +1,617 @@
+extern crate pyo3;
+
+use std::collections::HashMap;
+
+use crate::script_engine::interpreter::*;
+use crate::script_engine::py_executor::executor;
+use crate::script_engine::py_interpreter::{format_traceback, PyArgument};
+use crate::script_engine::*;
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use json::JsonValue;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyModule};
+
+const SCRIPT_TAG: &str = "flaunch";
+const PARAMETERS_TAG: &str = "parameters";
+
+/// Exposes code cells of jupyter notebooks as scripts.
+///
+/// Cells tagged `flaunch` become scripts. The cell tagged `parameters`
+/// (papermill style) declares the arguments of all scripts in the notebook.
+/// Calling a script runs, headless, every untagged code cell above it, injects
+/// the arguments after the parameters cell and finally runs the script cell.
+/// Every line the cells print is sent right away, as an output event or an
+/// error event for stderr. The call returns the output of the script cell.
+#[derive(Debug)]
+pub struct NbInterpreter {
+    runner: Py<PyModule>,
+}
+
+impl Default for NbInterpreter {
+    fn default() -> Self {
+        executor().run_blocking(|py| {
+            let module = PyModule::from_code(
+                py,
+                include_str!("nb_runner.py"),
+                "nb_runner.py",
+                "nb_runner",
+            )
+            .unwrap();
+            NbInterpreter {
+                runner: module.into_py(py),
+            }
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Cell {
+    /// position in the notebook, counting all cell types
+    index: usize,
+    source: String,
+    tags: Vec<String>,
+    metadata: JsonValue,
+}
+
+impl Cell {
+    fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+}
+
+fn read_cells(notebook: &JsonValue) -> Vec<Cell> {
+    notebook["cells"]
+        .members()
+        .enumerate()
+        .filter(|(_, cell)| cell["cell_type"] == "code")
+        .map(|(index, cell)| {
+            let source = &cell["source"];
+            let source = if source.is_array() {
+                source.members().filter_map(JsonValue::as_str).collect()
+            } else {
+                source.as_str().unwrap_or_default().to_string()
+            };
+            Cell {
+                index,
+                source: strip_magics(&source),
+                tags: cell["metadata"]["tags"]
+                    .members()
+                    .filter_map(JsonValue::as_str)
+                    .map(str::to_string)
+                    .collect(),
+                metadata: cell["metadata"][SCRIPT_TAG].clone(),
+            }
+        })
+        .collect()
+}
+
+/// IPython magics and shell escapes can't run without a kernel. Only lines
+/// that start a statement are magics, not those inside strings or brackets.
+fn strip_magics(source: &str) -> String {
+    let mut scanner = Scanner::default();
+    source
+        .lines()
+        .filter(|line| {
+            let at_statement = scanner.at_statement();
+            scanner.scan(line);
+            let line = line.trim_start();
+            !(at_statement && (line.starts_with('%') || line.starts_with('!')))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Tracks whether a python line continues the statement of the lines before.
+#[derive(Default)]
+struct Scanner {
+    /// open `(`, `[` and `{`
+    depth: usize,
+    /// the quote of an unterminated triple quoted string
+    triple_quote: Option<char>,
+    continued: bool,
+}
+
+impl Scanner {
+    fn at_statement(&self) -> bool {
+        self.depth == 0 && self.triple_quote.is_none() && !self.continued
+    }
+
+    fn scan(&mut self, line: &str) {
+        let mut chars = line.chars().peekable();
+        let mut quote = None;
+        self.continued = false;
+        while let Some(c) = chars.next() {
+            if let Some(q) = self.triple_quote {
+                if c == '\\' {
+                    chars.next();
+                } else if c == q && chars.next_if_eq(&q).is_some() && chars.next_if_eq(&q).is_some()
+                {
+                    self.triple_quote = None;
+                }
+                continue;
+            }
+            if let Some(q) = quote {
+                if c == '\\' {
+                    chars.next();
+                } else if c == q {
+                    quote = None;
+                }
+                continue;
+            }
+            match c {
+                '#' => break,
+                '\'' | '"' => {
+                    if chars.next_if_eq(&c).is_some() {
+                        if chars.next_if_eq(&c).is_some() {
+                            self.triple_quote = Some(c);
+                        }
+                        // otherwise an empty string
+                    } else {
+                        quote = Some(c);
+                    }
+                }
+                '(' | '[' | '{' => self.depth += 1,
+                ')' | ']' | '}' => self.depth = self.depth.saturating_sub(1),
+                '\\' if chars.peek().is_none() => self.continued = true,
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Reads `name = value  # description` assignments of a parameters cell.
+/// The type is taken from an annotation, or inferred from the default value.
+fn parse_parameters(source: &str) -> Vec<(String, ArgumentType, String)> {
+    let mut parameters = Vec::new();
+    for line in source.lines() {
+        let (code, description) = split_comment(line);
+        let (target, value) = match code.split_once('=') {
+            Some((target, value)) => (target.trim(), value.trim()),
+            None => continue,
+        };
+        let (name, annotation) = match target.split_once(':') {
+            Some((name, annotation)) => (name.trim(), Some(annotation.trim())),
+            None => (target, None),
+        };
+        if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            continue;
+        }
+        parameters.push((
+            name.to_string(),
+            parameter_type(annotation, value),
+            description.to_string(),
+        ));
+    }
+    parameters
+}
+
+fn split_comment(line: &str) -> (&str, &str) {
+    let mut quote = None;
+    for (index, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '#') => return (&line[..index], line[index + 1..].trim()),
+            _ => {}
+        }
+    }
+    (line, "")
+}
+
+fn parameter_type(annotation: Option<&str>, value: &str) -> ArgumentType {
+    let unquoted = value.trim_matches(|c| c == '"' || c == '\'').to_string();
+    match annotation {
+        Some("int") => return ArgumentType::Int(value.parse().unwrap_or_default()),
+        Some("float") => return ArgumentType::Float(value.parse().unwrap_or_default()),
+        Some("bool") => return ArgumentType::Boolean(value.to_string()),
+        Some("str") => return ArgumentType::String(unquoted),
+        Some(list) if list.starts_with("list") => return ArgumentType::List(value.to_string()),
+        _ => {}
+    }
+
+    if let Ok(i) = value.parse::<i32>() {
+        ArgumentType::Int(i)
+    } else if let Ok(f) = value.parse::<f32>() {
+        ArgumentType::Float(f)
+    } else if value == "True" || value == "False" {
+        ArgumentType::Boolean(value.to_string())
+    } else if value.starts_with('"') || value.starts_with('\'') {
+        ArgumentType::String(unquoted)
+    } else if value.starts_with('[') {
+        ArgumentType::List(value.to_string())
+    } else {
+        ArgumentType::NotSpecified
+    }
+}
+
+fn script_name(file: &Path, cell: &Cell) -> String {
+    match cell.metadata["name"].as_str() {
+        Some(name) => name.to_string(),
+        None => format!(
+            "{}_{}",
+            file.file_stem().unwrap_or_default().to_string_lossy(),
+            cell.index
+        ),
+    }
+}
+
+fn script_description(cell: &Cell) -> String {
+    if let Some(description) = cell.metadata["description"].as_str() {
+        return description.to_string();
+    }
+    // leading comment lines of the cell
+    cell.source
+        .lines()
+        .map_while(|line| line.trim().strip_prefix('#'))
+        .map(str::trim)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl Interpreter for NbInterpreter {
+    fn parse(&self, content: &[u8], file: &Path) -> ParseResult {
+        let mut scripts = Vec::new();
+        let mut callables: Vec<(u64, Arc<dyn Callable>)> = Vec::new();
+
+        let notebook = match json::parse(&String::from_utf8_lossy(content)) {
+            Ok(notebook) => notebook,
+            Err(e) => {
+                let error = ParseError {
+                    filename: file.to_string_lossy().to_string(),
+                    message: format!("invalid notebook: {}", e),
+                    traceback: String::new(),
+                };
+                return (scripts, callables, vec![error]);
+            }
+        };
+
+        let cells = read_cells(&notebook);
+        let parameters_cell = cells.iter().position(|c| c.has_tag(PARAMETERS_TAG));
+        let arguments = parameters_cell
+            .map(|index| parse_parameters(&cells[index].source))
+            .unwrap_or_default();
+
+        let mut runs = HashMap::new();
+        for (index, cell) in cells.iter().enumerate() {
+            if !cell.has_tag(SCRIPT_TAG) {
+                continue;
+            }
+
+            let mut script = Script::new(script_name(file, cell), InterpreterType::Notebook);
+            script.file = file.to_path_buf();
+            script.description = script_description(cell);
+            script.arguments = arguments.clone();
+
+            // untagged cells above the script are its setup
+            let mut sources = Vec::new();
+            let mut inject_at = 0;
+            for (i, setup) in cells[..index].iter().enumerate() {
+                if setup.has_tag(SCRIPT_TAG) {
+                    continue;
+                }
+                sources.push(setup.source.clone());
+                if Some(i) == parameters_cell {
+                    inject_at = sources.len();
+                }
+            }
+            sources.push(cell.source.clone());
+
+            let run = CellRun {
+                sources,
+                inject_at,
+                parameters: arguments.iter().map(|a| a.0.clone()).collect(),
+                name: script.name.clone(),
+            };
+            runs.insert(script.get_key().unwrap(), run);
+            scripts.push(script);
+        }
+
+        if !runs.is_empty() {
+            let rc: Arc<dyn Callable> = Arc::new(NbCallable {
+                runs,
+                runner: self.runner.clone(),
+            });
+            callables = scripts
+                .iter()
+                .map(|s| (s.get_key().unwrap(), rc.clone()))
+                .collect();
+        }
+
+        (scripts, callables, Vec::new())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CellRun {
+    /// code cells executed in order, the last one is the script cell.
+    sources: Vec<String>,
+    /// arguments are injected before running the cell at this index.
+    inject_at: usize,
+    parameters: Vec<String>,
+    name: String,
+}
+
+#[derive(Debug)]
+pub struct NbCallable {
+    runs: HashMap<u64, CellRun>,
+    runner: Py<PyModule>,
+}
+
+impl NbCallable {
+    /// the job running the cells of the script on the python thread.
+    fn job(
+        &self,
+        key: u64,
+        args: &[Box<dyn Any + Send>],
+        events: &CallEvents,
+    ) -> Result<impl FnOnce(Python) -> Result<CallOutput, CallError> + Send + 'static, CallError>
+    {
+        let run = self
+            .runs
+            .get(&key)
+            .cloned()
+            .ok_or(CallError::KeyNotPresent(key))?;
+        if args.len() > run.parameters.len() {
+            return Err(CallError::WrongArguments);
+        }
+
+        let mut values = Vec::new();
+        for arg in args {
+            values.push(PyArgument::from_any(arg.as_ref()).ok_or(CallError::WrongArguments)?);
+        }
+
+        let runner = self.runner.clone();
+        let events = events.clone();
+        Ok(move |py: Python| {
+            run_cells(py, runner.as_ref(py), run, values, events)
+                .map(CallOutput::Text)
+                .map_err(|e| CallError::Exception {
+                    message: e.pvalue(py).to_string(),
+                    traceback: format_traceback(py, &e),
+                })
+        })
+    }
+}
+
+impl Callable for NbCallable {
+    fn call(
+        &self,
+        key: u64,
+        args: &[Box<dyn Any + Send>],
+        events: &CallEvents,
+    ) -> Result<CallOutput, CallError> {
+        let job = self.job(key, args, events)?;
+        executor().run_blocking(job)
+    }
+
+    fn call_async(
+        self: Arc<Self>,
+        key: u64,
+        args: Vec<Box<dyn Any + Send>>,
+        events: CallEvents,
+    ) -> BoxFuture<'static, Result<CallOutput, CallError>> {
+        match self.job(key, &args, &events) {
+            Ok(job) => executor().run(job).boxed(),
+            Err(e) => futures::future::ready(Err(e)).boxed(),
+        }
+    }
+}
+
+/// Stream the cells write to, sends every line as an output event, or as
+/// an error event for stderr.
+#[pyclass]
+struct CellOutput {
+    events: CallEvents,
+    line: String,
+    stderr: bool,
+}
+
+impl CellOutput {
+    fn new(events: CallEvents, stderr: bool) -> Self {
+        CellOutput {
+            events,
+            line: String::new(),
+            stderr,
+        }
+    }
+
+    fn send(&self, line: String) {
+        let event = if self.stderr {
+            CallEvent::Error(line)
+        } else {
+            CallEvent::Output(line)
+        };
+        // the caller might not be interested in events
+        let _ = self.events.send(event);
+    }
+}
+
+#[pymethods]
+impl CellOutput {
+    fn write(&mut self, text: &str) -> usize {
+        self.line.push_str(text);
+        while let Some(end) = self.line.find('\n') {
+            let line: String = self.line.drain(..=end).collect();
+            self.send(line.trim_end_matches('\n').to_string());
+        }
+        text.chars().count()
+    }
+
+    fn flush(&mut self) {
+        if !self.line.is_empty() {
+            let line = std::mem::take(&mut self.line);
+            self.send(line);
+        }
+    }
+}
+
+fn run_cells(
+    py: Python,
+    runner: &PyModule,
+    run: CellRun,
+    values: Vec<PyArgument>,
+    events: CallEvents,
+) -> PyResult<String> {
+    let parameters = PyDict::new(py);
+    for (name, value) in run.parameters.iter().zip(values.iter()) {
+        parameters.set_item(name, value.to_object(py))?;
+    }
+    let stdout = Py::new(py, CellOutput::new(events.clone(), false))?;
+    let stderr = Py::new(py, CellOutput::new(events, true))?;
+    runner
+        .call1(
+            "run_cells",
+            (
+                run.sources,
+                run.inject_at,
+                parameters,
+                run.name,
+                stdout,
+                stderr,
+            ),
+        )?
+        .extract()
+}
+
+unsafe impl Send for NbInterpreter {}
+unsafe impl Sync for NbInterpreter {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOTEBOOK: &str = r##"{
+        "cells": [
+            {"cell_type": "markdown", "metadata": {}, "source": ["# Runbook"]},
+            {"cell_type": "code", "metadata": {}, "source": ["%matplotlib inline\n", "greeting = 'hello'"]},
+            {"cell_type": "code", "metadata": {"tags": ["parameters"]},
+             "source": ["name = 'world'  # who to greet\n", "times: int = 1\n"]},
+            {"cell_type": "code", "metadata": {"tags": ["flaunch"], "flaunch": {"name": "greet"}},
+             "source": ["# greets someone\n", "print(greeting, name)\n", "times * 2"]},
+            {"cell_type": "code", "metadata": {"tags": ["flaunch"]}, "source": "print('second')"}
+        ],
+        "metadata": {},
+        "nbformat": 4,
+        "nbformat_minor": 5
+    }"##;
+
+    const STDERR_NOTEBOOK: &str = r##"{
+        "cells": [
+            {"cell_type": "code", "metadata": {"tags": ["flaunch"]},
+             "source": ["import sys\n", "print('out')\n", "print('warned', file=sys.stderr)"]}
+        ],
+        "metadata": {},
+        "nbformat": 4,
+        "nbformat_minor": 5
+    }"##;
+
+    #[test]
+    fn strip_magics_at_statements_only() {
+        let source = concat!(
+            "%time x = 1\n",
+            "  !ls\n",
+            "text = \"\"\"\n",
+            "%not a magic\n",
+            "\"\"\"\n",
+            "call(\n",
+            "    !flag,\n",
+            ")\n",
+            "y = 1 + \\\n",
+            "!z\n",
+            "s = '%s' % 1  # (\n",
+            "%%bash"
+        );
+        assert_eq!(
+            strip_magics(source),
+            concat!(
+                "text = \"\"\"\n",
+                "%not a magic\n",
+                "\"\"\"\n",
+                "call(\n",
+                "    !flag,\n",
+                ")\n",
+                "y = 1 + \\\n",
+                "!z\n",
+                "s = '%s' % 1  # (",
+            )
+        );
+    }
+
+    #[test]
+    fn parse_tagged_cells() {
+        let (scripts, callables, errors) = NbInterpreter::default()
+            .parse(NOTEBOOK.as_bytes(), &PathBuf::from("/my/runbook.ipynb"));
+
+        assert!(errors.is_empty());
+        assert_eq!(scripts.len(), 2);
+        assert_eq!(callables.len(), 2);
+        assert_eq!(scripts[0].name, "greet");
+        assert_eq!(scripts[0].description, "greets someone");
+        assert_eq!(scripts[1].name, "runbook_4");
+        assert_eq!(
+            scripts[0].arguments,
+            vec![
+                (
+                    "name".to_string(),
+                    ArgumentType::String("world".to_string()),
+                    "who to greet".to_string()
+                ),
+                ("times".to_string(), ArgumentType::Int(1), String::new()),
+            ]
+        );
+    }
+
+    #[test]
+    fn call_runs_notebook_with_arguments() {
+        let (scripts, callables, _) = NbInterpreter::default()
+            .parse(NOTEBOOK.as_bytes(), &PathBuf::from("/my/runbook.ipynb"));
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+
+        let args: Vec<Box<dyn Any + Send>> = vec![Box::new("sven".to_string()), Box::new(2i32)];
+        let output = callables[0]
+            .1
+            .call(scripts[0].get_key().unwrap(), &args, &tx);
+        assert_eq!(
+            output.unwrap(),
+            CallOutput::Text("hello sven\n4\n".to_string())
+        );
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            CallEvent::Output("hello sven".to_string())
+        );
+        assert_eq!(rx.try_recv().unwrap(), CallEvent::Output("4".to_string()));
+
+        let output = callables[0].1.call(scripts[0].get_key().unwrap(), &[], &tx);
+        assert_eq!(
+            output.unwrap(),
+            CallOutput::Text("hello world\n2\n".to_string())
+        );
+    }
+
+    #[test]
+    fn stderr_is_sent_as_error() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let (scripts, callables, _) = NbInterpreter::default()
+            .parse(STDERR_NOTEBOOK.as_bytes(), &PathBuf::from("/my/warn.ipynb"));
+        let output = callables[0].1.call(scripts[0].get_key().unwrap(), &[], &tx);
+        assert_eq!(
+            output.unwrap(),
+            CallOutput::Text("out\nwarned\n".to_string())
+        );
+        assert_eq!(rx.try_recv().unwrap(), CallEvent::Output("out".to_string()));
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            CallEvent::Error("warned".to_string())
+        );
+    }
+
+    #[test]
+    fn invalid_notebook_is_parse_error() {
+        let (scripts, _, errors) =
+            NbInterpreter::default().parse(b"{not json", &PathBuf::from("/my/runbook.ipynb"));
+        assert!(scripts.is_empty());
+        assert_eq!(errors.len(), 1);
+    }
+}
@@ -0,0 +1,51 @@
+import ast
+import contextlib
+import io
+
+
+class _Tee(io.TextIOBase):
+    """writes to all of its streams"""
+
+    def __init__(self, *streams):
+        self.streams = streams
+
+    def write(self, text):
+        for stream in self.streams:
+            stream.write(text)
+        return len(text)
+
+    def flush(self):
+        for stream in self.streams:
+            stream.flush()
+
+
+def _run_source(source, filename, scope):
+    tree = ast.parse(source, filename, "exec")
+    last = None
+    if tree.body and isinstance(tree.body[-1], ast.Expr):
+        last = ast.Expression(tree.body.pop().value)
+    exec(compile(tree, filename, "exec"), scope)
+    if last is not None:
+        value = eval(compile(last, filename, "eval"), scope)
+        if value is not None:
+            print(repr(value))
+
+
+def run_cells(cells, inject_at, parameters, name, stdout, stderr):
+    """runs notebook cells in a fresh namespace, writing their output to stdout
+    and stderr, and returns the output of the last cell"""
+    scope = {"__name__": "__main__"}
+    output = io.StringIO()
+    for index, source in enumerate(cells):
+        if index == inject_at:
+            scope.update(parameters)
+        output = io.StringIO()
+        try:
+            with contextlib.redirect_stdout(_Tee(output, stdout)), contextlib.redirect_stderr(
+                _Tee(output, stderr)
+            ):
+                _run_source(source, "%s[%d]" % (name, index), scope)
+        finally:
+            stdout.flush()
+            stderr.flush()
+    return output.getvalue()
@@ -4,3 +4,44 @@ def flaunch(*args, **kwargs):
     def inner(func):
         flaunch_callables[func] = kwargs
     return inner
+
+
+def _choices(annotation):
+    """the type ("str" or "int") and values of a Literal or Enum annotation,
+    None for other annotations"""
+    import enum
+    import typing
+    if isinstance(annotation, type) and issubclass(annotation, enum.Enum):
+        return ("str", [member.name for member in annotation])
+    if getattr(annotation, "__origin__", None) is typing.Literal:
+        values = annotation.__args__
+        numbers = all(type(value) is int for value in values)
+        return ("int" if numbers else "str", [str(value) for value in values])
+    return None
+
+
+class Progress:
+    """yield from a generator script to report progress"""
+    def __init__(self, fraction, message=""):
+        self.fraction = fraction
+        self.message = message
+
+
+_loop = None
+
+def _event_loop():
+    global _loop
+    if _loop is None or _loop.is_closed():
+        import asyncio
+        _loop = asyncio.new_event_loop()
+    return _loop
+
+def _run_coroutine(coro):
+    return _event_loop().run_until_complete(coro)
+
+def _anext(agen):
+    """returns (True, value), or (False, None) once the generator is exhausted"""
+    try:
+        return (True, _event_loop().run_until_complete(agen.__anext__()))
+    except StopAsyncIteration:
+        return (False, None)
@@ -0,0 +1,401 @@
+use crate::app_meta;
+use crate::logging::*;
+use crate::script_engine::interpreter::{Fnv1a, ParseError};
+use crate::settings::Settings;
+use crate::SettingKey;
+
+use log::info;
+use std::cmp::Ordering;
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const BLOCK_START: &str = "# /// script";
+const BLOCK_END: &str = "# ///";
+const COMPLETE_MARKER: &str = ".flaunch-complete";
+
+/// Inline script metadata as described in PEP 723.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ScriptMetadata {
+    pub requires_python: Option<String>,
+    pub dependencies: Vec<String>,
+}
+
+/// Extracts the `# /// script` block of a python source file.
+/// Returns `Ok(None)` when the file does not contain such a block.
+pub fn parse_script_metadata(source: &str) -> Result<Option<ScriptMetadata>, String> {
+    let lines: Vec<&str> = source.lines().collect();
+    let mut starts = lines.iter().enumerate().filter(|(_, l)| **l == BLOCK_START);
+    let start = match starts.next() {
+        Some((index, _)) => index,
+        None => return Ok(None),
+    };
+    if starts.next().is_some() {
+        return Err("multiple `script` metadata blocks found".to_string());
+    }
+
+    // the closing line is the last `# ///` of the comment run following the start.
+    let run: Vec<&str> = lines[start + 1..]
+        .iter()
+        .take_while(|l| l.starts_with('#'))
+        .cloned()
+        .collect();
+    let end = run
+        .iter()
+        .rposition(|l| *l == BLOCK_END)
+        .ok_or_else(|| "unclosed `script` metadata block".to_string())?;
+
+    let mut content = String::new();
+    for line in &run[..end] {
+        match line.strip_prefix("# ").or_else(|| line.strip_prefix('#')) {
+            Some(stripped) => {
+                content.push_str(stripped);
+                content.push('\n');
+            }
+            None => return Err(format!("invalid metadata line {:?}", line)),
+        }
+    }
+
+    let table = content
+        .parse::<toml::Table>()
+        .map_err(|e| format!("invalid `script` metadata: {}", e))?;
+
+    let mut metadata = ScriptMetadata::default();
+    if let Some(requires) = table.get("requires-python") {
+        metadata.requires_python = requires.as_str().map(str::to_string);
+    }
+    if let Some(deps) = table.get("dependencies") {
+        let deps = deps
+            .as_array()
+            .ok_or_else(|| "`dependencies` should be an array".to_string())?;
+        for dep in deps {
+            let dep = dep
+                .as_str()
+                .ok_or_else(|| format!("dependency {} is not a string", dep))?;
+            metadata.dependencies.push(dep.to_string());
+        }
+    }
+    Ok(Some(metadata))
+}
+
+/// Whether `version`, like `3.11.2`, satisfies a `requires-python` specifier
+/// like `>=3.8, <4`. Knows the comparison operators of PEP 440, `==` and `!=`
+/// with a trailing `.*`, but not pre- or post-releases.
+pub fn python_satisfies(version: &str, specifier: &str) -> Result<bool, String> {
+    let invalid = || format!("invalid `requires-python` {:?}", specifier);
+    let running = release(version).ok_or_else(invalid)?;
+    for clause in specifier.split(',').map(str::trim) {
+        let split = clause
+            .find(|c: char| c.is_ascii_digit())
+            .ok_or_else(invalid)?;
+        let (operator, wanted) = (clause[..split].trim(), &clause[split..]);
+        if let Some(prefix) = wanted.strip_suffix(".*") {
+            let prefix = release(prefix).ok_or_else(invalid)?;
+            let matches = running.iter().take(prefix.len()).eq(prefix.iter());
+            let satisfied = match operator {
+                "==" => matches,
+                "!=" => !matches,
+                _ => return Err(invalid()),
+            };
+            if !satisfied {
+                return Ok(false);
+            }
+            continue;
+        }
+
+        let wanted = release(wanted).ok_or_else(invalid)?;
+        let ordering = compare(&running, &wanted);
+        let satisfied = match operator {
+            "==" => ordering == Ordering::Equal,
+            "!=" => ordering != Ordering::Equal,
+            "<" => ordering == Ordering::Less,
+            "<=" => ordering != Ordering::Greater,
+            ">" => ordering == Ordering::Greater,
+            ">=" => ordering != Ordering::Less,
+            // `~=3.8` is `>=3.8, ==3.*`
+            "~=" if wanted.len() > 1 => {
+                ordering != Ordering::Less
+                    && running
+                        .iter()
+                        .take(wanted.len() - 1)
+                        .eq(wanted[..wanted.len() - 1].iter())
+            }
+            _ => return Err(invalid()),
+        };
+        if !satisfied {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// the numbers of a version, `3.12.0rc1` is taken as `3.12.0`.
+fn release(version: &str) -> Option<Vec<u64>> {
+    version
+        .split('.')
+        .map(|part| {
+            let digits = part
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(part.len());
+            part[..digits].parse().ok()
+        })
+        .collect()
+}
+
+/// compares releases padded with zeros, `3.8` equals `3.8.0`.
+fn compare(a: &[u64], b: &[u64]) -> Ordering {
+    let component = |v: &[u64], i: usize| v.get(i).cloned().unwrap_or_default();
+    (0..a.len().max(b.len()))
+        .map(|i| component(a, i).cmp(&component(b, i)))
+        .find(|o| *o != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
+
+/// Builds and caches per-script package environments from a local wheelhouse
+/// or package index mirror. Installation never touches the public index
+/// unless a mirror is explicitly configured.
+#[derive(Debug, Clone)]
+pub struct DependencyResolver {
+    wheelhouse: Option<PathBuf>,
+    package_index: Option<String>,
+    cache_dir: PathBuf,
+    python: PathBuf,
+    python_version: String,
+}
+
+impl DependencyResolver {
+    pub fn new(settings: &Settings<SettingKey>, python: PathBuf, python_version: String) -> Self {
+        let non_empty = |key| {
+            settings
+                .get_str(key)
+                .filter(|s| !s.is_empty())
+                .map(str::to_string)
+        };
+
+        let mut cache_dir =
+            app_dirs::get_app_root(app_dirs::AppDataType::UserCache, &app_meta::APP_INFO)
+                .unwrap_or_else(|_| std::env::temp_dir());
+        cache_dir.push("python_envs");
+
+        DependencyResolver {
+            wheelhouse: non_empty(SettingKey::PythonWheelhouse).map(PathBuf::from),
+            package_index: non_empty(SettingKey::PythonPackageIndex),
+            cache_dir,
+            python,
+            python_version,
+        }
+    }
+
+    /// Reads the inline metadata of `source` and makes sure its dependencies
+    /// are installed. Returns the directory that needs to be added to `sys.path`,
+    /// or `None` when the script does not declare any dependencies.
+    pub fn prepare(&self, source: &str, file: &Path) -> Result<Option<PathBuf>, ParseError> {
+        let error = |message: String, traceback: String| ParseError {
+            filename: file.to_string_lossy().to_string(),
+            message,
+            traceback,
+        };
+
+        let metadata = match parse_script_metadata(source) {
+            Ok(Some(metadata)) => metadata,
+            Ok(None) => return Ok(None),
+            Err(e) => return Err(error(e, String::new())),
+        };
+        if let Some(requires) = &metadata.requires_python {
+            // `py.version()` is followed by the build and compiler
+            let running = self
+                .python_version
+                .split_whitespace()
+                .next()
+                .unwrap_or_default();
+            match python_satisfies(running, requires) {
+                Ok(true) => {}
+                Ok(false) => {
+                    return Err(error(
+                        format!("requires python {}, running {}", requires, running),
+                        String::new(),
+                    ))
+                }
+                Err(e) => return Err(error(e, String::new())),
+            }
+        }
+        if metadata.dependencies.is_empty() {
+            return Ok(None);
+        }
+
+        if self.wheelhouse.is_none() && self.package_index.is_none() {
+            return Err(error(
+                format!(
+                    "unmet dependencies {}: no python wheelhouse or package index configured",
+                    metadata.dependencies.join(", ")
+                ),
+                String::new(),
+            ));
+        }
+
+        let env_dir = self
+            .cache_dir
+            .join(format!("{:016x}", self.env_key(&metadata)));
+        let site_packages = env_dir.join("site-packages");
+        if env_dir.join(COMPLETE_MARKER).exists() {
+            return Ok(Some(site_packages));
+        }
+
+        info!(
+            "installing {} for {}",
+            metadata.dependencies.join(", "),
+            file.to_string_lossy()
+        );
+        match self.install(&metadata.dependencies, &site_packages) {
+            Ok(()) => {
+                if let Err(e) = std::fs::write(env_dir.join(COMPLETE_MARKER), "") {
+                    warn!("could not mark {:?} as complete: {}", env_dir, e);
+                }
+                Ok(Some(site_packages))
+            }
+            Err(traceback) => {
+                let _ = std::fs::remove_dir_all(&env_dir);
+                Err(error(
+                    format!("unmet dependencies {}", metadata.dependencies.join(", ")),
+                    traceback,
+                ))
+            }
+        }
+    }
+
+    /// Environments outlive flaunchd, the key must not change with its build.
+    fn env_key(&self, metadata: &ScriptMetadata) -> u64 {
+        let mut dependencies = metadata.dependencies.clone();
+        dependencies.sort();
+
+        let wheelhouse = self.wheelhouse.as_ref().map(|w| w.to_string_lossy());
+        let parts = std::iter::once(Some(self.python_version.as_str()))
+            .chain(std::iter::once(wheelhouse.as_deref()))
+            .chain(std::iter::once(self.package_index.as_deref()))
+            .chain(dependencies.iter().map(|d| Some(d.as_str())));
+        let mut hasher = Fnv1a::default();
+        for part in parts {
+            // separated, and `None` distinct from `Some("")`
+            match part {
+                Some(part) => {
+                    hasher.write(part.as_bytes());
+                    hasher.write(&[0]);
+                }
+                None => hasher.write(&[1]),
+            }
+        }
+        hasher.finish()
+    }
+
+    fn install(&self, dependencies: &[String], target: &Path) -> Result<(), String> {
+        std::fs::create_dir_all(target).map_err(|e| e.to_string())?;
+
+        let mut command = Command::new(&self.python);
+        command
+            .args(["-m", "pip", "install"])
+            .args(["--disable-pip-version-check", "--no-input", "--quiet"])
+            .arg("--target")
+            .arg(target);
+        if let Some(wheelhouse) = &self.wheelhouse {
+            command.arg("--find-links").arg(wheelhouse);
+        }
+        match &self.package_index {
+            Some(index) => command.args(["--index-url", index]),
+            None => command.arg("--no-index"),
+        };
+        command.args(dependencies);
+
+        let output = command
+            .output()
+            .map_err(|e| format!("could not run {:?}: {}", self.python, e))?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(String::from_utf8_lossy(&output.stderr).to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_metadata_block() {
+        assert_eq!(parse_script_metadata("print('hoi')\n"), Ok(None));
+    }
+
+    #[test]
+    fn parse_metadata_block() {
+        let metadata = parse_script_metadata(concat!(
+            "# /// script\n",
+            "# requires-python = \">=3.8\"\n",
+            "# dependencies = [\n",
+            "#   \"requests<3\",\n",
+            "#   \"rich\",\n",
+            "# ]\n",
+            "# ///\n",
+            "import requests\n"
+        ))
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(metadata.requires_python, Some(">=3.8".to_string()));
+        assert_eq!(metadata.dependencies, vec!["requests<3", "rich"]);
+    }
+
+    #[test]
+    fn unclosed_metadata_block() {
+        assert!(parse_script_metadata("# /// script\n# dependencies = []\nimport os\n").is_err());
+    }
+
+    #[test]
+    fn env_key_is_stable() {
+        let resolver = DependencyResolver {
+            wheelhouse: None,
+            package_index: Some("http://mirror/simple".to_string()),
+            cache_dir: PathBuf::new(),
+            python: PathBuf::new(),
+            python_version: "3.11".to_string(),
+        };
+        let metadata = ScriptMetadata {
+            dependencies: vec!["rich".to_string(), "requests<3".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(resolver.env_key(&metadata), 0x03a4884468d469f4);
+    }
+
+    #[test]
+    fn python_version_specifiers() {
+        for (specifier, satisfied) in [
+            (">=3.8", true),
+            (">=3.8, <4", true),
+            (">3.11", true),
+            ("<3.11.2", false),
+            ("<=3.11", false),
+            ("==3.11.2", true),
+            ("==3.11", false),
+            ("==3.11.*", true),
+            ("!=3.11.*", false),
+            ("==3.1.*", false),
+            ("~=3.9", true),
+            ("~=3.11.4", false),
+            ("~=3.10.0", false),
+            (">=3.12rc1", false),
+        ] {
+            assert_eq!(
+                python_satisfies("3.11.2", specifier),
+                Ok(satisfied),
+                "{}",
+                specifier
+            );
+        }
+        for specifier in ["3.8", "=>3.8", ">=3.*", "~=3", ">= three"] {
+            assert!(
+                python_satisfies("3.11.2", specifier).is_err(),
+                "{}",
+                specifier
+            );
+        }
+    }
+}
@@ -0,0 +1,142 @@
+extern crate pyo3;
+
+use crate::logging::*;
+
+use once_cell::sync::OnceCell;
+use pyo3::Python;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::sync::Mutex;
+use tokio::sync::oneshot;
+
+type Job = Box<dyn FnOnce(Python) + Send>;
+
+/// Runs all python work on one dedicated thread.
+///
+/// Jobs are queued and executed in order while holding the GIL, so
+/// callers never acquire the GIL on their own thread. This keeps long running
+/// scripts from stalling the tokio runtime, and keeps thread bound python
+/// state, like the asyncio event loop, on a single thread.
+///
+/// All python work is serialized: while a python script or notebook runs,
+/// other python calls and the loading of `.py` and `.ipynb` files wait for
+/// it to return. Other interpreters are not affected.
+///
+/// A job that panics fails only its own caller. Should the thread stop
+/// anyway, it is started again for the next job.
+pub struct PyExecutor {
+    queue: Mutex<mpsc::Sender<Job>>,
+}
+
+impl PyExecutor {
+    fn start() -> Self {
+        PyExecutor {
+            queue: Mutex::new(Self::spawn_worker()),
+        }
+    }
+
+    fn spawn_worker() -> mpsc::Sender<Job> {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        std::thread::Builder::new()
+            .name("flaunch-python".to_string())
+            .spawn(move || {
+                for job in receiver {
+                    let gil = Python::acquire_gil();
+                    job(gil.python());
+                }
+            })
+            .expect("could not start python executor thread");
+        sender
+    }
+
+    /// Queues `job` and returns a future resolving to its result, the
+    /// awaiting task does not block while the job runs. A panic of `job` is
+    /// resumed when the future is polled.
+    pub fn run<F, R>(&self, job: F) -> impl Future<Output = R>
+    where
+        F: FnOnce(Python) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (sender, receiver) = oneshot::channel();
+        self.queue(Box::new(move |py| {
+            let _ = sender.send(panic::catch_unwind(AssertUnwindSafe(|| job(py))));
+        }));
+
+        async move {
+            match receiver.await {
+                Ok(Ok(result)) => result,
+                Ok(Err(payload)) => panic::resume_unwind(payload),
+                Err(_) => panic!("python executor thread stopped during the job"),
+            }
+        }
+    }
+
+    /// Queues `job` and blocks the current thread until it completed.
+    /// Must not be called from within an async context. A panic of `job`
+    /// is resumed on the calling thread.
+    pub fn run_blocking<F, R>(&self, job: F) -> R
+    where
+        F: FnOnce(Python) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (sender, receiver) = mpsc::sync_channel(1);
+        self.queue(Box::new(move |py| {
+            let _ = sender.send(panic::catch_unwind(AssertUnwindSafe(|| job(py))));
+        }));
+
+        match receiver.recv() {
+            Ok(Ok(result)) => result,
+            Ok(Err(payload)) => panic::resume_unwind(payload),
+            Err(_) => panic!("python executor thread stopped during the job"),
+        }
+    }
+
+    fn queue(&self, job: Job) {
+        let mut queue = self.queue.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(mpsc::SendError(job)) = queue.send(job) {
+            warn!("python executor thread stopped, starting a new one");
+            *queue = Self::spawn_worker();
+            queue
+                .send(job)
+                .expect("python executor thread stopped right away");
+        }
+    }
+}
+
+pub fn executor() -> &'static PyExecutor {
+    EXECUTOR.get_or_init(PyExecutor::start)
+}
+
+static EXECUTOR: OnceCell<PyExecutor> = OnceCell::new();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jobs_run_on_executor_thread() {
+        let thread = executor().run_blocking(|_| std::thread::current().name().map(str::to_string));
+        assert_eq!(thread, Some("flaunch-python".to_string()));
+    }
+
+    #[test]
+    fn panics_fail_only_their_job() {
+        let panicked = panic::catch_unwind(|| executor().run_blocking(|_| panic!("broken job")));
+        assert_eq!(
+            panicked.unwrap_err().downcast_ref::<&str>(),
+            Some(&"broken job")
+        );
+        let major = executor().run_blocking(|py| py.version_info().major);
+        assert_eq!(major, 3);
+    }
+
+    #[tokio::test]
+    async fn run_is_awaitable() {
+        let (major, thread) = executor()
+            .run(|py| (py.version_info().major, std::thread::current().id()))
+            .await;
+        assert_eq!(major, 3);
+        assert_ne!(thread, std::thread::current().id());
+    }
+}
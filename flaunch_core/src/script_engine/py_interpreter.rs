@@ -1,12 +1,17 @@
 extern crate pyo3;
 
-use std::any::TypeId;
 use std::collections::HashMap;
 
 use crate::script_engine::interpreter::*;
+use crate::script_engine::py_dependencies::DependencyResolver;
+use crate::script_engine::py_executor::executor;
 use crate::script_engine::*;
+use crate::settings::Settings;
+use crate::SettingKey;
 
-use log::info;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use log::{info, warn};
 
 use pyo3::types::*;
 pub use pyo3::{
@@ -17,70 +22,233 @@ pub use pyo3::{
 #[derive(Debug)]
 pub struct PyInterpreter {
     annotation_mod: Py<PyModule>,
+    dependencies: DependencyResolver,
 }
 
 impl Default for PyInterpreter {
     fn default() -> Self {
-        let gil = Python::acquire_gil();
-        let py = gil.python();
-        info!("python version = {}", py.version());
-        let module = PyModule::from_code(
-            py,
-            &include_str!("py_annotation.py"),
-            "py_annotation.py",
-            "py_annotation",
-        )
-        .unwrap();
+        PyInterpreter::new(&crate::load_settings())
+    }
+}
+
+impl PyInterpreter {
+    /// `settings` configure where script dependencies are installed from.
+    pub fn new(settings: &Settings<SettingKey>) -> Self {
+        let (module, python, version) = executor().run_blocking(|py| {
+            info!("python version = {}", py.version());
+            let module = PyModule::from_code(
+                py,
+                include_str!("py_annotation.py"),
+                "py_annotation.py",
+                "py_annotation",
+            )
+            .unwrap();
+            (
+                module.into_py(py),
+                base_executable(py),
+                py.version().to_string(),
+            )
+        });
         PyInterpreter {
-            annotation_mod: module.into_py(gil.python()),
+            annotation_mod: module,
+            dependencies: DependencyResolver::new(settings, python, version),
+        }
+    }
+}
+
+/// path of the python executable belonging to the embedded interpreter.
+/// used to run pip for installing script dependencies.
+fn base_executable(py: Python) -> PathBuf {
+    let version = py.version_info();
+    let prefix = py
+        .import("sys")
+        .and_then(|sys| sys.getattr("base_prefix"))
+        .map(|p| p.to_string())
+        .unwrap_or_default();
+
+    let mut executable = PathBuf::from(prefix);
+    if cfg!(target_os = "windows") {
+        executable.push("python.exe");
+    } else {
+        executable.push("bin");
+        executable.push(format!("python{}.{}", version.major, version.minor));
+    }
+    executable
+}
+
+/// Runs `job` with `site_packages` in front of `sys.path`. Afterwards
+/// `sys.path` is restored and the modules imported from `site_packages` are
+/// evicted, so scripts never see the dependencies of other scripts. This
+/// also happens when `job` panics, the executor thread runs the next job.
+fn with_dependencies<R>(
+    py: Python,
+    site_packages: Option<&Path>,
+    job: impl FnOnce() -> R,
+) -> PyResult<R> {
+    let site_packages = match site_packages {
+        Some(dir) => dir.to_string_lossy().to_string(),
+        None => return Ok(job()),
+    };
+    let sys = py.import("sys")?;
+    let saved = sys.getattr("path")?;
+    let path = PyList::new(py, saved.iter()?.collect::<PyResult<Vec<_>>>()?);
+    path.insert(0, &site_packages)?;
+    sys.setattr("path", path)?;
+
+    let _restore = RestoreSysPath {
+        sys,
+        saved,
+        site_packages,
+    };
+    Ok(job())
+}
+
+struct RestoreSysPath<'p> {
+    sys: &'p PyModule,
+    saved: &'p PyAny,
+    site_packages: String,
+}
+
+impl RestoreSysPath<'_> {
+    fn restore(&self) -> PyResult<()> {
+        self.sys.setattr("path", self.saved)?;
+        let modules = self.sys.getattr("modules")?.downcast::<PyDict>()?;
+        let imported: Vec<&PyAny> = modules
+            .iter()
+            .filter(|(_, module)| {
+                module
+                    .getattr("__file__")
+                    .map(|file| file.to_string().starts_with(&self.site_packages))
+                    .unwrap_or_default()
+            })
+            .map(|(name, _)| name)
+            .collect();
+        for name in imported {
+            modules.del_item(name)?;
         }
+        Ok(())
+    }
+}
+
+impl Drop for RestoreSysPath<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = self.restore() {
+            warn!("could not restore sys.path: {}", e);
+        }
+    }
+}
+
+impl PyInterpreter {
+    /// installs the dependencies of the file, which may block, and returns
+    /// the job parsing it on the python thread.
+    fn prepare(
+        &self,
+        content: &[u8],
+        file: &Path,
+    ) -> Result<impl FnOnce(Python) -> ParseResult + Send + 'static, ParseResult> {
+        let source = String::from_utf8_lossy(content).to_string();
+
+        let site_packages = match self.dependencies.prepare(&source, file) {
+            Ok(site_packages) => site_packages,
+            Err(e) => {
+                info!("err {:?}", e);
+                return Err((Vec::new(), Vec::new(), vec![e]));
+            }
+        };
+
+        let helpers = self.annotation_mod.clone();
+        let file = file.to_path_buf();
+        Ok(move |py: Python| {
+            let site_packages = site_packages.as_deref();
+            with_dependencies(py, site_packages, || {
+                parse_module(py, helpers, &source, &file, site_packages)
+            })
+            .unwrap_or_else(|e| {
+                let error = ParseError {
+                    filename: file.to_string_lossy().to_string(),
+                    message: e.pvalue(py).to_string(),
+                    traceback: String::new(),
+                };
+                (Vec::new(), Vec::new(), vec![error])
+            })
+        })
     }
 }
 
 impl Interpreter for PyInterpreter {
     fn parse(&self, content: &[u8], file: &Path) -> ParseResult {
-        let mut scripts = Vec::new();
-        let mut callables: Vec<(u64, Arc<dyn Callable>)> = Vec::new();
-        let mut errors = Vec::new();
+        match self.prepare(content, file) {
+            Ok(job) => executor().run_blocking(job),
+            Err(result) => result,
+        }
+    }
 
-        let as_str = std::str::from_utf8(content).unwrap();
+    fn parse_async(
+        &'static self,
+        content: Vec<u8>,
+        file: PathBuf,
+    ) -> BoxFuture<'static, ParseResult> {
+        async move {
+            match tokio::task::spawn_blocking(move || self.prepare(&content, &file)).await {
+                Ok(Ok(job)) => executor().run(job).await,
+                Ok(Err(result)) => result,
+                Err(e) => {
+                    warn!("parsing failed {}", e);
+                    ParseResult::default()
+                }
+            }
+        }
+        .boxed()
+    }
+}
 
-        let gil = Python::acquire_gil();
-        let py = gil.python();
+fn parse_module(
+    py: Python,
+    helpers: Py<PyModule>,
+    source: &str,
+    file: &Path,
+    site_packages: Option<&Path>,
+) -> ParseResult {
+    let mut scripts = Vec::new();
+    let mut callables: Vec<(u64, Arc<dyn Callable>)> = Vec::new();
+    let mut errors = Vec::new();
 
-        let globals = pyo3::types::PyDict::new(py);
-        if let Err(e) = Python::run(py, as_str, None, Some(globals)) {
-            let error_tuple: ParseError = ParseError {
-                filename: file.to_string_lossy().to_string(),
-                message: e.pvalue(py).to_string(),
-                traceback: e.ptraceback(py).to_object(py).to_string(),
-            };
-            errors.push(error_tuple);
-            info!("err {:?}", errors);
-        } else {
-            if let Some(func_call) = globals.get_item("flaunch_callables") {
-                let mut py_call = PyCallable::default();
-                for (key, value) in func_call.downcast::<PyDict>().unwrap() {
-                    let descriptions = value.downcast::<PyDict>().unwrap();
-                    if let Ok(func) = key.downcast::<PyFunction>() {
-                        if let Ok(name) = func.getattr("__name__") {
-                            let script = create_script_object(name, file, func, descriptions);
-                            py_call.insert(script.get_key().unwrap(), func.to_object(py));
-                            scripts.push(script);
-                        }
-                    }
+    let globals = pyo3::types::PyDict::new(py);
+    if let Err(e) = Python::run(py, source, Some(globals), None) {
+        let error_tuple: ParseError = ParseError {
+            filename: file.to_string_lossy().to_string(),
+            message: e.pvalue(py).to_string(),
+            traceback: format_traceback(py, &e),
+        };
+        errors.push(error_tuple);
+        info!("err {:?}", errors);
+    } else if let Some(func_call) = globals.get_item("flaunch_callables") {
+        let func_call = func_call.downcast::<PyDict>().unwrap();
+        let mut py_call = PyCallable::new(helpers.clone(), site_packages.map(Path::to_path_buf));
+        for (key, value) in func_call {
+            let descriptions = value.downcast::<PyDict>().unwrap();
+            if let Ok(func) = key.downcast::<PyFunction>() {
+                if let Ok(name) = func.getattr("__name__") {
+                    let script =
+                        create_script_object(helpers.as_ref(py), name, file, func, descriptions);
+                    py_call.insert(script.get_key().unwrap(), func.to_object(py));
+                    scripts.push(script);
                 }
-                let keys = py_call.keys();
-                let rc: Arc<dyn Callable> = Arc::new(py_call);
-                callables = keys.iter().map(|key| (key.clone(), rc.clone())).collect();
             }
         }
+        // the registry is shared by all files importing py_annotation.
+        func_call.clear();
 
-        (scripts, callables, errors)
+        let keys = py_call.keys();
+        let rc: Arc<dyn Callable> = Arc::new(py_call);
+        callables = keys.iter().map(|key| (*key, rc.clone())).collect();
     }
+
+    (scripts, callables, errors)
 }
 
 fn create_script_object(
+    helpers: &PyModule,
     name: &PyAny,
     file: &Path,
     func: &PyFunction,
@@ -98,9 +266,31 @@ fn create_script_object(
         if let Some(des) = descriptions.get_item(key) {
             description = des.to_string().trim().to_string();
         }
-        script
-            .arguments
-            .push((key.to_string(), get_flaunch_type(value), description));
+        let mut typ = get_flaunch_type(value);
+        // `Literal` and `Enum` annotations list the values to choose from.
+        let choices = helpers
+            .call1("_choices", (value,))
+            .and_then(|c| c.extract::<Option<(String, Vec<String>)>>());
+        if let Ok(Some((choice_type, choices))) = choices {
+            typ = match choice_type.as_str() {
+                "int" => ArgumentType::Int(0),
+                _ => ArgumentType::String(String::new()),
+            };
+            let constraint = ArgumentConstraint {
+                choices,
+                ..Default::default()
+            };
+            script.constraints.insert(key.to_string(), constraint);
+        }
+        script.arguments.push((key.to_string(), typ, description));
+    }
+    // `tags` is only taken as tags when the function has no such argument.
+    if let Some(tags) = descriptions.get_item("tags") {
+        if !annotations.contains("tags").unwrap_or_default() {
+            if let Ok(tags) = tags.downcast::<PyList>() {
+                script.tags = tags.iter().map(|t| t.to_string()).collect();
+            }
+        }
     }
     if func.hasattr("__doc__").unwrap() {
         let doc = func.getattr("__doc__").unwrap().to_string();
@@ -122,12 +312,24 @@ fn get_flaunch_type(any: &PyAny) -> ArgumentType {
     ArgumentType::NotSpecified
 }
 
-#[derive(Default, Debug)]
+#[derive(Debug)]
 pub struct PyCallable {
     callables: HashMap<u64, PyObject>,
+    /// the `py_annotation` module, providing the event loop and `Progress`.
+    helpers: Py<PyModule>,
+    /// the dependencies of the file, on `sys.path` during calls only.
+    site_packages: Option<PathBuf>,
 }
 
 impl PyCallable {
+    pub fn new(helpers: Py<PyModule>, site_packages: Option<PathBuf>) -> Self {
+        PyCallable {
+            callables: HashMap::new(),
+            helpers,
+            site_packages,
+        }
+    }
+
     pub fn insert(&mut self, key: u64, value: PyObject) {
         self.callables.insert(key, value);
     }
@@ -136,30 +338,182 @@ impl PyCallable {
         self.callables.keys().cloned().collect()
     }
 }
-impl Callable for PyCallable {
-    fn call(&self, key: u64, args: &[Box<dyn Any>]) -> Result<(), CallError> {
-        if let Some(obj) = self.callables.get(&key) {
-            let gil = Python::acquire_gil();
-            let py = gil.python();
-
-            if args.len() == 0 {
-                obj.call0(py).unwrap();
-            } else {
-                let mut py_arguments = Vec::new();
-                for arg in args {
-                    let typ = (&*arg).type_id();
-                    if typ == TypeId::of::<String>() {
-                        py_arguments.push(arg.downcast_ref::<String>().unwrap());
-                    } else {
-                        return Err(CallError::WrongArguments);
-                    }
-                }
-                let py_args = PyTuple::new(py, py_arguments);
-                obj.call1(py, py_args).unwrap();
+
+/// Drives coroutines and (async) generators returned by a script to
+/// completion. Yielded values are forwarded as events.
+fn complete(
+    py: Python,
+    helpers: &PyModule,
+    result: &PyAny,
+    events: &CallEvents,
+) -> PyResult<CallOutput> {
+    let inspect = py.import("inspect")?;
+    let is = |check: &str| -> PyResult<bool> { inspect.call1(check, (result,))?.extract() };
+
+    if is("iscoroutine")? {
+        let value = helpers.call1("_run_coroutine", (result,))?;
+        return Ok(to_call_output(value));
+    }
+
+    if is("isgenerator")? {
+        for item in result.iter()? {
+            emit(py, helpers, item?, events)?;
+        }
+        return Ok(CallOutput::Nothing);
+    }
+
+    if is("isasyncgen")? {
+        loop {
+            let (more, item): (bool, &PyAny) = helpers.call1("_anext", (result,))?.extract()?;
+            if !more {
+                return Ok(CallOutput::Nothing);
             }
-            Ok(())
+            emit(py, helpers, item, events)?;
+        }
+    }
+
+    Ok(to_call_output(result))
+}
+
+fn emit(py: Python, helpers: &PyModule, item: &PyAny, events: &CallEvents) -> PyResult<()> {
+    let progress = helpers.getattr("Progress")?;
+    let is_progress: bool = py
+        .import("builtins")?
+        .call1("isinstance", (item, progress))?
+        .extract()?;
+    let event = if is_progress {
+        CallEvent::Progress(
+            item.getattr("fraction")?.extract()?,
+            item.getattr("message")?.to_string(),
+        )
+    } else {
+        CallEvent::Output(item.to_string())
+    };
+    // the caller might not be interested in events
+    let _ = events.send(event);
+    Ok(())
+}
+
+fn to_call_output(value: &PyAny) -> CallOutput {
+    if value.is_none() {
+        CallOutput::Nothing
+    } else {
+        CallOutput::Text(value.to_string())
+    }
+}
+
+/// Owned copy of a call argument that can be moved to the python thread.
+pub(crate) enum PyArgument {
+    Str(String),
+    Int(i32),
+    Uint(u32),
+    Float(f32),
+    Bool(bool),
+    List(Vec<String>),
+}
+
+impl PyArgument {
+    pub(crate) fn from_any(arg: &dyn Any) -> Option<Self> {
+        if let Some(s) = arg.downcast_ref::<String>() {
+            Some(PyArgument::Str(s.clone()))
+        } else if let Some(i) = arg.downcast_ref::<i32>() {
+            Some(PyArgument::Int(*i))
+        } else if let Some(u) = arg.downcast_ref::<u32>() {
+            Some(PyArgument::Uint(*u))
+        } else if let Some(f) = arg.downcast_ref::<f32>() {
+            Some(PyArgument::Float(*f))
+        } else if let Some(b) = arg.downcast_ref::<bool>() {
+            Some(PyArgument::Bool(*b))
         } else {
-            return Err(CallError::KeyNotPresent(key));
+            arg.downcast_ref::<Vec<String>>()
+                .map(|l| PyArgument::List(l.clone()))
+        }
+    }
+}
+
+impl ToPyObject for PyArgument {
+    fn to_object(&self, py: Python) -> PyObject {
+        match self {
+            PyArgument::Str(s) => s.to_object(py),
+            PyArgument::Int(i) => i.to_object(py),
+            PyArgument::Uint(u) => u.to_object(py),
+            PyArgument::Float(f) => f.to_object(py),
+            PyArgument::Bool(b) => b.to_object(py),
+            PyArgument::List(l) => l.to_object(py),
+        }
+    }
+}
+
+pub(crate) fn format_traceback(py: Python, e: &PyErr) -> String {
+    py.import("traceback")
+        .and_then(|tb| {
+            tb.call1(
+                "format_exception",
+                (e.ptype(py), e.pvalue(py), e.ptraceback(py)),
+            )
+        })
+        .and_then(|lines| lines.extract::<Vec<String>>())
+        .map(|lines| lines.concat())
+        .unwrap_or_default()
+}
+
+impl PyCallable {
+    /// the job calling the script on the python thread.
+    fn job(
+        &self,
+        key: u64,
+        args: &[Box<dyn Any + Send>],
+        events: &CallEvents,
+    ) -> Result<impl FnOnce(Python) -> Result<CallOutput, CallError> + Send + 'static, CallError>
+    {
+        let obj = self
+            .callables
+            .get(&key)
+            .cloned()
+            .ok_or(CallError::KeyNotPresent(key))?;
+
+        let mut py_arguments = Vec::new();
+        for arg in args {
+            py_arguments.push(PyArgument::from_any(arg.as_ref()).ok_or(CallError::WrongArguments)?);
+        }
+
+        let helpers = self.helpers.clone();
+        let site_packages = self.site_packages.clone();
+        let events = events.clone();
+        Ok(move |py: Python| {
+            with_dependencies(py, site_packages.as_deref(), || {
+                obj.call1(py, PyTuple::new(py, py_arguments))
+                    .and_then(|result| complete(py, helpers.as_ref(py), result.as_ref(py), &events))
+            })
+            .and_then(|result| result)
+            .map_err(|e| CallError::Exception {
+                message: e.pvalue(py).to_string(),
+                traceback: format_traceback(py, &e),
+            })
+        })
+    }
+}
+
+impl Callable for PyCallable {
+    fn call(
+        &self,
+        key: u64,
+        args: &[Box<dyn Any + Send>],
+        events: &CallEvents,
+    ) -> Result<CallOutput, CallError> {
+        let job = self.job(key, args, events)?;
+        executor().run_blocking(job)
+    }
+
+    fn call_async(
+        self: Arc<Self>,
+        key: u64,
+        args: Vec<Box<dyn Any + Send>>,
+        events: CallEvents,
+    ) -> BoxFuture<'static, Result<CallOutput, CallError>> {
+        match self.job(key, &args, &events) {
+            Ok(job) => executor().run(job).boxed(),
+            Err(e) => futures::future::ready(Err(e)).boxed(),
         }
     }
 }
@@ -171,9 +525,14 @@ unsafe impl Sync for PyInterpreter {}
 mod tests {
     use super::*;
 
+    /// without a wheelhouse or package index, whatever the machine has set.
+    fn interpreter() -> PyInterpreter {
+        PyInterpreter::new(&Settings::new(&crate::app_setting_defaults()))
+    }
+
     #[test]
     fn parse_error_added() {
-        let py_interpreter = PyInterpreter::default();
+        let py_interpreter = interpreter();
         let result = py_interpreter.parse(
             "adsfasdf".as_bytes(),
             &std::path::PathBuf::from("/my/path/sven.py"),
@@ -186,12 +545,14 @@ mod tests {
 
     #[test]
     fn parse_py_files() {
-        let py_interpreter = PyInterpreter::default();
+        let py_interpreter = interpreter();
         let (scripts, callables, errors) = py_interpreter.parse(
             concat!(
-                "@flaunch(wat=\"Print Statement\", number=\"Given Number\")",
-                "def test_123(wat):\n\t\"\"\"this is a test",
+                "from py_annotation import *\n",
+                "@flaunch(wat=\"Print Statement\", number=\"Given Number\")\n",
+                "def test_123(wat: str):\n\t\"\"\"this is a test",
                 " doc\"\"\"\n\tprint(\"hoi\")\n",
+                "@flaunch(tags=[\"deploy\", \"ops\"])\n",
                 "def test_2():\n\tprint(\"test2\")\n"
             )
             .as_bytes(),
@@ -201,15 +562,19 @@ mod tests {
         assert_eq!(scripts[0].name, "test_123".to_string());
         assert_eq!(scripts[0].file, PathBuf::from("/my/path/sven.py"));
         assert_eq!(scripts[0].description, "this is a test doc".to_string());
+        assert_eq!(scripts[0].arguments[0].0, "wat".to_string());
         assert_eq!(
-            scripts[0].argument_type[0],
-            ArgumentType::String("wat".to_string())
+            scripts[0].arguments[0].1,
+            ArgumentType::String(String::new())
         );
+        assert_eq!(scripts[0].arguments[0].2, "Print Statement".to_string());
 
         assert_eq!(scripts[1].name, "test_2".to_string());
         assert_eq!(scripts[1].file, PathBuf::from("/my/path/sven.py"));
         assert!(scripts[1].description.is_empty());
-        assert!(scripts[1].argument_type.is_empty());
+        assert!(scripts[1].arguments.is_empty());
+        assert!(scripts[0].tags.is_empty());
+        assert_eq!(scripts[1].tags, vec!["deploy", "ops"]);
 
         assert_eq!(callables.len(), 2);
         assert!(callables
@@ -222,11 +587,140 @@ mod tests {
             .is_some());
     }
 
+    #[test]
+    fn literal_and_enum_arguments_have_choices() {
+        let (scripts, _, errors) = interpreter().parse(
+            concat!(
+                "import enum\n",
+                "from typing import Literal\n",
+                "from py_annotation import *\n",
+                "class Color(enum.Enum):\n",
+                "\tred = 1\n",
+                "\tgreen = 2\n",
+                "@flaunch()\n",
+                "def paint(color: Color, coats: Literal[1, 2], finish: Literal['matt', 'gloss'], name: str):\n",
+                "\tpass\n"
+            )
+            .as_bytes(),
+            &PathBuf::from("/my/path/paint.py"),
+        );
+        assert!(errors.is_empty());
+        let script = &scripts[0];
+        let types: Vec<_> = script.arguments.iter().map(|a| a.1.clone()).collect();
+        assert_eq!(
+            types,
+            vec![
+                ArgumentType::String(String::new()),
+                ArgumentType::Int(0),
+                ArgumentType::String(String::new()),
+                ArgumentType::String(String::new()),
+            ]
+        );
+        let choices = |name: &str| script.constraints.get(name).map(|c| c.choices.clone());
+        assert_eq!(
+            choices("color"),
+            Some(vec!["red".to_string(), "green".to_string()])
+        );
+        assert_eq!(
+            choices("coats"),
+            Some(vec!["1".to_string(), "2".to_string()])
+        );
+        assert_eq!(
+            choices("finish"),
+            Some(vec!["matt".to_string(), "gloss".to_string()])
+        );
+        assert_eq!(choices("name"), None);
+    }
+
+    #[test]
+    fn unmet_dependencies_are_parse_errors() {
+        let py_interpreter = interpreter();
+        let result = py_interpreter.parse(
+            concat!(
+                "# /// script\n",
+                "# dependencies = [\"flaunch-does-not-exist\"]\n",
+                "# ///\n",
+                "import flaunch_does_not_exist\n"
+            )
+            .as_bytes(),
+            &std::path::PathBuf::from("/my/path/sven.py"),
+        );
+
+        assert_eq!(result.0.len(), 0);
+        assert_eq!(result.2.len(), 1);
+        assert!(result.2[0].message.contains("flaunch-does-not-exist"));
+    }
+
+    #[test]
+    fn unsupported_python_is_parse_error() {
+        let result = interpreter().parse(
+            concat!(
+                "# /// script\n",
+                "# requires-python = \"<3\"\n",
+                "# ///\n",
+                "from py_annotation import *\n",
+                "@flaunch()\n",
+                "def legacy():\n\tpass\n",
+            )
+            .as_bytes(),
+            &std::path::PathBuf::from("/my/path/sven.py"),
+        );
+
+        assert_eq!(result.0.len(), 0);
+        assert_eq!(result.2.len(), 1);
+        assert!(result.2[0].message.starts_with("requires python <3"));
+    }
+
+    #[test]
+    fn dependencies_are_only_importable_by_their_script() {
+        let dir = std::env::temp_dir().join(format!("flaunch_site_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("flaunch_isolated.py"), "VALUE = 1\n").unwrap();
+
+        let (during, after, path_restored) = executor().run_blocking(move |py| {
+            let path = || {
+                py.eval("list(__import__('sys').path)", None, None)
+                    .unwrap()
+                    .to_string()
+            };
+            let before = path();
+            let during =
+                with_dependencies(py, Some(&dir), || py.import("flaunch_isolated").is_ok())
+                    .unwrap();
+            let after = py.import("flaunch_isolated").is_ok();
+            std::fs::remove_dir_all(dir).unwrap();
+            (during, after, before == path())
+        });
+        assert!(during);
+        assert!(!after);
+        assert!(path_restored);
+    }
+
+    #[test]
+    fn sys_path_is_restored_after_a_panic() {
+        let dir = std::env::temp_dir().join(format!("flaunch_site_panic_{}", std::process::id()));
+        let restored = executor().run_blocking(move |py| {
+            let path = || {
+                py.eval("list(__import__('sys').path)", None, None)
+                    .unwrap()
+                    .to_string()
+            };
+            let before = path();
+            let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                with_dependencies(py, Some(&dir), || panic!("broken script"))
+            }));
+            panicked.is_err() && before == path()
+        });
+        assert!(restored);
+    }
+
     #[test]
     fn keys_are_the_same() {
-        let py_interpreter = PyInterpreter::default();
+        let py_interpreter = interpreter();
         let (scripts, _callables, _errors) = py_interpreter.parse(
             concat!(
+                "from py_annotation import *\n",
+                "@flaunch()\n",
                 "def test_123(wat):\n\t\"\"\"this is a test",
                 " doc\"\"\"\n\tprint(\"hoi\")\n",
             )
@@ -235,4 +729,119 @@ mod tests {
         );
         assert_eq!(scripts[0].get_key(), scripts[0].get_key());
     }
+
+    fn call_only_script(
+        source: &str,
+        args: &[Box<dyn Any + Send>],
+    ) -> (Result<CallOutput, CallError>, Vec<CallEvent>) {
+        let py_interpreter = interpreter();
+        let (scripts, callables, errors) =
+            py_interpreter.parse(source.as_bytes(), &PathBuf::from("/my/path/sven.py"));
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let key = scripts[0].get_key().unwrap();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let result = callables[0].1.call(key, args, &tx);
+
+        let mut events = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            events.push(event);
+        }
+        (result, events)
+    }
+
+    #[test]
+    fn call_returns_value() {
+        let (result, events) = call_only_script(
+            concat!(
+                "from py_annotation import *\n",
+                "@flaunch()\n",
+                "def add(a: int, b: int):\n\treturn a + b\n",
+            ),
+            &[Box::new(1i32), Box::new(2i32)],
+        );
+        assert_eq!(result.unwrap(), CallOutput::Text("3".to_string()));
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn call_async_def() {
+        let (result, _) = call_only_script(
+            concat!(
+                "from py_annotation import *\n",
+                "import asyncio\n",
+                "@flaunch()\n",
+                "async def poll(name: str):\n",
+                "\tawait asyncio.sleep(0)\n",
+                "\treturn 'hello ' + name\n",
+            ),
+            &[Box::new("sven".to_string())],
+        );
+        assert_eq!(result.unwrap(), CallOutput::Text("hello sven".to_string()));
+    }
+
+    #[test]
+    fn call_generator_streams_events() {
+        let (result, events) = call_only_script(
+            concat!(
+                "from py_annotation import *\n",
+                "@flaunch()\n",
+                "def count():\n",
+                "\tyield 'first'\n",
+                "\tyield Progress(0.5, 'halfway')\n",
+                "\tyield 'last'\n",
+            ),
+            &[],
+        );
+        assert_eq!(result.unwrap(), CallOutput::Nothing);
+        assert_eq!(
+            events,
+            vec![
+                CallEvent::Output("first".to_string()),
+                CallEvent::Progress(0.5, "halfway".to_string()),
+                CallEvent::Output("last".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn call_async_generator_streams_events() {
+        let (result, events) = call_only_script(
+            concat!(
+                "from py_annotation import *\n",
+                "@flaunch()\n",
+                "async def count():\n",
+                "\tfor i in range(2):\n",
+                "\t\tyield i\n",
+            ),
+            &[],
+        );
+        assert_eq!(result.unwrap(), CallOutput::Nothing);
+        assert_eq!(
+            events,
+            vec![
+                CallEvent::Output("0".to_string()),
+                CallEvent::Output("1".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn call_exception_is_error() {
+        let (result, _) = call_only_script(
+            concat!(
+                "from py_annotation import *\n",
+                "@flaunch()\n",
+                "def fail():\n\traise ValueError('broken')\n",
+            ),
+            &[],
+        );
+        match result {
+            Err(CallError::Exception { message, traceback }) => {
+                assert_eq!(message, "broken");
+                assert!(traceback.contains("ValueError"));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
 }
@@ -0,0 +1,1014 @@
+use std::collections::HashMap;
+use std::hash::Hasher;
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+use std::sync::{Condvar, Mutex};
+
+use crate::app_meta;
+use crate::logging::*;
+use crate::script_engine::http_interpreter::to_text;
+use crate::script_engine::interpreter::*;
+use crate::script_engine::*;
+
+use quote::ToTokens;
+
+/// Exposes single file rust scripts, in the style of rust-script and
+/// cargo-script, as scripts.
+///
+/// The cargo manifest is embedded in a `---` front matter or in a
+/// `//! ```cargo` doc block. Functions annotated with `#[flaunch]` become
+/// scripts, otherwise `main` does, taking the fields of a `clap::Parser`
+/// struct as arguments.
+///
+/// Each file is compiled once, offline, into a cache keyed by its content.
+/// The compilation runs in the background, calls wait for it to finish.
+/// Compiler errors are logged and returned by the calls, they are not
+/// reported as parse errors. Once a file is built, the cached builds of its
+/// earlier versions are removed.
+#[derive(Debug, Clone)]
+pub struct RsInterpreter {
+    cache_dir: PathBuf,
+    builds: Arc<Mutex<Builds>>,
+}
+
+impl Default for RsInterpreter {
+    fn default() -> Self {
+        let mut cache_dir =
+            app_dirs::get_app_root(app_dirs::AppDataType::UserCache, &app_meta::APP_INFO)
+                .unwrap_or_else(|_| std::env::temp_dir());
+        cache_dir.push("rust_scripts");
+        RsInterpreter::with_cache_dir(cache_dir)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Builds {
+    /// builds in progress, by content key
+    running: HashMap<u64, Arc<Build>>,
+    /// content key of the last parse of each file
+    latest: HashMap<PathBuf, u64>,
+}
+
+/// The binary of one build, or its error message and compiler output.
+#[derive(Debug, Default)]
+struct Build {
+    binary: Mutex<Option<Result<PathBuf, (String, String)>>>,
+    finished: Condvar,
+}
+
+impl Build {
+    fn finish(&self, result: Result<PathBuf, (String, String)>) {
+        *self.binary.lock().unwrap_or_else(|e| e.into_inner()) = Some(result);
+        self.finished.notify_all();
+    }
+
+    /// Blocks until the build finished.
+    fn wait(&self) -> Result<PathBuf, CallError> {
+        let mut binary = self.binary.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            match &*binary {
+                Some(Ok(binary)) => return Ok(binary.clone()),
+                Some(Err((message, traceback))) => {
+                    return Err(CallError::Exception {
+                        message: message.clone(),
+                        traceback: traceback.clone(),
+                    })
+                }
+                None => {
+                    binary = self
+                        .finished
+                        .wait(binary)
+                        .unwrap_or_else(|e| e.into_inner())
+                }
+            }
+        }
+    }
+}
+
+/// How a script passes its arguments to the binary.
+#[derive(Debug, Clone, PartialEq)]
+enum Entry {
+    /// `main`, with the command line flag of every argument. `None` for positionals.
+    Main(Vec<Option<String>>),
+    /// `#[flaunch]` function, dispatched on its name by the generated `main`,
+    /// with its number of parameters.
+    Function(String, usize),
+}
+
+#[derive(Debug, Default)]
+struct RustScript {
+    manifest: String,
+    source: String,
+    scripts: Vec<(Script, Entry)>,
+}
+
+/// Splits the embedded manifest from the source. Front matter lines are
+/// blanked so compiler messages keep pointing at the right line.
+fn split_manifest(content: &str) -> (String, String) {
+    let mut manifest = String::new();
+    let mut source = String::new();
+    let mut in_front_matter = false;
+    let mut in_doc_block = false;
+
+    for (index, line) in content.lines().enumerate() {
+        let trimmed = line.trim();
+        if index == 0 && trimmed.starts_with("#!") && !trimmed.starts_with("#![") {
+            source.push('\n');
+            continue;
+        }
+        if source.trim().is_empty() && manifest.is_empty() && trimmed.starts_with("---") {
+            in_front_matter = true;
+            source.push('\n');
+            continue;
+        }
+        if in_front_matter {
+            if trimmed == "---" {
+                in_front_matter = false;
+            } else {
+                manifest.push_str(line);
+                manifest.push('\n');
+            }
+            source.push('\n');
+            continue;
+        }
+
+        if let Some(doc) = trimmed.strip_prefix("//!") {
+            let doc = doc.trim();
+            if doc == "```cargo" {
+                in_doc_block = true;
+            } else if in_doc_block && doc == "```" {
+                in_doc_block = false;
+            } else if in_doc_block {
+                manifest.push_str(doc);
+                manifest.push('\n');
+            }
+        }
+        source.push_str(line);
+        source.push('\n');
+    }
+    (manifest, source)
+}
+
+fn doc_comment(attrs: &[syn::Attribute]) -> String {
+    let mut lines = Vec::new();
+    let mut in_manifest = false;
+    for attr in attrs.iter().filter(|a| a.path().is_ident("doc")) {
+        if let syn::Meta::NameValue(syn::MetaNameValue {
+            value:
+                syn::Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Str(doc),
+                    ..
+                }),
+            ..
+        }) = &attr.meta
+        {
+            let line = doc.value().trim().to_string();
+            // the embedded manifest isn't part of the description
+            if line == "```cargo" {
+                in_manifest = true;
+            } else if in_manifest && line == "```" {
+                in_manifest = false;
+            } else if !in_manifest {
+                lines.push(line);
+            }
+        }
+    }
+    lines.join(" ").trim().to_string()
+}
+
+fn argument_type(ty: &syn::Type) -> ArgumentType {
+    let ty = ty.to_token_stream().to_string().replace(' ', "");
+    let ty = ty
+        .strip_prefix("Option<")
+        .and_then(|t| t.strip_suffix('>'))
+        .unwrap_or(&ty);
+    match ty {
+        "String" | "&str" | "PathBuf" => ArgumentType::String(String::new()),
+        "i8" | "i16" | "i32" | "i64" | "isize" => ArgumentType::Int(0),
+        "u8" | "u16" | "u32" | "u64" | "usize" => ArgumentType::Uint(0),
+        "f32" | "f64" => ArgumentType::Float(0.0),
+        "bool" => ArgumentType::Boolean(String::new()),
+        list if list.starts_with("Vec<") => ArgumentType::List(String::new()),
+        _ => ArgumentType::NotSpecified,
+    }
+}
+
+/// Command line flag of a clap derived field, `None` for positional arguments.
+fn clap_flag(field: &syn::Field) -> Option<String> {
+    let name = field.ident.as_ref()?.to_string();
+    let (mut long, mut short) = (None, None);
+    let attrs = field
+        .attrs
+        .iter()
+        .filter(|a| a.path().is_ident("arg") || a.path().is_ident("clap"));
+    for attr in attrs {
+        // flags found before a part syn doesn't understand are kept
+        let _ = attr.parse_nested_meta(|meta| {
+            let value = if meta.input.peek(syn::Token![=]) {
+                Some(meta.value()?.parse::<syn::Expr>()?)
+            } else {
+                if meta.input.peek(syn::token::Paren) {
+                    let arguments;
+                    syn::parenthesized!(arguments in meta.input);
+                    arguments.parse::<proc_macro2::TokenStream>()?;
+                }
+                None
+            };
+            let literal = match value {
+                Some(syn::Expr::Lit(syn::ExprLit { lit, .. })) => Some(lit),
+                _ => None,
+            };
+            if meta.path.is_ident("long") {
+                long = Some(match literal {
+                    Some(syn::Lit::Str(long)) => long.value(),
+                    _ => name.replace('_', "-"),
+                });
+            } else if meta.path.is_ident("short") {
+                short = match literal {
+                    Some(syn::Lit::Char(short)) => Some(short.value()),
+                    _ => name.chars().next(),
+                };
+            }
+            Ok(())
+        });
+    }
+    long.map(|long| format!("--{}", long))
+        .or_else(|| short.map(|short| format!("-{}", short)))
+}
+
+fn is_flaunch(func: &syn::ItemFn) -> bool {
+    func.attrs.iter().any(|a| a.path().is_ident("flaunch"))
+}
+
+fn flaunch_functions(parsed: &syn::File) -> impl Iterator<Item = &syn::ItemFn> {
+    parsed.items.iter().filter_map(|item| match item {
+        syn::Item::Fn(func) if is_flaunch(func) => Some(func),
+        _ => None,
+    })
+}
+
+/// `source` without the `#[flaunch]` attributes, which rustc doesn't know.
+/// They are blanked, so compiler messages keep pointing at the right column.
+fn without_flaunch_attributes(source: &str, parsed: &syn::File) -> String {
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(source.match_indices('\n').map(|(index, _)| index + 1))
+        .collect();
+    let offset = |position: proc_macro2::LineColumn| {
+        let line = line_starts[position.line - 1];
+        source[line..]
+            .char_indices()
+            .nth(position.column)
+            .map_or(source.len(), |(index, _)| line + index)
+    };
+
+    let mut attributes: Vec<(usize, usize)> = flaunch_functions(parsed)
+        .flat_map(|func| &func.attrs)
+        .filter(|a| a.path().is_ident("flaunch"))
+        .map(|a| {
+            (
+                offset(a.pound_token.span.start()),
+                offset(a.bracket_token.span.close().end()),
+            )
+        })
+        .collect();
+    attributes.sort();
+
+    let mut source = source.to_string();
+    for (start, end) in attributes.into_iter().rev() {
+        let blank = " ".repeat(source[start..end].chars().count());
+        source.replace_range(start..end, &blank);
+    }
+    source
+}
+
+fn analyze(content: &str, file: &Path) -> Result<RustScript, String> {
+    let (manifest, source) = split_manifest(content);
+    let parsed = syn::parse_file(&source).map_err(|e| e.to_string())?;
+
+    let mut script = RustScript {
+        manifest,
+        ..Default::default()
+    };
+    let mut has_main = false;
+    let mut parser = None;
+
+    for item in &parsed.items {
+        match item {
+            syn::Item::Fn(func) if is_flaunch(func) => {
+                let name = func.sig.ident.to_string();
+                let mut s = Script::new(name.clone(), InterpreterType::Rust);
+                s.file = file.to_path_buf();
+                s.description = doc_comment(&func.attrs);
+                for input in &func.sig.inputs {
+                    if let syn::FnArg::Typed(arg) = input {
+                        let arg_name = arg.pat.to_token_stream().to_string();
+                        s.arguments
+                            .push((arg_name, argument_type(&arg.ty), String::new()));
+                    }
+                }
+                let arity = func.sig.inputs.len();
+                script.scripts.push((s, Entry::Function(name, arity)));
+            }
+            syn::Item::Fn(func) if func.sig.ident == "main" => has_main = true,
+            syn::Item::Struct(item) => {
+                let derives_parser = item.attrs.iter().any(|a| {
+                    a.path().is_ident("derive")
+                        && a.meta.to_token_stream().to_string().contains("Parser")
+                });
+                if derives_parser {
+                    parser = Some(item);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if !script.scripts.is_empty() {
+        if has_main {
+            return Err("files with #[flaunch] functions can't define main".to_string());
+        }
+        script.source = without_flaunch_attributes(&source, &parsed) + &dispatcher(&parsed)?;
+        return Ok(script);
+    }
+
+    if has_main {
+        let name = file.file_stem().unwrap_or_default().to_string_lossy();
+        let mut s = Script::new(name.to_string(), InterpreterType::Rust);
+        s.file = file.to_path_buf();
+        s.description = doc_comment(&parsed.attrs);
+        let mut flags = Vec::new();
+        if let Some(parser) = parser {
+            if s.description.is_empty() {
+                s.description = doc_comment(&parser.attrs);
+            }
+            for field in &parser.fields {
+                let field_name = field
+                    .ident
+                    .as_ref()
+                    .map(ToString::to_string)
+                    .unwrap_or_default();
+                s.arguments.push((
+                    field_name,
+                    argument_type(&field.ty),
+                    doc_comment(&field.attrs),
+                ));
+                flags.push(clap_flag(field));
+            }
+        }
+        script.scripts.push((s, Entry::Main(flags)));
+    }
+    script.source = source;
+    Ok(script)
+}
+
+fn is_str(ty: &syn::Type) -> bool {
+    match ty {
+        syn::Type::Reference(r) => {
+            r.mutability.is_none() && r.elem.to_token_stream().to_string() == "str"
+        }
+        _ => false,
+    }
+}
+
+/// Path types without generic arguments, which are parsed with `FromStr`.
+fn is_plain(ty: &syn::Type) -> bool {
+    match ty {
+        syn::Type::Path(p) => {
+            p.qself.is_none() && p.path.segments.iter().all(|s| s.arguments.is_empty())
+        }
+        _ => false,
+    }
+}
+
+/// The single type argument of `Vec<T>` or `Option<T>`.
+fn type_argument<'a>(ty: &'a syn::Type, outer: &str) -> Option<&'a syn::Type> {
+    let path = match ty {
+        syn::Type::Path(p) if p.qself.is_none() => &p.path,
+        _ => return None,
+    };
+    let segment = path.segments.last().filter(|s| s.ident == outer)?;
+    match &segment.arguments {
+        syn::PathArguments::AngleBracketed(a) if a.args.len() == 1 => match &a.args[0] {
+            syn::GenericArgument::Type(inner) => Some(inner),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Expression converting the `index`th argument of the generated `main`
+/// to `ty`, `None` if `ty` can't be passed on the command line.
+fn conversion(ty: &syn::Type, index: usize) -> Option<String> {
+    let parse = format!("parse().expect(\"argument {}\")", index);
+    if is_str(ty) {
+        Some(format!("&arg({})", index))
+    } else if is_plain(ty) {
+        Some(format!("arg({}).{}", index, parse))
+    } else if let Some(inner) = type_argument(ty, "Vec") {
+        // lists are passed comma separated
+        let items = format!("arg({}).split(',').filter(|s| !s.is_empty())", index);
+        if is_str(inner) {
+            Some(format!("{}.collect()", items))
+        } else if is_plain(inner) {
+            Some(format!("{}.map(|s| s.{}).collect()", items, parse))
+        } else {
+            None
+        }
+    } else if let Some(inner) = type_argument(ty, "Option") {
+        // an empty argument is `None`
+        let value = format!("Some(arg({})).filter(|s| !s.is_empty())", index);
+        if is_str(inner) {
+            Some(format!("{}.as_deref()", value))
+        } else if is_plain(inner) {
+            Some(format!("{}.map(|s| s.{})", value, parse))
+        } else {
+            None
+        }
+    } else {
+        None
+    }
+}
+
+/// `main` calling the `#[flaunch]` function named by the first argument.
+fn dispatcher(parsed: &syn::File) -> Result<String, String> {
+    let mut arms = String::new();
+    for func in flaunch_functions(parsed) {
+        let name = func.sig.ident.to_string();
+        let mut parameters = Vec::new();
+        for (index, input) in func.sig.inputs.iter().enumerate() {
+            let arg = match input {
+                syn::FnArg::Typed(arg) => arg,
+                syn::FnArg::Receiver(_) => return Err(format!("{} can't take self", name)),
+            };
+            parameters.push(conversion(&arg.ty, index).ok_or_else(|| {
+                format!(
+                    "argument {} of {} can't be passed on the command line: {}",
+                    arg.pat.to_token_stream(),
+                    name,
+                    arg.ty.to_token_stream()
+                )
+            })?);
+        }
+        // checked by the callable too, this is for running the binary by hand
+        arms.push_str(&format!(
+            concat!(
+                "        \"{0}\" if args.len() != {2} => {{\n",
+                "            eprintln!(\"{0} takes {1} arguments\");\n",
+                "            std::process::ExitCode::FAILURE\n",
+                "        }}\n",
+                "        \"{0}\" => std::process::Termination::report({0}({3})),\n",
+            ),
+            name,
+            parameters.len(),
+            parameters.len() + 1,
+            parameters.join(", ")
+        ));
+    }
+
+    Ok(format!(
+        concat!(
+            "\nfn main() -> std::process::ExitCode {{\n",
+            "    let args: Vec<String> = std::env::args().skip(1).collect();\n",
+            "    let arg = |i: usize| args.get(i + 1).cloned().unwrap_or_default();\n",
+            "    match args.first().map(String::as_str).unwrap_or_default() {{\n",
+            "{}",
+            "        other => {{\n",
+            "            eprintln!(\"unknown function {{}}\", other);\n",
+            "            std::process::ExitCode::FAILURE\n",
+            "        }}\n",
+            "    }}\n",
+            "}}\n"
+        ),
+        arms
+    ))
+}
+
+fn project_name(key: u64) -> String {
+    format!("flaunch_{:016x}", key)
+}
+
+impl RsInterpreter {
+    fn with_cache_dir(cache_dir: PathBuf) -> Self {
+        RsInterpreter {
+            cache_dir,
+            builds: Arc::default(),
+        }
+    }
+
+    fn builds(&self) -> std::sync::MutexGuard<'_, Builds> {
+        self.builds.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The build of `key`, started in the background unless its binary is
+    /// cached or it is running already.
+    fn start_build(&self, manifest: String, source: String, file: &Path, key: u64) -> Arc<Build> {
+        let mut builds = self.builds();
+        builds.latest.insert(file.to_path_buf(), key);
+        if let Some(build) = builds.running.get(&key) {
+            return build.clone();
+        }
+
+        let build = Arc::new(Build::default());
+        let name = project_name(key);
+        let binary =
+            self.cache_dir
+                .join(&name)
+                .join(format!("{}{}", name, std::env::consts::EXE_SUFFIX));
+        if binary.exists() {
+            self.remove_superseded(file, key, &builds);
+            build.finish(Ok(binary));
+            return build;
+        }
+        builds.running.insert(key, build.clone());
+
+        let interpreter = self.clone();
+        let file = file.to_path_buf();
+        let running = build.clone();
+        std::thread::Builder::new()
+            .name("flaunch-rust-build".to_string())
+            .spawn(move || {
+                let result = interpreter.build(&manifest, &source, &file, &binary);
+                match &result {
+                    Ok(_) => info!("built {}", file.to_string_lossy()),
+                    Err((message, traceback)) => error!(
+                        "could not build {}: {}\n{}",
+                        file.to_string_lossy(),
+                        message,
+                        traceback
+                    ),
+                }
+                running.finish(result);
+
+                let mut builds = interpreter.builds();
+                builds.running.remove(&key);
+                if builds.latest.get(&file) == Some(&key) {
+                    interpreter.remove_superseded(&file, key, &builds);
+                }
+            })
+            .expect("could not start rust build thread");
+        build
+    }
+
+    /// Builds the script into `binary`.
+    fn build(
+        &self,
+        manifest: &str,
+        source: &str,
+        file: &Path,
+        binary: &Path,
+    ) -> Result<PathBuf, (String, String)> {
+        let project = binary.parent().unwrap_or(&self.cache_dir);
+        let name = project.file_name().unwrap_or_default().to_string_lossy();
+
+        let mut manifest = manifest
+            .parse::<toml::Table>()
+            .map_err(|e| (format!("invalid embedded manifest: {}", e), String::new()))?;
+        let package = manifest
+            .entry("package")
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+        if let Some(package) = package.as_table_mut() {
+            package.insert("name".to_string(), name.to_string().into());
+            package.entry("version").or_insert_with(|| "0.0.0".into());
+            package.entry("edition").or_insert_with(|| "2021".into());
+        }
+        // a standalone workspace, so the project isn't mistaken for a member of another
+        manifest
+            .entry("workspace")
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+
+        let io_error = |e: std::io::Error| (e.to_string(), String::new());
+        std::fs::create_dir_all(project.join("src")).map_err(io_error)?;
+        // the script the project was built from, to find superseded builds
+        std::fs::write(project.join("source"), file.to_string_lossy().as_bytes())
+            .map_err(io_error)?;
+        std::fs::write(project.join("Cargo.toml"), manifest.to_string()).map_err(io_error)?;
+        std::fs::write(project.join("src").join("main.rs"), source).map_err(io_error)?;
+
+        let target_dir = self.cache_dir.join("target");
+        let output = Command::new("cargo")
+            .args(["build", "--release", "--offline", "--quiet"])
+            .arg("--manifest-path")
+            .arg(project.join("Cargo.toml"))
+            .arg("--target-dir")
+            .arg(&target_dir)
+            .output()
+            .map_err(|e| (format!("could not run cargo: {}", e), String::new()))?;
+        if !output.status.success() {
+            return Err((
+                "compilation failed".to_string(),
+                String::from_utf8_lossy(&output.stderr).to_string(),
+            ));
+        }
+
+        let built = target_dir
+            .join("release")
+            .join(binary.file_name().unwrap_or_default());
+        std::fs::copy(built, binary).map_err(io_error)?;
+        Ok(binary.to_path_buf())
+    }
+
+    /// Removes the cached builds of other versions of `file`, and of files
+    /// that don't exist anymore, along with their build artifacts.
+    fn remove_superseded(&self, file: &Path, key: u64, builds: &Builds) {
+        let keep: Vec<String> = std::iter::once(key)
+            .chain(builds.running.keys().copied())
+            .map(project_name)
+            .collect();
+        let release = self.cache_dir.join("target").join("release");
+        let projects = std::fs::read_dir(&self.cache_dir).into_iter().flatten();
+        for project in projects.flatten() {
+            let name = project.file_name().to_string_lossy().to_string();
+            if !name.starts_with("flaunch_") || keep.contains(&name) {
+                continue;
+            }
+            let source = match std::fs::read_to_string(project.path().join("source")) {
+                Ok(source) => PathBuf::from(source),
+                Err(_) => continue,
+            };
+            if source != file && source.exists() {
+                continue;
+            }
+
+            debug!(
+                "removing cached build {} of {}",
+                name,
+                source.to_string_lossy()
+            );
+            let _ = std::fs::remove_dir_all(project.path());
+            for dir in [
+                release.clone(),
+                release.join("deps"),
+                release.join("build"),
+                release.join(".fingerprint"),
+            ] {
+                let artifacts = std::fs::read_dir(dir).into_iter().flatten().flatten();
+                for artifact in
+                    artifacts.filter(|a| a.file_name().to_string_lossy().starts_with(&name))
+                {
+                    let _ = match artifact.file_type() {
+                        Ok(t) if t.is_dir() => std::fs::remove_dir_all(artifact.path()),
+                        _ => std::fs::remove_file(artifact.path()),
+                    };
+                }
+            }
+        }
+    }
+}
+
+impl Interpreter for RsInterpreter {
+    fn parse(&self, content: &[u8], file: &Path) -> ParseResult {
+        let script = match analyze(&String::from_utf8_lossy(content), file) {
+            Ok(script) => script,
+            Err(message) => {
+                let error = ParseError {
+                    filename: file.to_string_lossy().to_string(),
+                    message,
+                    traceback: String::new(),
+                };
+                return (Vec::new(), Vec::new(), vec![error]);
+            }
+        };
+        if script.scripts.is_empty() {
+            return ParseResult::default();
+        }
+
+        // cached binaries outlive flaunchd, the key must not change with its build
+        let mut hasher = Fnv1a::default();
+        hasher.write(content);
+        let build = self.start_build(script.manifest, script.source, file, hasher.finish());
+
+        let mut call = RsCallable {
+            build,
+            entries: HashMap::new(),
+        };
+        let mut scripts = Vec::new();
+        for (s, entry) in script.scripts {
+            call.entries.insert(s.get_key().unwrap(), entry);
+            scripts.push(s);
+        }
+
+        let rc: Arc<dyn Callable> = Arc::new(call);
+        let callables = scripts
+            .iter()
+            .map(|s| (s.get_key().unwrap(), rc.clone()))
+            .collect();
+        (scripts, callables, Vec::new())
+    }
+}
+
+#[derive(Debug)]
+pub struct RsCallable {
+    build: Arc<Build>,
+    entries: HashMap<u64, Entry>,
+}
+
+impl RsCallable {
+    fn command_line(entry: &Entry, args: &[Box<dyn Any + Send>]) -> Result<Vec<String>, CallError> {
+        let mut command_line = Vec::new();
+        match entry {
+            Entry::Function(name, arity) => {
+                if args.len() != *arity {
+                    return Err(CallError::WrongArguments);
+                }
+                command_line.push(name.clone());
+                for arg in args {
+                    command_line.push(to_text(arg.as_ref()).ok_or(CallError::WrongArguments)?);
+                }
+            }
+            Entry::Main(flags) => {
+                if args.len() > flags.len() {
+                    return Err(CallError::WrongArguments);
+                }
+                for (flag, arg) in flags.iter().zip(args) {
+                    match (flag, arg.downcast_ref::<bool>()) {
+                        (Some(flag), Some(true)) => command_line.push(flag.clone()),
+                        (Some(_), Some(false)) => {}
+                        (Some(flag), None) => {
+                            command_line.push(flag.clone());
+                            command_line
+                                .push(to_text(arg.as_ref()).ok_or(CallError::WrongArguments)?);
+                        }
+                        (None, _) => command_line
+                            .push(to_text(arg.as_ref()).ok_or(CallError::WrongArguments)?),
+                    }
+                }
+            }
+        }
+        Ok(command_line)
+    }
+}
+
+impl Callable for RsCallable {
+    fn call(
+        &self,
+        key: u64,
+        args: &[Box<dyn Any + Send>],
+        events: &CallEvents,
+    ) -> Result<CallOutput, CallError> {
+        let entry = self
+            .entries
+            .get(&key)
+            .ok_or(CallError::KeyNotPresent(key))?;
+        let command_line = RsCallable::command_line(entry, args)?;
+        let binary = self.build.wait()?;
+
+        let failed = |message: String| CallError::Exception {
+            message,
+            traceback: String::new(),
+        };
+        let mut child = Command::new(&binary)
+            .args(&command_line)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| failed(e.to_string()))?;
+
+        let stderr = BufReader::new(child.stderr.take().unwrap());
+        let stderr_events = events.clone();
+        let stderr = std::thread::spawn(move || {
+            let mut text = String::new();
+            for line in stderr.lines().map_while(Result::ok) {
+                let _ = stderr_events.send(CallEvent::Error(line.clone()));
+                text.push_str(&line);
+                text.push('\n');
+            }
+            text
+        });
+
+        let mut output = String::new();
+        for line in BufReader::new(child.stdout.take().unwrap()).lines() {
+            let line = line.map_err(|e| failed(e.to_string()))?;
+            let _ = events.send(CallEvent::Output(line.clone()));
+            output.push_str(&line);
+            output.push('\n');
+        }
+
+        let status = child.wait().map_err(|e| failed(e.to_string()))?;
+        let stderr = stderr.join().unwrap_or_default();
+        if status.success() {
+            Ok(CallOutput::Text(output))
+        } else {
+            Err(CallError::Exception {
+                message: format!("{} exited with {}", binary.to_string_lossy(), status),
+                traceback: stderr,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn analyze_main_with_clap_arguments() {
+        let script = analyze(
+            concat!(
+                "#!/usr/bin/env rust-script\n",
+                "//! ```cargo\n",
+                "//! [dependencies]\n",
+                "//! clap = { version = \"4\", features = [\"derive\"] }\n",
+                "//! ```\n",
+                "use clap::Parser;\n",
+                "/// cleans old build artifacts\n",
+                "#[derive(Parser)]\n",
+                "struct Args {\n",
+                "    /// directory to clean\n",
+                "    dir: String,\n",
+                "    /// days to keep\n",
+                "    #[arg(long, default_value_t = 7)]\n",
+                "    keep_days: u32,\n",
+                "}\n",
+                "fn main() { let _args = Args::parse(); }\n",
+            ),
+            &PathBuf::from("/my/clean.rs"),
+        )
+        .unwrap();
+
+        assert!(script.manifest.contains("[dependencies]"));
+        assert_eq!(script.scripts.len(), 1);
+        let (s, entry) = &script.scripts[0];
+        assert_eq!(s.name, "clean");
+        assert_eq!(s.description, "cleans old build artifacts");
+        assert_eq!(
+            s.arguments,
+            vec![
+                (
+                    "dir".to_string(),
+                    ArgumentType::String(String::new()),
+                    "directory to clean".to_string()
+                ),
+                (
+                    "keep_days".to_string(),
+                    ArgumentType::Uint(0),
+                    "days to keep".to_string()
+                ),
+            ]
+        );
+        assert_eq!(
+            *entry,
+            Entry::Main(vec![None, Some("--keep-days".to_string())])
+        );
+    }
+
+    #[test]
+    fn clap_flags_follow_the_arg_meta() {
+        let parser: syn::ItemStruct = syn::parse_str(concat!(
+            "struct Args {\n",
+            "    #[arg(default_value = \"long\")]\n",
+            "    positional: String,\n",
+            "    #[arg(short, long)]\n",
+            "    dry_run: bool,\n",
+            "    #[arg(short = 'n', value_parser = clap::value_parser!(u32).range(1..))]\n",
+            "    count: u32,\n",
+            "    #[arg(long = \"out\", help = \"short\")]\n",
+            "    output: String,\n",
+            "    #[clap(short)]\n",
+            "    verbose: bool,\n",
+            "}\n",
+        ))
+        .unwrap();
+        let flags: Vec<Option<String>> = parser.fields.iter().map(clap_flag).collect();
+        assert_eq!(
+            flags,
+            vec![
+                None,
+                Some("--dry-run".to_string()),
+                Some("-n".to_string()),
+                Some("--out".to_string()),
+                Some("-v".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn analyze_flaunch_functions() {
+        let script = analyze(
+            concat!(
+                "---\n",
+                "[dependencies]\n",
+                "---\n",
+                "/// adds two numbers\n",
+                "#[flaunch] fn add(a: i32, b: i32) { println!(\"{}\", a + b); }\n",
+                "const MARKER: &str = \"#[flaunch]\";\n",
+            ),
+            &PathBuf::from("/my/math.rs"),
+        )
+        .unwrap();
+
+        assert_eq!(script.manifest, "[dependencies]\n");
+        assert!(!script.source.contains("#[flaunch] fn"));
+        assert!(script.source.contains("\"#[flaunch]\""));
+        assert!(script.source.contains("          fn add"));
+        assert!(script.source.contains("fn main()"));
+        assert_eq!(script.scripts[0].0.name, "add");
+        assert_eq!(script.scripts[0].0.description, "adds two numbers");
+        assert_eq!(script.scripts[0].0.arguments.len(), 2);
+        assert_eq!(script.scripts[0].1, Entry::Function("add".to_string(), 2));
+    }
+
+    #[test]
+    fn function_arguments_must_all_be_given() {
+        let entry = Entry::Function("add".to_string(), 2);
+        let one: Vec<Box<dyn Any + Send>> = vec![Box::new(1i32)];
+        assert!(matches!(
+            RsCallable::command_line(&entry, &one),
+            Err(CallError::WrongArguments)
+        ));
+        let two: Vec<Box<dyn Any + Send>> = vec![Box::new(1i32), Box::new(2i32)];
+        assert_eq!(
+            RsCallable::command_line(&entry, &two).unwrap(),
+            vec!["add", "1", "2"]
+        );
+    }
+
+    #[test]
+    fn unsupported_argument_is_error() {
+        let error = analyze(
+            "#[flaunch] fn swap(pair: (i32, i32)) {}\n",
+            &PathBuf::from("/my/swap.rs"),
+        )
+        .unwrap_err();
+        assert!(error.contains("argument pair of swap"), "{}", error);
+    }
+
+    #[test]
+    fn syntax_error_is_parse_error() {
+        let interpreter = RsInterpreter::with_cache_dir(std::env::temp_dir());
+        let (scripts, _, errors) =
+            interpreter.parse(b"fn main( {", &PathBuf::from("/my/broken.rs"));
+        assert!(scripts.is_empty());
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn build_and_call_function() {
+        let interpreter = RsInterpreter::with_cache_dir(
+            std::env::temp_dir().join(format!("flaunch_rs_{}", std::process::id())),
+        );
+        let (scripts, callables, errors) = interpreter.parse(
+            concat!(
+                "#[flaunch]\n",
+                "fn greet(name: &str, times: u32, also: Vec<&str>, sign: Option<char>) {\n",
+                "    for _ in 0..times { println!(\"hello {}\", name); }\n",
+                "    println!(\"and {:?} {:?}\", also, sign);\n",
+                "}\n",
+            )
+            .as_bytes(),
+            &PathBuf::from("/my/greet.rs"),
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let args: Vec<Box<dyn Any + Send>> = vec![
+            Box::new("sven".to_string()),
+            Box::new(2u32),
+            Box::new(vec!["ada".to_string(), "bob".to_string()]),
+            Box::new(String::new()),
+        ];
+        let output = callables[0]
+            .1
+            .call(scripts[0].get_key().unwrap(), &args, &tx)
+            .unwrap();
+        assert_eq!(
+            output,
+            CallOutput::Text("hello sven\nhello sven\nand [\"ada\", \"bob\"] None\n".to_string())
+        );
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            CallEvent::Output("hello sven".to_string())
+        );
+        std::fs::remove_dir_all(&interpreter.cache_dir).unwrap();
+    }
+
+    #[test]
+    fn new_version_replaces_cached_build() {
+        let interpreter = RsInterpreter::with_cache_dir(
+            std::env::temp_dir().join(format!("flaunch_rs_versions_{}", std::process::id())),
+        );
+        let file = PathBuf::from("/my/version.rs");
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut projects = Vec::new();
+        for version in 1..=2 {
+            let content = format!(
+                "#[flaunch]\nfn version() {{ println!(\"{}\"); }}\n",
+                version
+            );
+            let (scripts, callables, _) = interpreter.parse(content.as_bytes(), &file);
+            let output = callables[0].1.call(scripts[0].get_key().unwrap(), &[], &tx);
+            assert_eq!(output.unwrap(), CallOutput::Text(format!("{}\n", version)));
+
+            // the superseded build is removed after the call returned
+            while !interpreter.builds().running.is_empty() {
+                std::thread::yield_now();
+            }
+            projects = std::fs::read_dir(&interpreter.cache_dir)
+                .unwrap()
+                .flatten()
+                .filter(|p| p.file_name().to_string_lossy().starts_with("flaunch_"))
+                .collect();
+        }
+        assert_eq!(projects.len(), 1);
+        std::fs::remove_dir_all(&interpreter.cache_dir).unwrap();
+    }
+}
@@ -0,0 +1,394 @@
+use std::collections::HashMap;
+
+use crate::script_engine::interpreter::*;
+use crate::script_engine::*;
+use crate::settings::JsonValue;
+use crate::SettingKey;
+
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::{Connection, OpenFlags, Statement};
+
+const NAME: &str = "-- name:";
+const ARG: &str = "-- arg:";
+const DATABASE: &str = "-- database:";
+
+/// Exposes named queries in `.sql` files as scripts.
+///
+/// ```sql
+/// -- database: sessions.db
+///
+/// -- name: cleanup_old_sessions
+/// -- removes sessions older than the given amount of days
+/// -- arg: days int number of days to keep
+/// DELETE FROM sessions WHERE created < date('now', '-' || :days || ' days');
+/// ```
+///
+/// The database is taken from the `-- database:` header, relative to the
+/// file, or from the `sqlite_database` setting. Arguments are bound to the
+/// equally named `:parameters` of the query.
+#[derive(Debug)]
+pub struct SqlInterpreter {
+    default_database: Option<PathBuf>,
+}
+
+impl Default for SqlInterpreter {
+    fn default() -> Self {
+        let settings = crate::load_settings();
+        SqlInterpreter {
+            default_database: settings
+                .get_str(SettingKey::SqliteDatabase)
+                .filter(|s| !s.is_empty())
+                .map(PathBuf::from),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Query {
+    name: String,
+    description: Vec<String>,
+    arguments: Vec<(String, ArgumentType, String)>,
+    sql: String,
+}
+
+fn argument_type(name: &str) -> ArgumentType {
+    match name.to_lowercase().as_str() {
+        "int" | "integer" => ArgumentType::Int(0),
+        "uint" => ArgumentType::Uint(0),
+        "float" | "real" => ArgumentType::Float(0.0),
+        "bool" | "boolean" => ArgumentType::Boolean(String::new()),
+        "str" | "string" | "text" => ArgumentType::String(String::new()),
+        _ => ArgumentType::NotSpecified,
+    }
+}
+
+/// Splits a file in its `-- database:` header and named queries.
+fn parse_queries(source: &str) -> (Option<String>, Vec<Query>) {
+    let mut database = None;
+    let mut queries: Vec<Query> = Vec::new();
+
+    for line in source.lines() {
+        let trimmed = line.trim();
+        if let Some(name) = trimmed.strip_prefix(NAME) {
+            queries.push(Query {
+                name: name.trim().to_string(),
+                ..Default::default()
+            });
+            continue;
+        }
+
+        let query = match queries.last_mut() {
+            Some(query) => query,
+            None => {
+                if let Some(path) = trimmed.strip_prefix(DATABASE) {
+                    database = Some(path.trim().to_string());
+                }
+                continue;
+            }
+        };
+
+        if let Some(arg) = trimmed.strip_prefix(ARG) {
+            let mut parts = arg.trim().splitn(3, char::is_whitespace);
+            let name = parts.next().unwrap_or_default().to_string();
+            let typ = argument_type(parts.next().unwrap_or_default());
+            let description = parts.next().unwrap_or_default().trim().to_string();
+            query.arguments.push((name, typ, description));
+        } else if let Some(comment) = trimmed.strip_prefix("--") {
+            if query.sql.is_empty() && !comment.trim().is_empty() {
+                query.description.push(comment.trim().to_string());
+            }
+        } else if !trimmed.is_empty() {
+            query.sql.push_str(line);
+            query.sql.push('\n');
+        }
+    }
+
+    (database, queries)
+}
+
+impl Interpreter for SqlInterpreter {
+    fn parse(&self, content: &[u8], file: &Path) -> ParseResult {
+        let mut scripts = Vec::new();
+        let mut callables: Vec<(u64, Arc<dyn Callable>)> = Vec::new();
+        let mut errors = Vec::new();
+        let error = |message: String| ParseError {
+            filename: file.to_string_lossy().to_string(),
+            message,
+            traceback: String::new(),
+        };
+
+        let (database, queries) = parse_queries(&String::from_utf8_lossy(content));
+        let database = match database {
+            Some(path) => file.parent().unwrap_or(Path::new("")).join(path),
+            None => match &self.default_database {
+                Some(path) => path.clone(),
+                None => {
+                    if !queries.is_empty() {
+                        errors.push(error(
+                            "no `-- database:` header or sqlite_database setting".to_string(),
+                        ));
+                    }
+                    return (scripts, callables, errors);
+                }
+            },
+        };
+
+        let mut call = SqlCallable {
+            database,
+            queries: HashMap::new(),
+        };
+        for query in queries {
+            if query.name.is_empty() || query.sql.trim().is_empty() {
+                errors.push(error(format!(
+                    "query {:?} has no name or statement",
+                    query.name
+                )));
+                continue;
+            }
+
+            let mut script = Script::new(query.name.clone(), InterpreterType::Sql);
+            script.file = file.to_path_buf();
+            script.description = query.description.join(" ");
+            script.arguments = query.arguments.clone();
+            call.queries.insert(script.get_key().unwrap(), query);
+            scripts.push(script);
+        }
+
+        let rc: Arc<dyn Callable> = Arc::new(call);
+        callables = scripts
+            .iter()
+            .map(|s| (s.get_key().unwrap(), rc.clone()))
+            .collect();
+
+        (scripts, callables, errors)
+    }
+}
+
+#[derive(Debug)]
+pub struct SqlCallable {
+    database: PathBuf,
+    queries: HashMap<u64, Query>,
+}
+
+fn to_sql_value(arg: &dyn Any) -> Option<Value> {
+    if let Some(s) = arg.downcast_ref::<String>() {
+        Some(Value::Text(s.clone()))
+    } else if let Some(i) = arg.downcast_ref::<i32>() {
+        Some(Value::Integer(*i as i64))
+    } else if let Some(u) = arg.downcast_ref::<u32>() {
+        Some(Value::Integer(*u as i64))
+    } else if let Some(f) = arg.downcast_ref::<f32>() {
+        Some(Value::Real(*f as f64))
+    } else {
+        arg.downcast_ref::<bool>()
+            .map(|b| Value::Integer(*b as i64))
+    }
+}
+
+fn to_json(value: ValueRef) -> JsonValue {
+    match value {
+        ValueRef::Null => JsonValue::Null,
+        ValueRef::Integer(i) => i.into(),
+        ValueRef::Real(f) => f.into(),
+        ValueRef::Text(t) => String::from_utf8_lossy(t).to_string().into(),
+        ValueRef::Blob(b) => b
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>()
+            .into(),
+    }
+}
+
+fn run(statement: &mut Statement) -> rusqlite::Result<CallOutput> {
+    if statement.column_count() == 0 {
+        let changed = statement.raw_execute()?;
+        return Ok(CallOutput::Text(format!("{} rows affected", changed)));
+    }
+
+    let mut table = Table {
+        columns: statement
+            .column_names()
+            .into_iter()
+            .map(str::to_string)
+            .collect(),
+        rows: Vec::new(),
+    };
+    let columns = table.columns.len();
+    let mut rows = statement.raw_query();
+    while let Some(row) = rows.next()? {
+        let mut values = Vec::with_capacity(columns);
+        for index in 0..columns {
+            values.push(to_json(row.get_ref(index)?));
+        }
+        table.rows.push(values);
+    }
+    Ok(CallOutput::Table(table))
+}
+
+impl Callable for SqlCallable {
+    fn call(
+        &self,
+        key: u64,
+        args: &[Box<dyn Any + Send>],
+        _events: &CallEvents,
+    ) -> Result<CallOutput, CallError> {
+        let query = self
+            .queries
+            .get(&key)
+            .ok_or(CallError::KeyNotPresent(key))?;
+        // queries have no defaults, a left out argument would bind NULL
+        if args.len() != query.arguments.len() {
+            return Err(CallError::WrongArguments);
+        }
+
+        let failed = |e: rusqlite::Error| CallError::Exception {
+            message: e.to_string(),
+            traceback: format!("{}\n{}", self.database.to_string_lossy(), query.sql),
+        };
+
+        let connection = Connection::open_with_flags(
+            &self.database,
+            OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_URI,
+        )
+        .map_err(failed)?;
+        let mut statement = connection.prepare(&query.sql).map_err(failed)?;
+
+        for ((name, _, _), arg) in query.arguments.iter().zip(args) {
+            let value = to_sql_value(arg.as_ref()).ok_or(CallError::WrongArguments)?;
+            match statement
+                .parameter_index(&format!(":{}", name))
+                .map_err(failed)?
+            {
+                Some(index) => statement.raw_bind_parameter(index, value).map_err(failed)?,
+                None => warn!("{} does not use argument {}", query.name, name),
+            }
+        }
+
+        run(&mut statement).map_err(failed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUERIES: &str = concat!(
+        "-- database: sessions.db\n",
+        "\n",
+        "-- name: sessions_of\n",
+        "-- lists the sessions of a user\n",
+        "-- arg: user text name of the user\n",
+        "SELECT id, user FROM sessions\n",
+        "WHERE user = :user ORDER BY id;\n",
+        "\n",
+        "-- name: cleanup_old_sessions\n",
+        "-- arg: days int\n",
+        "DELETE FROM sessions WHERE age > :days;\n",
+    );
+
+    fn database_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("flaunch_sql_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let connection = Connection::open(dir.join("sessions.db")).unwrap();
+        connection
+            .execute_batch(concat!(
+                "DROP TABLE IF EXISTS sessions;",
+                "CREATE TABLE sessions (id INTEGER, user TEXT, age INTEGER);",
+                "INSERT INTO sessions VALUES (1, 'sven', 10), (2, 'sven', 1), (3, 'bob', 20);",
+            ))
+            .unwrap();
+        dir
+    }
+
+    #[test]
+    fn parse_named_queries() {
+        let interpreter = SqlInterpreter {
+            default_database: None,
+        };
+        let (scripts, callables, errors) =
+            interpreter.parse(QUERIES.as_bytes(), &PathBuf::from("/my/queries.sql"));
+
+        assert!(errors.is_empty());
+        assert_eq!(callables.len(), 2);
+        assert_eq!(scripts[0].name, "sessions_of");
+        assert_eq!(scripts[0].description, "lists the sessions of a user");
+        assert_eq!(
+            scripts[0].arguments,
+            vec![(
+                "user".to_string(),
+                ArgumentType::String(String::new()),
+                "name of the user".to_string()
+            )]
+        );
+        assert_eq!(scripts[1].name, "cleanup_old_sessions");
+        assert_eq!(scripts[1].arguments[0].1, ArgumentType::Int(0));
+    }
+
+    #[test]
+    fn missing_database_is_parse_error() {
+        let interpreter = SqlInterpreter {
+            default_database: None,
+        };
+        let (scripts, _, errors) = interpreter.parse(
+            b"-- name: all\nSELECT 1;\n",
+            &PathBuf::from("/my/queries.sql"),
+        );
+        assert!(scripts.is_empty());
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn call_binds_arguments() {
+        let dir = database_dir("call");
+        let interpreter = SqlInterpreter {
+            default_database: None,
+        };
+        let (scripts, callables, _) =
+            interpreter.parse(QUERIES.as_bytes(), &dir.join("queries.sql"));
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+
+        let args: Vec<Box<dyn Any + Send>> = vec![Box::new("sven".to_string())];
+        let output = callables[0]
+            .1
+            .call(scripts[0].get_key().unwrap(), &args, &tx);
+        assert_eq!(
+            output.unwrap(),
+            CallOutput::Table(Table {
+                columns: vec!["id".to_string(), "user".to_string()],
+                rows: vec![vec![1.into(), "sven".into()], vec![2.into(), "sven".into()]],
+            })
+        );
+
+        let args: Vec<Box<dyn Any + Send>> = vec![Box::new(5i32)];
+        let output = callables[1]
+            .1
+            .call(scripts[1].get_key().unwrap(), &args, &tx);
+        assert_eq!(
+            output.unwrap(),
+            CallOutput::Text("2 rows affected".to_string())
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn call_needs_every_argument() {
+        let dir = database_dir("arguments");
+        let interpreter = SqlInterpreter {
+            default_database: None,
+        };
+        let (scripts, callables, _) =
+            interpreter.parse(QUERIES.as_bytes(), &dir.join("queries.sql"));
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let key = scripts[1].get_key().unwrap();
+
+        let none: Vec<Box<dyn Any + Send>> = Vec::new();
+        let two: Vec<Box<dyn Any + Send>> = vec![Box::new(5i32), Box::new(6i32)];
+        for args in [none, two] {
+            assert!(matches!(
+                callables[1].1.call(key, &args, &tx),
+                Err(CallError::WrongArguments)
+            ));
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
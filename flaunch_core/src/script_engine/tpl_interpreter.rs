@@ -0,0 +1,558 @@
+use crate::script_engine::interpreter::*;
+use crate::script_engine::*;
+
+use std::convert::TryFrom;
+use std::path::Component;
+use tera::{Context, Tera};
+
+const FRONT_MATTER: &str = "+++";
+const TEMPLATE: &str = "template";
+const TARGET: &str = "target";
+
+/// Exposes Tera/Jinja templates with a TOML front matter as scripts.
+///
+/// ```text
+/// +++
+/// name = "incident_report"
+/// description = "skeleton for a new incident report"
+/// tags = ["oncall"]
+/// target = "incidents/{{ id }}.md"
+///
+/// [[variables]]
+/// name = "id"
+/// type = "uint"
+/// minimum = 1
+///
+/// [[variables]]
+/// name = "severity"
+/// type = "string"
+/// choices = ["minor", "major"]
+///
+/// [[variables]]
+/// name = "summary"
+/// type = "string"
+/// description = "one line summary"
+/// default = "TBD"
+/// +++
+/// # Incident {{ id }}
+/// {{ summary }}
+/// ```
+///
+/// `choices`, `minimum` and `maximum` are passed on to clients, they are not
+/// checked when the script is called.
+///
+/// Calling the script renders the template with its arguments. The text is
+/// returned, or written to `target` when given. The target is rendered as
+/// well and relative to the template file.
+#[derive(Debug, Default)]
+pub struct TplInterpreter;
+
+#[derive(Debug, Default)]
+struct Template {
+    name: String,
+    description: String,
+    target: Option<String>,
+    tags: Vec<String>,
+    variables: Vec<(String, ArgumentType, String)>,
+    constraints: HashMap<String, ArgumentConstraint>,
+    body: String,
+}
+
+fn argument_type(name: &str, default: Option<&toml::Value>) -> Result<ArgumentType, String> {
+    let typ = match name.to_lowercase().as_str() {
+        "int" | "integer" => ArgumentType::Int(integer(default)?),
+        "uint" => ArgumentType::Uint(integer(default)?),
+        "float" => {
+            ArgumentType::Float(default.and_then(toml::Value::as_float).unwrap_or_default() as f32)
+        }
+        "bool" | "boolean" => ArgumentType::Boolean(
+            default
+                .and_then(toml::Value::as_bool)
+                .map(|b| b.to_string())
+                .unwrap_or_default(),
+        ),
+        "str" | "string" | "text" => ArgumentType::String(
+            default
+                .and_then(toml::Value::as_str)
+                .unwrap_or_default()
+                .to_string(),
+        ),
+        "list" => ArgumentType::List(
+            default
+                .and_then(toml::Value::as_array)
+                .map(|l| {
+                    l.iter()
+                        .filter_map(toml::Value::as_str)
+                        .collect::<Vec<_>>()
+                        .join(",")
+                })
+                .unwrap_or_default(),
+        ),
+        "" => ArgumentType::NotSpecified,
+        other => return Err(format!("unknown variable type {:?}", other)),
+    };
+    Ok(typ)
+}
+
+/// Defaults that don't fit the type of the variable are refused, rather
+/// than wrapped around.
+fn integer<T: TryFrom<i64> + Default>(default: Option<&toml::Value>) -> Result<T, String> {
+    match default.and_then(toml::Value::as_integer) {
+        Some(i) => T::try_from(i).map_err(|_| format!("default {} is out of range", i)),
+        None => Ok(T::default()),
+    }
+}
+
+fn constraint(variable: &toml::Value) -> Result<ArgumentConstraint, String> {
+    let bound = |key: &str| match variable.get(key) {
+        None => Ok(None),
+        Some(toml::Value::Integer(i)) => Ok(Some(*i as f64)),
+        Some(toml::Value::Float(f)) => Ok(Some(*f)),
+        Some(other) => Err(format!("{} {} is not a number", key, other)),
+    };
+    let choices = variable
+        .get("choices")
+        .and_then(toml::Value::as_array)
+        .map(|choices| {
+            choices
+                .iter()
+                .map(|choice| match choice.as_str() {
+                    Some(s) => s.to_string(),
+                    None => choice.to_string(),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    Ok(ArgumentConstraint {
+        choices,
+        minimum: bound("minimum")?,
+        maximum: bound("maximum")?,
+    })
+}
+
+/// Splits the `+++` front matter from the template body.
+fn parse_template(content: &str, file: &Path) -> Result<Option<Template>, String> {
+    let rest = match content.strip_prefix(FRONT_MATTER) {
+        Some(rest) => rest,
+        None => return Ok(None),
+    };
+    let end = rest
+        .find(&format!("\n{}", FRONT_MATTER))
+        .ok_or_else(|| "front matter is not closed".to_string())?;
+    let front_matter = rest[..end]
+        .parse::<toml::Table>()
+        .map_err(|e| format!("invalid front matter: {}", e))?;
+    let body = rest[end + 1 + FRONT_MATTER.len()..]
+        .strip_prefix('\n')
+        .unwrap_or(&rest[end + 1 + FRONT_MATTER.len()..]);
+
+    let text = |key: &str| {
+        front_matter
+            .get(key)
+            .and_then(toml::Value::as_str)
+            .map(str::to_string)
+    };
+    let mut template = Template {
+        name: text("name").unwrap_or_else(|| {
+            file.file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string()
+        }),
+        description: text("description").unwrap_or_default(),
+        target: text(TARGET),
+        tags: front_matter
+            .get("tags")
+            .and_then(toml::Value::as_array)
+            .map(|tags| {
+                tags.iter()
+                    .filter_map(toml::Value::as_str)
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default(),
+        body: body.to_string(),
+        ..Default::default()
+    };
+
+    let variables = front_matter
+        .get("variables")
+        .and_then(toml::Value::as_array)
+        .cloned()
+        .unwrap_or_default();
+    for variable in variables {
+        let name = variable
+            .get("name")
+            .and_then(toml::Value::as_str)
+            .ok_or_else(|| "variable without a name".to_string())?;
+        let typ = variable
+            .get("type")
+            .and_then(toml::Value::as_str)
+            .unwrap_or_default();
+        let description = variable
+            .get("description")
+            .and_then(toml::Value::as_str)
+            .unwrap_or_default();
+        template.variables.push((
+            name.to_string(),
+            argument_type(typ, variable.get("default"))?,
+            description.to_string(),
+        ));
+        let constraint = constraint(&variable)?;
+        if constraint != ArgumentConstraint::default() {
+            template.constraints.insert(name.to_string(), constraint);
+        }
+    }
+    Ok(Some(template))
+}
+
+impl Interpreter for TplInterpreter {
+    fn parse(&self, content: &[u8], file: &Path) -> ParseResult {
+        let error = |message: String| {
+            (
+                Vec::new(),
+                Vec::new(),
+                vec![ParseError {
+                    filename: file.to_string_lossy().to_string(),
+                    message,
+                    traceback: String::new(),
+                }],
+            )
+        };
+
+        let template = match parse_template(&String::from_utf8_lossy(content), file) {
+            Ok(Some(template)) => template,
+            // files without front matter aren't scripts, each template is
+            // rendered on its own so they can't be included either
+            Ok(None) => return ParseResult::default(),
+            Err(e) => return error(e),
+        };
+
+        let mut tera = Tera::new();
+        if let Err(e) = tera.add_raw_template(TEMPLATE, &template.body) {
+            return error(e.to_string());
+        }
+        if let Some(target) = &template.target {
+            if let Err(e) = tera.add_raw_template(TARGET, target) {
+                return error(e.to_string());
+            }
+        }
+
+        let mut script = Script::new(template.name.clone(), InterpreterType::Template);
+        script.file = file.to_path_buf();
+        script.description = template.description.clone();
+        script.arguments = template.variables.clone();
+        script.tags = template.tags.clone();
+        script.constraints = template.constraints.clone();
+        let key = script.get_key().unwrap();
+
+        let call: Arc<dyn Callable> = Arc::new(TplCallable {
+            key,
+            tera,
+            directory: file.parent().unwrap_or(Path::new("")).to_path_buf(),
+            variables: template.variables,
+            has_target: template.target.is_some(),
+        });
+        (vec![script], vec![(key, call)], Vec::new())
+    }
+}
+
+#[derive(Debug)]
+pub struct TplCallable {
+    key: u64,
+    tera: Tera,
+    directory: PathBuf,
+    variables: Vec<(String, ArgumentType, String)>,
+    has_target: bool,
+}
+
+fn insert_default(context: &mut Context, name: &str, typ: &ArgumentType) {
+    match typ {
+        ArgumentType::Int(i) => context.insert(name.to_string(), i),
+        ArgumentType::Uint(u) => context.insert(name.to_string(), u),
+        ArgumentType::Float(f) => context.insert(name.to_string(), f),
+        ArgumentType::Boolean(b) => context.insert(name.to_string(), &(b == "true")),
+        ArgumentType::String(s) => context.insert(name.to_string(), s),
+        ArgumentType::List(l) => context.insert(
+            name.to_string(),
+            &l.split(',').filter(|s| !s.is_empty()).collect::<Vec<_>>(),
+        ),
+        ArgumentType::NotSpecified => {}
+    }
+}
+
+fn insert_argument(context: &mut Context, name: &str, arg: &dyn Any) -> Option<()> {
+    let name = name.to_string();
+    if let Some(s) = arg.downcast_ref::<String>() {
+        context.insert(name, s);
+    } else if let Some(i) = arg.downcast_ref::<i32>() {
+        context.insert(name, i);
+    } else if let Some(u) = arg.downcast_ref::<u32>() {
+        context.insert(name, u);
+    } else if let Some(f) = arg.downcast_ref::<f32>() {
+        context.insert(name, f);
+    } else if let Some(b) = arg.downcast_ref::<bool>() {
+        context.insert(name, b);
+    } else {
+        context.insert(name, arg.downcast_ref::<Vec<String>>()?);
+    }
+    Some(())
+}
+
+impl TplCallable {
+    /// The target comes from the caller's arguments, it may only name a
+    /// file below the directory of the template.
+    fn target_path(&self, target: &str) -> Result<PathBuf, String> {
+        let mut path = self.directory.clone();
+        for component in Path::new(target).components() {
+            match component {
+                Component::Normal(part) => path.push(part),
+                Component::CurDir => {}
+                Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                    return Err(format!(
+                        "target {:?} is outside of the template dir",
+                        target
+                    ))
+                }
+            }
+        }
+        if path == self.directory || !path.starts_with(&self.directory) {
+            return Err(format!(
+                "target {:?} is not a file in the template dir",
+                target
+            ));
+        }
+        Ok(path)
+    }
+}
+
+impl Callable for TplCallable {
+    fn call(
+        &self,
+        key: u64,
+        args: &[Box<dyn Any + Send>],
+        _events: &CallEvents,
+    ) -> Result<CallOutput, CallError> {
+        if key != self.key {
+            return Err(CallError::KeyNotPresent(key));
+        }
+        if args.len() > self.variables.len() {
+            return Err(CallError::WrongArguments);
+        }
+
+        let mut context = Context::new();
+        for (index, (name, typ, _)) in self.variables.iter().enumerate() {
+            match args.get(index) {
+                Some(arg) => {
+                    insert_argument(&mut context, name, arg.as_ref())
+                        .ok_or(CallError::WrongArguments)?;
+                }
+                None => insert_default(&mut context, name, typ),
+            }
+        }
+
+        let failed = |message: String| CallError::Exception {
+            message,
+            traceback: String::new(),
+        };
+        let text = self
+            .tera
+            .render(TEMPLATE, &context)
+            .map_err(|e| failed(e.to_string()))?;
+        if !self.has_target {
+            return Ok(CallOutput::Text(text));
+        }
+
+        let target = self
+            .tera
+            .render(TARGET, &context)
+            .map_err(|e| failed(e.to_string()))?;
+        let target = self.target_path(target.trim()).map_err(failed)?;
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| failed(e.to_string()))?;
+        }
+        std::fs::write(&target, text).map_err(|e| failed(e.to_string()))?;
+        Ok(CallOutput::Text(format!(
+            "written to {}",
+            target.to_string_lossy()
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REPORT: &str = concat!(
+        "+++\n",
+        "name = \"incident_report\"\n",
+        "description = \"skeleton for a new incident report\"\n",
+        "tags = [\"oncall\"]\n",
+        "\n",
+        "[[variables]]\n",
+        "name = \"id\"\n",
+        "type = \"uint\"\n",
+        "\n",
+        "[[variables]]\n",
+        "name = \"summary\"\n",
+        "type = \"string\"\n",
+        "description = \"one line summary\"\n",
+        "default = \"TBD\"\n",
+        "+++\n",
+        "# Incident {{ id }}\n",
+        "{{ summary }}\n",
+    );
+
+    #[test]
+    fn parse_front_matter() {
+        let (scripts, callables, errors) =
+            TplInterpreter.parse(REPORT.as_bytes(), &PathBuf::from("/my/report.tera"));
+
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(callables.len(), 1);
+        assert_eq!(scripts[0].name, "incident_report");
+        assert_eq!(scripts[0].description, "skeleton for a new incident report");
+        assert_eq!(scripts[0].tags, vec!["oncall"]);
+        assert_eq!(
+            scripts[0].arguments,
+            vec![
+                ("id".to_string(), ArgumentType::Uint(0), String::new()),
+                (
+                    "summary".to_string(),
+                    ArgumentType::String("TBD".to_string()),
+                    "one line summary".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn render_with_defaults() {
+        let (scripts, callables, _) =
+            TplInterpreter.parse(REPORT.as_bytes(), &PathBuf::from("/my/report.tera"));
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+
+        let args: Vec<Box<dyn Any + Send>> = vec![Box::new(42u32)];
+        let output = callables[0]
+            .1
+            .call(scripts[0].get_key().unwrap(), &args, &tx);
+        assert_eq!(
+            output.unwrap(),
+            CallOutput::Text("# Incident 42\nTBD\n".to_string())
+        );
+    }
+
+    #[test]
+    fn render_to_target() {
+        let dir = std::env::temp_dir().join(format!("flaunch_tpl_{}", std::process::id()));
+        let template = REPORT.replacen(
+            "description",
+            "target = \"incidents/{{ id }}.md\"\ndescription",
+            1,
+        );
+        let (scripts, callables, _) =
+            TplInterpreter.parse(template.as_bytes(), &dir.join("report.j2"));
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+
+        let args: Vec<Box<dyn Any + Send>> =
+            vec![Box::new(7u32), Box::new("disk full".to_string())];
+        callables[0]
+            .1
+            .call(scripts[0].get_key().unwrap(), &args, &tx)
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.join("incidents").join("7.md")).unwrap(),
+            "# Incident 7\ndisk full\n"
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn target_stays_in_the_template_dir() {
+        let dir = std::env::temp_dir().join(format!("flaunch_tpl_escape_{}", std::process::id()));
+        let template = REPORT.replacen("description", "target = \"{{ summary }}\"\ndescription", 1);
+        let (scripts, callables, _) =
+            TplInterpreter.parse(template.as_bytes(), &dir.join("report.j2"));
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+
+        let escape = dir.join("..").join("escaped.md");
+        for target in [
+            "../escaped.md".to_string(),
+            "incidents/../../escaped.md".to_string(),
+            escape.to_string_lossy().to_string(),
+            String::new(),
+        ]
+        .iter()
+        {
+            let args: Vec<Box<dyn Any + Send>> = vec![Box::new(1u32), Box::new(target.clone())];
+            let output = callables[0]
+                .1
+                .call(scripts[0].get_key().unwrap(), &args, &tx);
+            assert!(output.is_err(), "{:?} was written", target);
+        }
+        assert!(!escape.exists());
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn negative_uint_default_is_parse_error() {
+        let template = REPORT.replacen("type = \"uint\"\n", "type = \"uint\"\ndefault = -1\n", 1);
+        let (scripts, _, errors) =
+            TplInterpreter.parse(template.as_bytes(), &PathBuf::from("/my/report.tera"));
+        assert!(scripts.is_empty());
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn choices_and_bounds_from_front_matter() {
+        let template = REPORT
+            .replacen(
+                "type = \"uint\"\n",
+                "type = \"uint\"\nminimum = 1\nmaximum = 9.5\n",
+                1,
+            )
+            .replacen(
+                "default = \"TBD\"\n",
+                "choices = [\"TBD\", \"outage\"]\n",
+                1,
+            );
+        let (scripts, _, errors) =
+            TplInterpreter.parse(template.as_bytes(), &PathBuf::from("/my/report.tera"));
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(
+            scripts[0].constraints.get("id"),
+            Some(&ArgumentConstraint {
+                choices: Vec::new(),
+                minimum: Some(1.0),
+                maximum: Some(9.5),
+            })
+        );
+        assert_eq!(
+            scripts[0]
+                .constraints
+                .get("summary")
+                .map(|c| c.choices.clone()),
+            Some(vec!["TBD".to_string(), "outage".to_string()])
+        );
+
+        let template = REPORT.replacen(
+            "type = \"uint\"\n",
+            "type = \"uint\"\nminimum = \"one\"\n",
+            1,
+        );
+        let (scripts, _, errors) =
+            TplInterpreter.parse(template.as_bytes(), &PathBuf::from("/my/report.tera"));
+        assert!(scripts.is_empty());
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn syntax_error_is_parse_error() {
+        let (scripts, _, errors) = TplInterpreter.parse(
+            b"+++\nname = \"broken\"\n+++\n{{ unclosed\n",
+            &PathBuf::from("/my/broken.tera"),
+        );
+        assert!(scripts.is_empty());
+        assert_eq!(errors.len(), 1);
+    }
+}
@@ -6,7 +6,7 @@ pub use json::JsonValue;
 use logging::*;
 use std::hash::Hash;
 use std::marker::Copy;
-use std::path::PathBuf;
+use std::path::Path;
 use std::{cmp::Eq, collections::HashMap};
 use tokio::sync::watch;
 
@@ -20,6 +20,9 @@ where
     /// a valid value. on load value is insterted with a default
     // value.
     settings: HashMap<Key, JsonValue>,
+    /// values used for keys missing from the settings file, and to check
+    /// the type of new values against.
+    defaults: HashMap<Key, JsonValue>,
     /// mapping from textual json key to rust enum key
     mapping: HashMap<&'static str, Key>,
     channel: (
@@ -27,7 +30,45 @@ where
         watch::Receiver<SettingsChanged>,
     ),
 }
-pub struct SettingsChanged(PathBuf);
+/// json key of the setting that changed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SettingsChanged(pub String);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SettingsError {
+    UnknownKey(String),
+    WrongType(String, &'static str),
+    /// the settings file could not be written.
+    NotSaved(String),
+}
+
+impl std::fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SettingsError::UnknownKey(key) => write!(f, "unknown setting {:?}", key),
+            SettingsError::WrongType(key, expected) => {
+                write!(f, "setting {:?} expects a {} value", key, expected)
+            }
+            SettingsError::NotSaved(e) => write!(f, "could not save settings: {}", e),
+        }
+    }
+}
+
+fn type_name(value: &JsonValue) -> &'static str {
+    if value.is_string() {
+        "string"
+    } else if value.is_number() {
+        "number"
+    } else if value.is_boolean() {
+        "boolean"
+    } else if value.is_array() {
+        "array"
+    } else if value.is_object() {
+        "object"
+    } else {
+        "null"
+    }
+}
 
 impl<Key> Settings<Key>
 where
@@ -36,12 +77,14 @@ where
     pub fn new(key_mapping: &[KeyWithDefault<Key>]) -> Self {
         let mut set = Settings::<Key> {
             settings: HashMap::new(),
+            defaults: HashMap::new(),
             mapping: HashMap::new(),
-            channel: watch::channel(SettingsChanged(PathBuf::new())),
+            channel: watch::channel(SettingsChanged(String::new())),
         };
 
         for (key, json_key, default) in key_mapping {
             set.settings.insert(*key, default.clone());
+            set.defaults.insert(*key, default.clone());
             set.mapping.insert(json_key, *key);
         }
         set
@@ -59,6 +102,23 @@ where
         }
     }
 
+    /// Loads the master settings again, observers are notified when a
+    /// setting changed. Settings missing from the file go back to their
+    /// default.
+    pub fn reload(&mut self) {
+        self.reload_from(&master_settings().to_string_lossy());
+    }
+
+    fn reload_from(&mut self, settings_file: &str) {
+        let before = self.settings.clone();
+        self.from_json(settings_file);
+        for (json_key, key) in &self.mapping {
+            if before.get(key) != self.settings.get(key) {
+                let _ = self.channel.0.send(SettingsChanged(json_key.to_string()));
+            }
+        }
+    }
+
     pub fn reset(&self) {
         std::fs::remove_file(master_settings()).unwrap();
     }
@@ -75,16 +135,111 @@ where
         self.settings.get(&setting).map(|x| x.as_str())?
     }
 
+    pub fn get(&self, json_key: &str) -> Option<&JsonValue> {
+        self.settings.get(self.mapping.get(json_key)?)
+    }
+
+    pub fn key(&self, json_key: &str) -> Option<Key> {
+        self.mapping.get(json_key).cloned()
+    }
+
+    /// All settings by their json key, sorted on key.
+    pub fn entries(&self) -> Vec<(&'static str, JsonValue)> {
+        let mut entries: Vec<(&'static str, JsonValue)> = self
+            .mapping
+            .iter()
+            .filter_map(|(json_key, key)| Some((*json_key, self.settings.get(key)?.clone())))
+            .collect();
+        entries.sort_by_key(|(json_key, _)| *json_key);
+        entries
+    }
+
+    /// Sets a setting by its json key. The value needs to be of the same
+    /// type as the default. Observers are notified, `save` persists it.
+    pub fn set(&mut self, json_key: &str, value: JsonValue) -> Result<Key, SettingsError> {
+        let key = self.check(json_key, &value)?;
+        let current = self
+            .settings
+            .get_mut(&key)
+            .ok_or_else(|| SettingsError::UnknownKey(json_key.to_string()))?;
+        if *current != value {
+            *current = value;
+            let _ = self.channel.0.send(SettingsChanged(json_key.to_string()));
+        }
+        Ok(key)
+    }
+
+    /// Like `set`, but writes the master settings first. When they can't be
+    /// written the setting keeps its value and observers aren't notified.
+    pub fn set_and_save(&mut self, json_key: &str, value: JsonValue) -> Result<Key, SettingsError> {
+        self.set_and_save_to(&master_settings(), json_key, value)
+    }
+
+    fn set_and_save_to(
+        &mut self,
+        settings_file: &Path,
+        json_key: &str,
+        value: JsonValue,
+    ) -> Result<Key, SettingsError> {
+        self.check(json_key, &value)?;
+        let mut json = self.to_json();
+        json[json_key] = value.clone();
+        write(settings_file, &json).map_err(|e| SettingsError::NotSaved(e.to_string()))?;
+        self.set(json_key, value)
+    }
+
+    /// the key of `json_key`, if `value` has the type of its default.
+    fn check(&self, json_key: &str, value: &JsonValue) -> Result<Key, SettingsError> {
+        let key = *self
+            .mapping
+            .get(json_key)
+            .ok_or_else(|| SettingsError::UnknownKey(json_key.to_string()))?;
+        let default = self
+            .defaults
+            .get(&key)
+            .ok_or_else(|| SettingsError::UnknownKey(json_key.to_string()))?;
+        if type_name(default) != type_name(value) {
+            return Err(SettingsError::WrongType(
+                json_key.to_string(),
+                type_name(default),
+            ));
+        }
+        Ok(key)
+    }
+
+    /// Writes all settings to the master settings file.
+    pub fn save(&self) -> std::io::Result<()> {
+        write(&master_settings(), &self.to_json())
+    }
+
+    fn to_json(&self) -> JsonValue {
+        let mut json = JsonValue::new_object();
+        for (json_key, value) in self.entries() {
+            json[json_key] = value;
+        }
+        json
+    }
+
+    pub fn get_bool(&self, setting: Key) -> Option<bool> {
+        self.settings.get(&setting).map(|x| x.as_bool())?
+    }
+
+    /// Replaces the settings by those in `settings_file`, with the defaults
+    /// for missing keys and for values of the wrong type. When the file
+    /// can't be parsed the settings are kept.
     fn from_json(&mut self, settings_file: &str) {
         if let Ok(contents) = std::fs::read_to_string(settings_file) {
             if let Ok(json) = json::parse(contents.as_str()) {
+                self.settings = self.defaults.clone();
                 for (json_key, json_value) in json.entries() {
-                    if let Some(key) = self.mapping.get(json_key) {
-                        if let Some(val) = self.settings.get_mut(key) {
-                            *val = json_value.clone();
+                    match self.check(json_key, json_value) {
+                        Ok(key) => {
+                            self.settings.insert(key, json_value.clone());
+                        }
+                        Err(SettingsError::UnknownKey(_)) => {
+                            warn!("setting key {:?} not configured", json_key)
                         }
-                    } else {
-                        warn!("setting key {:?} not configured", json_key);
+                        Err(e) => warn!("{} in {}, using the default", e, settings_file),
                     }
                 }
             } else {
@@ -96,9 +251,169 @@ where
     }
 }
 
+fn write(settings_file: &Path, json: &JsonValue) -> std::io::Result<()> {
+    if let Some(dir) = settings_file.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(settings_file, json.pretty(4))
+}
+
 pub fn master_settings() -> std::path::PathBuf {
     let mut settings_file =
         app_dirs::get_app_root(app_dirs::AppDataType::UserConfig, &app_meta::APP_INFO).unwrap();
     settings_file.push("config.json");
     settings_file
 }
+
+/// directory for state that isn't configuration, like the flaunchd lock.
+pub fn app_data_dir() -> std::path::PathBuf {
+    app_dirs::get_app_root(app_dirs::AppDataType::UserData, &app_meta::APP_INFO).unwrap()
+}
+
+/// token clients need to present to flaunchd, next to the master settings.
+pub fn auth_token_file() -> std::path::PathBuf {
+    master_settings().with_file_name("token")
+}
+
+/// Directory for files that only live as long as flaunchd runs, like its
+/// socket. Only accessible by the user.
+pub fn runtime_dir() -> std::path::PathBuf {
+    let mut dir = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(std::path::PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir);
+    dir.push("flaunch");
+    dir
+}
+
+pub fn socket_path() -> std::path::PathBuf {
+    runtime_dir().join("flaunchd.sock")
+}
+
+/// Tells clients where flaunchd can be reached.
+pub fn runtime_file() -> std::path::PathBuf {
+    runtime_dir().join("flaunchd.json")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> Settings<u8> {
+        Settings::new(&[
+            (0, "scripts_dir", JsonValue::String("/scripts".to_string())),
+            (1, "folder_scan", JsonValue::Boolean(true)),
+        ])
+    }
+
+    #[test]
+    fn set_checks_type() {
+        let mut settings = settings();
+        assert_eq!(
+            settings.set("folder_scan", JsonValue::Boolean(false)),
+            Ok(1)
+        );
+        assert_eq!(settings.get_bool(1), Some(false));
+
+        assert_eq!(
+            settings.set("folder_scan", JsonValue::String("no".to_string())),
+            Err(SettingsError::WrongType(
+                "folder_scan".to_string(),
+                "boolean"
+            ))
+        );
+        assert_eq!(
+            settings.set("missing", JsonValue::Null),
+            Err(SettingsError::UnknownKey("missing".to_string()))
+        );
+    }
+
+    #[test]
+    fn set_notifies_observers() {
+        let mut settings = settings();
+        let changes = settings.observe();
+        settings
+            .set("scripts_dir", JsonValue::String("/other".to_string()))
+            .unwrap();
+        assert!(changes.has_changed().unwrap());
+        assert_eq!(
+            *changes.borrow(),
+            SettingsChanged("scripts_dir".to_string())
+        );
+        assert_eq!(
+            settings.entries()[1].1,
+            JsonValue::String("/other".to_string())
+        );
+    }
+
+    #[test]
+    fn set_and_save_keeps_the_value_when_saving_fails() {
+        let mut settings = settings();
+        let changes = settings.observe();
+        // a file can't be the parent of the settings file
+        let blocker = std::env::temp_dir().join(format!("flaunch_blocker_{}", std::process::id()));
+        std::fs::write(&blocker, "").unwrap();
+        let result = settings.set_and_save_to(
+            &blocker.join("config.json"),
+            "folder_scan",
+            JsonValue::Boolean(false),
+        );
+        std::fs::remove_file(&blocker).unwrap();
+
+        assert!(matches!(result, Err(SettingsError::NotSaved(_))));
+        assert_eq!(settings.get_bool(1), Some(true));
+        assert!(!changes.has_changed().unwrap());
+    }
+
+    #[test]
+    fn set_and_save_writes_the_new_value() {
+        let dir = std::env::temp_dir().join(format!("flaunch_save_{}", std::process::id()));
+        let file = dir.join("config.json");
+        let mut settings = settings();
+        settings
+            .set_and_save_to(&file, "folder_scan", JsonValue::Boolean(false))
+            .unwrap();
+        let saved = json::parse(&std::fs::read_to_string(&file).unwrap()).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(saved["folder_scan"], false);
+        assert_eq!(saved["scripts_dir"], "/scripts");
+        assert_eq!(settings.get_bool(1), Some(false));
+    }
+
+    #[test]
+    fn reload_notifies_observers() {
+        let file = std::env::temp_dir().join("flaunch_reload_settings.json");
+        std::fs::write(&file, r#"{"folder_scan": true}"#).unwrap();
+        let mut settings = settings();
+        let changes = settings.observe();
+
+        settings.reload_from(&file.to_string_lossy());
+        assert!(!changes.has_changed().unwrap());
+
+        std::fs::write(&file, r#"{"folder_scan": false}"#).unwrap();
+        settings.reload_from(&file.to_string_lossy());
+        std::fs::remove_file(&file).unwrap();
+        assert!(changes.has_changed().unwrap());
+        assert_eq!(
+            *changes.borrow(),
+            SettingsChanged("folder_scan".to_string())
+        );
+        assert_eq!(settings.get_bool(1), Some(false));
+    }
+
+    #[test]
+    fn reload_checks_types_and_restores_defaults() {
+        let file = std::env::temp_dir().join(format!("flaunch_reload_{}.json", std::process::id()));
+        std::fs::write(&file, r#"{"scripts_dir": "/other", "folder_scan": false}"#).unwrap();
+        let mut settings = settings();
+        settings.reload_from(&file.to_string_lossy());
+        assert_eq!(settings.get_str(0), Some("/other"));
+        assert_eq!(settings.get_bool(1), Some(false));
+
+        std::fs::write(&file, r#"{"folder_scan": "yes"}"#).unwrap();
+        settings.reload_from(&file.to_string_lossy());
+        std::fs::remove_file(&file).unwrap();
+        assert_eq!(settings.get_str(0), Some("/scripts"));
+        assert_eq!(settings.get_bool(1), Some(true));
+    }
+}